@@ -0,0 +1,80 @@
+use std::{sync::Arc, time::Duration};
+
+use log::{error, info, warn};
+
+use crate::{
+	errors::{DbError, GitHostError},
+	git_host::GitHost,
+	store::Store,
+};
+
+/// Outcome of a single purge pass
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct PurgeReport {
+	/// Repositories deleted from the git server and the database
+	pub purged: usize,
+	/// Repositories that could not be deleted and will be retried on the next pass
+	pub failed: usize,
+}
+
+/// Delete repositories archived more than `grace_period` ago from the git server, then delete
+/// them and their submissions from the database. The database is only touched once the git
+/// server has let go of the repository, so a failure is retried on the next pass.
+pub(crate) async fn purge_archived_repositories(
+	store: &dyn Store,
+	git_host: &dyn GitHost,
+	grace_period: Duration,
+) -> Result<PurgeReport, DbError> {
+	let grace_period = chrono::Duration::from_std(grace_period)
+		.map_err(|e| DbError::InternalServerError(e.to_string()))?;
+	let expired = store.list_archived_repositories(chrono::Utc::now() - grace_period).await?;
+
+	let mut report = PurgeReport::default();
+	for repository in expired {
+		let repo_name = &repository.repo_name;
+		match git_host.delete_repo(repo_name).await {
+			Ok(()) | Err(GitHostError::NotFound(_)) => {},
+			Err(e) => {
+				warn!(
+					"Failed to delete archived repository `{}` from git server: {}",
+					repo_name, e
+				);
+				report.failed += 1;
+				continue;
+			},
+		}
+		match store.purge_repository(repo_name).await {
+			Ok(()) => {
+				info!("Purged archived repository `{}`", repo_name);
+				report.purged += 1;
+			},
+			Err(e) => {
+				warn!("Failed to purge archived repository `{}`: {}", repo_name, e);
+				report.failed += 1;
+			},
+		}
+	}
+
+	Ok(report)
+}
+
+/// Run [`purge_archived_repositories`] every `interval` in the background
+pub(crate) fn spawn_purger(
+	store: Arc<dyn Store>,
+	git_host: Arc<dyn GitHost>,
+	interval: Duration,
+	grace_period: Duration,
+) {
+	actix_web::rt::spawn(async move {
+		let mut ticker = actix_web::rt::time::interval(interval);
+		loop {
+			ticker.tick().await;
+			match purge_archived_repositories(store.as_ref(), git_host.as_ref(), grace_period).await
+			{
+				Ok(report) if report == PurgeReport::default() => {},
+				Ok(report) => info!("Archived repository purge finished: {:?}", report),
+				Err(e) => error!("Archived repository purge failed: {}", e),
+			}
+		}
+	});
+}
@@ -0,0 +1,217 @@
+use std::future::{ready, Ready};
+
+use actix_web::{
+	body::MessageBody,
+	dev::{Payload, ServiceRequest, ServiceResponse},
+	http::header::AUTHORIZATION,
+	middleware::Next,
+	web, FromRequest, HttpMessage, HttpRequest,
+};
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
+use log::warn;
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use crate::{
+	config::{AuthConfig, TesterConfig},
+	errors::{ApiError, AuthError},
+	models::{Repository, TesterNonce},
+	AppState,
+};
+
+/// Header carrying the id of the tester signing a callback
+pub(crate) const TESTER_ID_HEADER: &str = "X-Tester-Id";
+/// Header carrying the unix timestamp, in seconds, at which a callback was signed
+pub(crate) const TESTER_TIMESTAMP_HEADER: &str = "X-Tester-Timestamp";
+/// Header carrying the hex encoded HMAC-SHA256 of `<timestamp>.<body>`, prefixed with `sha256=`
+pub(crate) const TESTER_SIGNATURE_HEADER: &str = "X-Tester-Signature";
+
+/// The role a caller holds. Admins may act on any resource.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Role {
+	#[default]
+	User,
+	Admin,
+}
+
+/// The claims carried by a bearer token
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct Claims {
+	/// The id of the user the token was issued to
+	pub sub: String,
+	#[serde(default)]
+	pub role: Role,
+	pub exp: u64,
+}
+
+/// The authenticated caller of a request. Placed in the request extensions by [`authenticate`] and
+/// extracted by handlers that require authentication.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Identity {
+	pub user_id: ObjectId,
+	pub role: Role,
+}
+
+impl Identity {
+	pub(crate) fn is_admin(&self) -> bool {
+		self.role == Role::Admin
+	}
+
+	/// Check that the caller is `user_id` or an admin
+	pub(crate) fn authorize_user(&self, user_id: &ObjectId) -> Result<(), AuthError> {
+		if self.is_admin() || &self.user_id == user_id {
+			Ok(())
+		} else {
+			warn!("User `{}` denied access to resources of user `{}`", self.user_id, user_id);
+			Err(AuthError::Forbidden)
+		}
+	}
+
+	/// Check that the caller owns `repository` or is an admin
+	pub(crate) fn authorize_repository(&self, repository: &Repository) -> Result<(), AuthError> {
+		match repository.relationships.user.as_ref() {
+			Some(owner) => self.authorize_user(&owner.id),
+			None if self.is_admin() => Ok(()),
+			None => {
+				warn!("Repository `{}` has no owner", repository.repo_name);
+				Err(AuthError::Forbidden)
+			},
+		}
+	}
+
+	/// Check that the caller is an admin
+	pub(crate) fn require_admin(&self) -> Result<(), AuthError> {
+		if self.is_admin() {
+			Ok(())
+		} else {
+			Err(AuthError::Forbidden)
+		}
+	}
+}
+
+impl FromRequest for Identity {
+	type Error = ApiError;
+	type Future = Ready<Result<Self, Self::Error>>;
+
+	fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+		ready(
+			req.extensions()
+				.get::<Identity>()
+				.cloned()
+				.ok_or(AuthError::MissingToken.into()),
+		)
+	}
+}
+
+/// Middleware validating the bearer token of a request, if any, and storing the caller's
+/// [`Identity`] in the request extensions. Requests without a token pass through unauthenticated;
+/// requests with an invalid token are rejected.
+pub(crate) async fn authenticate(
+	req: ServiceRequest,
+	next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+	if let Some(header) = req.headers().get(AUTHORIZATION) {
+		let token = header.to_str().ok().and_then(|header| header.strip_prefix("Bearer ")).ok_or(
+			ApiError::from(AuthError::InvalidToken("Malformed authorization header".to_string())),
+		)?;
+
+		let state = req
+			.app_data::<web::Data<AppState>>()
+			.expect("AppState is registered on the app");
+		let identity = verify_token(&state.config.auth, token).map_err(ApiError::from)?;
+		req.extensions_mut().insert(identity);
+	}
+
+	next.call(req).await
+}
+
+/// Verify the signature and expiry of a token and return the identity it carries
+pub(crate) fn verify_token(config: &AuthConfig, token: &str) -> Result<Identity, AuthError> {
+	// `Config::validate` rejects a missing or empty secret, never verify against an empty key
+	let secret = config
+		.jwt_secret
+		.as_deref()
+		.filter(|secret| !secret.is_empty())
+		.ok_or_else(|| AuthError::InvalidToken("No signing key is configured".to_string()))?;
+	let mut validation = Validation::new(Algorithm::HS256);
+	validation.leeway = config.leeway_secs;
+	if let Some(issuer) = &config.issuer {
+		validation.set_issuer(&[issuer]);
+	}
+	if let Some(audience) = &config.audience {
+		validation.set_audience(&[audience]);
+	} else {
+		validation.validate_aud = false;
+	}
+
+	let claims = decode::<Claims>(token, &DecodingKey::from_secret(secret.as_bytes()), &validation)
+		.map_err(|e| AuthError::InvalidToken(e.to_string()))?
+		.claims;
+
+	let user_id = ObjectId::parse_str(&claims.sub)
+		.map_err(|_| AuthError::InvalidToken(format!("Invalid subject `{}`", claims.sub)))?;
+
+	Ok(Identity { user_id, role: claims.role })
+}
+
+/// Verify the HMAC signature of a tester callback.
+///
+/// The tester signs `<timestamp>.<body>` with its shared secret. Requests signed with an unknown
+/// tester id, a bad signature, or a timestamp further than `max_clock_skew_secs` from now are
+/// rejected. On success, returns the nonce the caller must record to reject replays of the same
+/// request while its timestamp is still accepted.
+pub(crate) fn verify_tester_signature(
+	config: &TesterConfig,
+	req: &HttpRequest,
+	body: &[u8],
+) -> Result<TesterNonce, AuthError> {
+	let header = |name: &str| {
+		req.headers()
+			.get(name)
+			.and_then(|value| value.to_str().ok())
+			.ok_or_else(|| AuthError::InvalidSignature(format!("Missing {} header", name)))
+	};
+
+	let tester_id = header(TESTER_ID_HEADER)?;
+	let timestamp = header(TESTER_TIMESTAMP_HEADER)?;
+	let signature = header(TESTER_SIGNATURE_HEADER)?;
+
+	let secret = config
+		.secrets
+		.get(tester_id)
+		.ok_or_else(|| AuthError::InvalidSignature(format!("Unknown tester `{}`", tester_id)))?;
+
+	let signed_at: i64 = timestamp
+		.parse()
+		.map_err(|_| AuthError::InvalidSignature(format!("Invalid timestamp `{}`", timestamp)))?;
+	let max_skew = config.max_clock_skew_secs;
+	if chrono::Utc::now().timestamp().abs_diff(signed_at) > max_skew {
+		return Err(AuthError::InvalidSignature("Timestamp outside of allowed window".to_string()));
+	}
+
+	let signature_bytes = signature
+		.strip_prefix("sha256=")
+		.and_then(|signature| hex::decode(signature).ok())
+		.ok_or_else(|| AuthError::InvalidSignature("Malformed signature".to_string()))?;
+
+	let mut mac =
+		Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+	mac.update(timestamp.as_bytes());
+	mac.update(b".");
+	mac.update(body);
+	mac.verify_slice(&signature_bytes)
+		.map_err(|_| AuthError::InvalidSignature("Signature mismatch".to_string()))?;
+
+	Ok(TesterNonce {
+		tester_id: tester_id.to_string(),
+		signature: hex::encode(signature_bytes),
+		expires_at: i64::try_from(max_skew)
+			.ok()
+			.and_then(|max_skew| signed_at.checked_add(max_skew))
+			.and_then(|expires_at| chrono::DateTime::from_timestamp(expires_at, 0))
+			.ok_or_else(|| AuthError::InvalidSignature("Timestamp out of range".to_string()))?,
+	})
+}
@@ -0,0 +1,927 @@
+use std::{
+	collections::HashMap,
+	path::{Path, PathBuf},
+	str::FromStr,
+};
+
+use serde::Deserialize;
+use strum_macros::Display;
+
+use crate::{
+	errors::ConfigError, git_host::GitHostBackend, naming::RepoNamingStrategy,
+	notifier::NotifierBackend, queue::QueueBackend, store::StoreBackend,
+};
+
+/// The deployment environment. Selects which `[profile.<env>]` table of the config file is applied
+/// on top of the base settings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Display)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub(crate) enum Environment {
+	Dev,
+	Staging,
+	Prod,
+}
+
+impl FromStr for Environment {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"dev" | "development" => Ok(Environment::Dev),
+			"staging" => Ok(Environment::Staging),
+			"prod" | "production" => Ok(Environment::Prod),
+			other => Err(format!("Unknown environment `{}`", other)),
+		}
+	}
+}
+
+/// Application configuration, loaded once at startup and shared through `AppState`
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+	#[serde(skip, default = "default_environment")]
+	pub environment: Environment,
+	#[serde(default)]
+	pub server: ServerConfig,
+	#[serde(default)]
+	pub database: DatabaseConfig,
+	#[serde(default)]
+	pub redis: RedisConfig,
+	#[serde(default)]
+	pub logstream: LogstreamConfig,
+	#[serde(default)]
+	pub queue: QueueConfig,
+	#[serde(default)]
+	pub git: GitConfig,
+	#[serde(default)]
+	pub reconcile: ReconcileConfig,
+	#[serde(default)]
+	pub archive: ArchiveConfig,
+	#[serde(default)]
+	pub idempotency: IdempotencyConfig,
+	#[serde(default)]
+	pub auth: AuthConfig,
+	#[serde(default)]
+	pub tester: TesterConfig,
+	#[serde(default)]
+	pub reminders: ReminderConfig,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ServerConfig {
+	#[serde(default = "default_host")]
+	pub host: String,
+	#[serde(default = "default_port")]
+	pub port: u16,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct DatabaseConfig {
+	#[serde(default = "default_store_backend")]
+	pub backend: StoreBackend,
+	/// MongoDB connection string, required for the `mongo` backend
+	pub uri: Option<String>,
+	/// Database name. Must be set explicitly outside of `dev`.
+	pub name: Option<String>,
+	/// JSON file used to seed the `memory` backend
+	pub seed_file: Option<PathBuf>,
+	/// Whether to apply pending schema migrations before starting the server
+	#[serde(default)]
+	pub migrate_on_startup: bool,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RedisConfig {
+	pub uri: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LogstreamConfig {
+	/// Websocket URL clients connect to for streaming test logs
+	pub ws_url: Option<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct QueueConfig {
+	#[serde(default = "default_queue_backend")]
+	pub backend: QueueBackend,
+	/// Redis stream holding the jobs, for the `redis` backend
+	#[serde(default = "default_queue_stream")]
+	pub stream: String,
+	/// Seconds a claimed job is hidden from other workers without a heartbeat from its worker
+	#[serde(default = "default_queue_visibility_timeout_secs")]
+	pub visibility_timeout_secs: u64,
+	/// Times a job is handed out before its submission is marked as timed out
+	#[serde(default = "default_queue_max_deliveries")]
+	pub max_deliveries: u32,
+	/// Seconds between passes collecting jobs whose worker stopped heartbeating
+	#[serde(default = "default_queue_monitor_interval_secs")]
+	pub monitor_interval_secs: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct GitConfig {
+	#[serde(default = "default_git_backend")]
+	pub backend: GitHostBackend,
+	#[serde(default = "default_git_server_url")]
+	pub server_url: String,
+	/// Token used to authenticate with the git server
+	pub bearer_token: Option<String>,
+	/// Directory holding bare repositories, required for the `local` backend
+	pub repos_dir: Option<PathBuf>,
+	/// Directory holding course templates, required for the `local` backend
+	pub templates_dir: Option<PathBuf>,
+	/// How new repositories are named
+	#[serde(default = "default_repo_naming")]
+	pub naming: RepoNamingStrategy,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ReconcileConfig {
+	/// Whether to run the background task repairing interrupted repository creations
+	#[serde(default = "default_true")]
+	pub enabled: bool,
+	/// Seconds between reconciliation passes
+	#[serde(default = "default_reconcile_interval_secs")]
+	pub interval_secs: u64,
+	/// Seconds a repository creation may take before it is considered interrupted
+	#[serde(default = "default_reconcile_grace_period_secs")]
+	pub grace_period_secs: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ArchiveConfig {
+	/// Whether to run the background task purging archived repositories
+	#[serde(default = "default_true")]
+	pub purge_enabled: bool,
+	/// Seconds between purge passes
+	#[serde(default = "default_archive_purge_interval_secs")]
+	pub purge_interval_secs: u64,
+	/// Days an archived repository is kept, during which an admin may restore it
+	#[serde(default = "default_archive_grace_period_days")]
+	pub grace_period_days: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct IdempotencyConfig {
+	/// Seconds the response to a request made with an `Idempotency-Key` is kept for retries
+	#[serde(default = "default_idempotency_ttl_secs")]
+	pub ttl_secs: u64,
+	/// Seconds within which a submission of the same commit to the same repository returns the
+	/// original submission instead of creating another. Zero disables deduplication.
+	#[serde(default = "default_submission_dedup_window_secs")]
+	pub submission_dedup_window_secs: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct AuthConfig {
+	/// Shared secret used to verify HS256 bearer tokens
+	pub jwt_secret: Option<String>,
+	/// Expected `iss` claim, if any
+	pub issuer: Option<String>,
+	/// Expected `aud` claim, if any
+	pub audience: Option<String>,
+	/// Clock skew tolerated when checking token expiry
+	#[serde(default = "default_auth_leeway_secs")]
+	pub leeway_secs: u64,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct TesterConfig {
+	/// How far the timestamp of a signed tester callback may be from the server clock
+	#[serde(default = "default_tester_max_clock_skew_secs")]
+	pub max_clock_skew_secs: u64,
+	/// Shared secret of each tester, keyed by tester id
+	#[serde(default)]
+	pub secrets: HashMap<String, String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ReminderConfig {
+	/// Whether to run the background task sending practice reminders
+	#[serde(default = "default_true")]
+	pub enabled: bool,
+	/// Seconds between passes looking for repositories that are due a reminder
+	#[serde(default = "default_reminder_interval_secs")]
+	pub interval_secs: u64,
+	#[serde(default = "default_notifier_backend")]
+	pub notifier: NotifierBackend,
+	#[serde(default)]
+	pub smtp: SmtpConfig,
+	#[serde(default)]
+	pub webhook: WebhookConfig,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SmtpConfig {
+	/// SMTP relay, required for the `smtp` notifier
+	pub host: Option<String>,
+	#[serde(default = "default_smtp_port")]
+	pub port: u16,
+	pub username: Option<String>,
+	pub password: Option<String>,
+	/// Sender address, required for the `smtp` notifier
+	pub from: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct WebhookConfig {
+	/// URL reminders are POSTed to, required for the `webhook` notifier
+	pub url: Option<String>,
+	/// Token sent as a bearer token with each request
+	pub bearer_token: Option<String>,
+}
+
+impl Default for ServerConfig {
+	fn default() -> Self {
+		ServerConfig { host: default_host(), port: default_port() }
+	}
+}
+
+impl Default for DatabaseConfig {
+	fn default() -> Self {
+		DatabaseConfig {
+			backend: default_store_backend(),
+			uri: None,
+			name: None,
+			seed_file: None,
+			migrate_on_startup: false,
+		}
+	}
+}
+
+impl Default for QueueConfig {
+	fn default() -> Self {
+		QueueConfig {
+			backend: default_queue_backend(),
+			stream: default_queue_stream(),
+			visibility_timeout_secs: default_queue_visibility_timeout_secs(),
+			max_deliveries: default_queue_max_deliveries(),
+			monitor_interval_secs: default_queue_monitor_interval_secs(),
+		}
+	}
+}
+
+impl Default for GitConfig {
+	fn default() -> Self {
+		GitConfig {
+			backend: default_git_backend(),
+			server_url: default_git_server_url(),
+			bearer_token: None,
+			repos_dir: None,
+			templates_dir: None,
+			naming: default_repo_naming(),
+		}
+	}
+}
+
+impl Default for ReconcileConfig {
+	fn default() -> Self {
+		ReconcileConfig {
+			enabled: true,
+			interval_secs: default_reconcile_interval_secs(),
+			grace_period_secs: default_reconcile_grace_period_secs(),
+		}
+	}
+}
+
+impl Default for ArchiveConfig {
+	fn default() -> Self {
+		ArchiveConfig {
+			purge_enabled: true,
+			purge_interval_secs: default_archive_purge_interval_secs(),
+			grace_period_days: default_archive_grace_period_days(),
+		}
+	}
+}
+
+impl Default for IdempotencyConfig {
+	fn default() -> Self {
+		IdempotencyConfig {
+			ttl_secs: default_idempotency_ttl_secs(),
+			submission_dedup_window_secs: default_submission_dedup_window_secs(),
+		}
+	}
+}
+
+impl Default for AuthConfig {
+	fn default() -> Self {
+		AuthConfig {
+			jwt_secret: None,
+			issuer: None,
+			audience: None,
+			leeway_secs: default_auth_leeway_secs(),
+		}
+	}
+}
+
+impl Default for TesterConfig {
+	fn default() -> Self {
+		TesterConfig {
+			max_clock_skew_secs: default_tester_max_clock_skew_secs(),
+			secrets: HashMap::new(),
+		}
+	}
+}
+
+impl Default for ReminderConfig {
+	fn default() -> Self {
+		ReminderConfig {
+			enabled: true,
+			interval_secs: default_reminder_interval_secs(),
+			notifier: default_notifier_backend(),
+			smtp: SmtpConfig::default(),
+			webhook: WebhookConfig::default(),
+		}
+	}
+}
+
+impl Default for SmtpConfig {
+	fn default() -> Self {
+		SmtpConfig {
+			host: None,
+			port: default_smtp_port(),
+			username: None,
+			password: None,
+			from: None,
+		}
+	}
+}
+
+fn default_environment() -> Environment {
+	Environment::Dev
+}
+
+fn default_host() -> String {
+	"0.0.0.0".to_string()
+}
+
+fn default_port() -> u16 {
+	8080
+}
+
+fn default_store_backend() -> StoreBackend {
+	StoreBackend::Mongo
+}
+
+fn default_queue_backend() -> QueueBackend {
+	QueueBackend::Redis
+}
+
+fn default_queue_stream() -> String {
+	"submission-jobs".to_string()
+}
+
+fn default_queue_visibility_timeout_secs() -> u64 {
+	2 * 60
+}
+
+fn default_queue_max_deliveries() -> u32 {
+	3
+}
+
+fn default_queue_monitor_interval_secs() -> u64 {
+	15
+}
+
+fn default_git_backend() -> GitHostBackend {
+	GitHostBackend::Http
+}
+
+fn default_repo_naming() -> RepoNamingStrategy {
+	RepoNamingStrategy::Hex
+}
+
+fn default_git_server_url() -> String {
+	"https://git.dotcodeschool.com".to_string()
+}
+
+fn default_true() -> bool {
+	true
+}
+
+fn default_reconcile_interval_secs() -> u64 {
+	5 * 60
+}
+
+fn default_reconcile_grace_period_secs() -> u64 {
+	10 * 60
+}
+
+fn default_archive_purge_interval_secs() -> u64 {
+	60 * 60
+}
+
+fn default_archive_grace_period_days() -> u64 {
+	30
+}
+
+fn default_idempotency_ttl_secs() -> u64 {
+	24 * 60 * 60
+}
+
+fn default_submission_dedup_window_secs() -> u64 {
+	5 * 60
+}
+
+fn default_reminder_interval_secs() -> u64 {
+	60 * 60
+}
+
+fn default_notifier_backend() -> NotifierBackend {
+	NotifierBackend::Log
+}
+
+fn default_smtp_port() -> u16 {
+	587
+}
+
+fn default_auth_leeway_secs() -> u64 {
+	30
+}
+
+fn default_tester_max_clock_skew_secs() -> u64 {
+	5 * 60
+}
+
+/// Minimum length of the token secret outside of `dev`
+const MIN_JWT_SECRET_LEN: usize = 32;
+
+/// The database used in `dev` when no name is configured
+const DEV_DB_NAME: &str = "dcs-test";
+
+impl Config {
+	/// Load the configuration for the environment named by `APP_ENV` (default `dev`).
+	///
+	/// Settings are read from the TOML file at `CONFIG_FILE` (default `config.toml`, optional when
+	/// the default is used), the matching `[profile.<env>]` table is merged over the base settings,
+	/// and finally environment variables override individual values.
+	pub(crate) fn load() -> Result<Self, ConfigError> {
+		let environment = match std::env::var("APP_ENV") {
+			Ok(env) => env.parse().map_err(|e| ConfigError::Invalid(vec![e]))?,
+			Err(_) => Environment::Dev,
+		};
+
+		let (path, required) = match std::env::var("CONFIG_FILE") {
+			Ok(path) => (PathBuf::from(path), true),
+			Err(_) => (PathBuf::from("config.toml"), false),
+		};
+
+		let table = if path.exists() || required {
+			read_profile(&path, environment)?
+		} else {
+			toml::Table::new()
+		};
+
+		let mut config: Config = toml::Value::Table(table)
+			.try_into()
+			.map_err(|e| ConfigError::Parse { path: path.clone(), source: e })?;
+		config.environment = environment;
+		config.apply_env_overrides()?;
+		config.validate()?;
+
+		Ok(config)
+	}
+
+	/// Override individual settings from environment variables
+	fn apply_env_overrides(&mut self) -> Result<(), ConfigError> {
+		let mut errors = vec![];
+
+		if let Some(port) = env("PORT") {
+			match port.parse() {
+				Ok(port) => self.server.port = port,
+				Err(_) => errors.push(format!("PORT must be a port number, got `{}`", port)),
+			}
+		}
+		if let Some(backend) = env("STORE_BACKEND") {
+			match backend.parse() {
+				Ok(backend) => self.database.backend = backend,
+				Err(e) => errors.push(format!("STORE_BACKEND: {}", e)),
+			}
+		}
+		if let Some(backend) = env("QUEUE_BACKEND") {
+			match backend.parse() {
+				Ok(backend) => self.queue.backend = backend,
+				Err(e) => errors.push(format!("QUEUE_BACKEND: {}", e)),
+			}
+		}
+		if let Some(notifier) = env("REMINDER_NOTIFIER") {
+			match notifier.parse() {
+				Ok(notifier) => self.reminders.notifier = notifier,
+				Err(e) => errors.push(format!("REMINDER_NOTIFIER: {}", e)),
+			}
+		}
+		if let Some(migrate) = env("MIGRATE_ON_STARTUP") {
+			match migrate.parse() {
+				Ok(migrate) => self.database.migrate_on_startup = migrate,
+				Err(_) => errors.push(format!(
+					"MIGRATE_ON_STARTUP must be `true` or `false`, got `{}`",
+					migrate
+				)),
+			}
+		}
+		if let Some(backend) = env("GIT_HOST") {
+			match backend.parse() {
+				Ok(backend) => self.git.backend = backend,
+				Err(e) => errors.push(format!("GIT_HOST: {}", e)),
+			}
+		}
+		if let Some(naming) = env("REPO_NAMING") {
+			match naming.parse() {
+				Ok(naming) => self.git.naming = naming,
+				Err(e) => errors.push(format!("REPO_NAMING: {}", e)),
+			}
+		}
+
+		override_opt(&mut self.database.uri, "MONGODB_URI");
+		override_opt(&mut self.database.name, "DB_NAME");
+		override_opt(&mut self.database.seed_file, "MEMORY_STORE_SEED");
+		override_opt(&mut self.redis.uri, "REDIS_URI");
+		override_opt(&mut self.logstream.ws_url, "WS_URL");
+		override_opt(&mut self.git.bearer_token, "BEARER_TOKEN_SECRET");
+		override_opt(&mut self.git.repos_dir, "LOCAL_GIT_REPOS_DIR");
+		override_opt(&mut self.git.templates_dir, "LOCAL_GIT_TEMPLATES_DIR");
+		override_opt(&mut self.auth.jwt_secret, "JWT_SECRET");
+		override_opt(&mut self.reminders.smtp.host, "SMTP_HOST");
+		override_opt(&mut self.reminders.smtp.username, "SMTP_USERNAME");
+		override_opt(&mut self.reminders.smtp.password, "SMTP_PASSWORD");
+		override_opt(&mut self.reminders.smtp.from, "SMTP_FROM");
+		override_opt(&mut self.reminders.webhook.url, "REMINDER_WEBHOOK_URL");
+		override_opt(&mut self.reminders.webhook.bearer_token, "REMINDER_WEBHOOK_TOKEN");
+		if let Some(secrets) = env("TESTER_SECRETS") {
+			for entry in secrets.split(',').filter(|entry| !entry.is_empty()) {
+				match entry.split_once('=') {
+					Some((id, secret)) if !id.is_empty() && !secret.is_empty() => {
+						self.tester.secrets.insert(id.to_string(), secret.to_string());
+					},
+					_ => errors.push(
+						"TESTER_SECRETS must be a comma separated list of `tester_id=secret`"
+							.to_string(),
+					),
+				}
+			}
+		}
+		if let Some(url) = env("GIT_SERVER_URL") {
+			self.git.server_url = url;
+		}
+
+		if errors.is_empty() {
+			Ok(())
+		} else {
+			Err(ConfigError::Invalid(errors))
+		}
+	}
+
+	/// Check that every setting required by the selected backends is present
+	fn validate(&self) -> Result<(), ConfigError> {
+		let mut errors = vec![];
+
+		if self.database.backend == StoreBackend::Mongo && self.database.uri.is_none() {
+			errors.push("database.uri (MONGODB_URI) must be set for the mongo backend".to_string());
+		}
+		match self.database.name.as_deref() {
+			Some("") => errors.push("database.name (DB_NAME) must not be empty".to_string()),
+			None if self.environment != Environment::Dev => errors.push(format!(
+				"database.name (DB_NAME) must be set explicitly in the {} environment",
+				self.environment
+			)),
+			_ => {},
+		}
+		if self.redis.uri.is_none() {
+			errors.push("redis.uri (REDIS_URI) must be set".to_string());
+		}
+		if self.logstream.ws_url.is_none() {
+			errors.push("logstream.ws_url (WS_URL) must be set".to_string());
+		}
+		if self.git.backend == GitHostBackend::Local {
+			if self.git.repos_dir.is_none() {
+				errors.push(
+					"git.repos_dir (LOCAL_GIT_REPOS_DIR) must be set for the local git backend"
+						.to_string(),
+				);
+			}
+			if self.git.templates_dir.is_none() {
+				errors.push(
+					"git.templates_dir (LOCAL_GIT_TEMPLATES_DIR) must be set for the local git backend"
+						.to_string(),
+				);
+			}
+		}
+		match self.auth.jwt_secret.as_deref() {
+			None => errors.push("auth.jwt_secret (JWT_SECRET) must be set".to_string()),
+			Some("") => errors.push("auth.jwt_secret (JWT_SECRET) must not be empty".to_string()),
+			Some(secret)
+				if secret.len() < MIN_JWT_SECRET_LEN && self.environment != Environment::Dev =>
+				errors.push(format!(
+					"auth.jwt_secret (JWT_SECRET) must be at least {} bytes in the {} environment",
+					MIN_JWT_SECRET_LEN, self.environment
+				)),
+			_ => {},
+		}
+		if self.tester.secrets.is_empty() && self.environment != Environment::Dev {
+			errors.push(format!(
+				"tester.secrets (TESTER_SECRETS) must configure at least one tester in the {} \
+				 environment",
+				self.environment
+			));
+		}
+		if self.reconcile.interval_secs == 0 {
+			errors.push("reconcile.interval_secs must be greater than zero".to_string());
+		}
+		if self.queue.backend == QueueBackend::Redis && self.queue.stream.is_empty() {
+			errors.push("queue.stream must not be empty for the redis backend".to_string());
+		}
+		if self.queue.visibility_timeout_secs == 0 {
+			errors.push("queue.visibility_timeout_secs must be greater than zero".to_string());
+		}
+		if self.queue.max_deliveries == 0 {
+			errors.push("queue.max_deliveries must be greater than zero".to_string());
+		}
+		if self.queue.monitor_interval_secs == 0 {
+			errors.push("queue.monitor_interval_secs must be greater than zero".to_string());
+		}
+		if self.idempotency.ttl_secs == 0 {
+			errors.push("idempotency.ttl_secs must be greater than zero".to_string());
+		}
+		if self.archive.purge_interval_secs == 0 {
+			errors.push("archive.purge_interval_secs must be greater than zero".to_string());
+		}
+		if self.reminders.interval_secs == 0 {
+			errors.push("reminders.interval_secs must be greater than zero".to_string());
+		}
+		match self.reminders.notifier {
+			NotifierBackend::Smtp => {
+				if self.reminders.smtp.host.is_none() {
+					errors.push(
+						"reminders.smtp.host (SMTP_HOST) must be set for the smtp notifier"
+							.to_string(),
+					);
+				}
+				if self.reminders.smtp.from.is_none() {
+					errors.push(
+						"reminders.smtp.from (SMTP_FROM) must be set for the smtp notifier"
+							.to_string(),
+					);
+				}
+			},
+			NotifierBackend::Webhook if self.reminders.webhook.url.is_none() => errors.push(
+				"reminders.webhook.url (REMINDER_WEBHOOK_URL) must be set for the webhook notifier"
+					.to_string(),
+			),
+			_ => {},
+		}
+		if self.git.backend == GitHostBackend::Fake && self.environment == Environment::Prod {
+			errors.push("git.backend = \"fake\" is not allowed in prod".to_string());
+		}
+		if self.database.backend == StoreBackend::Memory && self.environment == Environment::Prod {
+			errors.push("database.backend = \"memory\" is not allowed in prod".to_string());
+		}
+		if self.queue.backend == QueueBackend::Memory && self.environment == Environment::Prod {
+			errors.push("queue.backend = \"memory\" is not allowed in prod".to_string());
+		}
+
+		if errors.is_empty() {
+			Ok(())
+		} else {
+			Err(ConfigError::Invalid(errors))
+		}
+	}
+
+	/// The address the HTTP server binds to
+	pub(crate) fn bind_address(&self) -> String {
+		format!("{}:{}", self.server.host, self.server.port)
+	}
+
+	/// The name of the MongoDB database
+	pub(crate) fn db_name(&self) -> &str {
+		self.database.name.as_deref().unwrap_or(DEV_DB_NAME)
+	}
+
+	/// The Redis URI used as the prefix for logstream URLs and by the `redis` queue backend
+	pub(crate) fn redis_uri(&self) -> &str {
+		self.redis.uri.as_deref().unwrap_or_default()
+	}
+
+	/// The websocket URL handed to clients for streaming test logs
+	pub(crate) fn ws_url(&self) -> &str {
+		self.logstream.ws_url.as_deref().unwrap_or_default()
+	}
+}
+
+/// Read the config file and merge the profile for `environment` over the base settings
+fn read_profile(path: &Path, environment: Environment) -> Result<toml::Table, ConfigError> {
+	let contents = std::fs::read_to_string(path)
+		.map_err(|e| ConfigError::Io { path: path.to_path_buf(), source: e })?;
+	let mut table: toml::Table = toml::from_str(&contents)
+		.map_err(|e| ConfigError::Parse { path: path.to_path_buf(), source: e })?;
+
+	let profiles = match table.remove("profile") {
+		Some(toml::Value::Table(profiles)) => profiles,
+		Some(_) =>
+			return Err(ConfigError::Invalid(vec![format!(
+				"`profile` in {} must be a table",
+				path.display()
+			)])),
+		None => toml::Table::new(),
+	};
+
+	if let Some(toml::Value::Table(profile)) = profiles.get(&environment.to_string()) {
+		merge(&mut table, profile.clone());
+	}
+
+	Ok(table)
+}
+
+/// Recursively merge `overlay` into `base`, with values in `overlay` taking precedence
+fn merge(base: &mut toml::Table, overlay: toml::Table) {
+	for (key, value) in overlay {
+		match (base.get_mut(&key), value) {
+			(Some(toml::Value::Table(base)), toml::Value::Table(overlay)) => merge(base, overlay),
+			(_, value) => {
+				base.insert(key, value);
+			},
+		}
+	}
+}
+
+fn env(key: &str) -> Option<String> {
+	std::env::var(key).ok().filter(|value| !value.is_empty())
+}
+
+fn override_opt<T: From<String>>(field: &mut Option<T>, key: &str) {
+	if let Some(value) = env(key) {
+		*field = Some(T::from(value));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+	fn config(environment: Environment, toml: &str) -> Config {
+		let mut config: Config = toml::from_str(toml).expect("config is valid TOML");
+		config.environment = environment;
+		config
+	}
+
+	fn complete(environment: Environment) -> Config {
+		config(
+			environment,
+			&format!(
+				r#"
+				[database]
+				uri = "mongodb://localhost:27017"
+				name = "dcs"
+				[redis]
+				uri = "redis://localhost:6379"
+				[logstream]
+				ws_url = "ws://localhost:8081"
+				[auth]
+				jwt_secret = "{}"
+				[tester.secrets]
+				tester = "secret"
+				"#,
+				SECRET
+			),
+		)
+	}
+
+	fn errors(config: &Config) -> Vec<String> {
+		match config.validate() {
+			Ok(()) => vec![],
+			Err(ConfigError::Invalid(errors)) => errors,
+			Err(e) => panic!("unexpected error {}", e),
+		}
+	}
+
+	#[test]
+	fn profile_is_merged_over_base_settings() {
+		let path = std::env::temp_dir().join(format!("dcs-config-{}.toml", uuid::Uuid::new_v4()));
+		std::fs::write(
+			&path,
+			r#"
+			[server]
+			port = 9000
+			[database]
+			name = "dcs"
+			uri = "mongodb://base"
+
+			[profile.prod.database]
+			uri = "mongodb://prod"
+			[profile.staging.server]
+			port = 9001
+			"#,
+		)
+		.unwrap();
+
+		let prod = read_profile(&path, Environment::Prod).unwrap();
+		let dev = read_profile(&path, Environment::Dev).unwrap();
+		std::fs::remove_file(&path).unwrap();
+
+		let prod: Config = toml::Value::Table(prod).try_into().unwrap();
+		assert_eq!(prod.database.uri.as_deref(), Some("mongodb://prod"));
+		assert_eq!(prod.database.name.as_deref(), Some("dcs"), "sibling keys are kept");
+		assert_eq!(prod.server.port, 9000, "other profiles are not applied");
+
+		let dev: Config = toml::Value::Table(dev).try_into().unwrap();
+		assert_eq!(dev.database.uri.as_deref(), Some("mongodb://base"));
+	}
+
+	#[test]
+	fn environment_variables_override_settings() {
+		// The only test touching these variables, so tests running in parallel don't race on them
+		std::env::set_var("PORT", "9100");
+		std::env::set_var("DB_NAME", "from-env");
+		std::env::set_var("JWT_SECRET", SECRET);
+		std::env::set_var("TESTER_SECRETS", "t1=s1,t2=s2");
+		let mut config = config(Environment::Dev, "[database]\nname = \"from-file\"");
+		let applied = config.apply_env_overrides();
+
+		std::env::set_var("PORT", "not-a-port");
+		std::env::set_var("STORE_BACKEND", "bogus");
+		let invalid = complete(Environment::Dev).apply_env_overrides();
+
+		for key in ["PORT", "DB_NAME", "JWT_SECRET", "TESTER_SECRETS", "STORE_BACKEND"] {
+			std::env::remove_var(key);
+		}
+
+		applied.unwrap();
+		assert_eq!(config.server.port, 9100);
+		assert_eq!(config.database.name.as_deref(), Some("from-env"));
+		assert_eq!(config.auth.jwt_secret.as_deref(), Some(SECRET));
+		assert_eq!(config.tester.secrets.get("t2").map(String::as_str), Some("s2"));
+
+		match invalid {
+			Err(ConfigError::Invalid(errors)) => {
+				assert_eq!(errors.len(), 2, "{:?}", errors);
+				assert!(errors[0].starts_with("PORT"));
+				assert!(errors[1].starts_with("STORE_BACKEND"));
+			},
+			other => panic!("expected both overrides to be rejected, got {:?}", other),
+		}
+	}
+
+	#[test]
+	fn complete_config_is_valid_in_every_environment() {
+		for environment in [Environment::Dev, Environment::Staging, Environment::Prod] {
+			assert_eq!(errors(&complete(environment)), Vec::<String>::new(), "{}", environment);
+		}
+	}
+
+	#[test]
+	fn empty_jwt_secret_is_rejected_in_every_environment() {
+		for environment in [Environment::Dev, Environment::Staging, Environment::Prod] {
+			let mut config = complete(environment);
+			config.auth.jwt_secret = Some(String::new());
+			assert_eq!(
+				errors(&config),
+				["auth.jwt_secret (JWT_SECRET) must not be empty"],
+				"{}",
+				environment
+			);
+		}
+	}
+
+	#[test]
+	fn short_jwt_secret_is_only_allowed_in_dev() {
+		let mut dev = complete(Environment::Dev);
+		dev.auth.jwt_secret = Some("short".to_string());
+		assert!(errors(&dev).is_empty());
+
+		let mut prod = complete(Environment::Prod);
+		prod.auth.jwt_secret = Some("short".to_string());
+		assert_eq!(errors(&prod).len(), 1);
+		assert!(errors(&prod)[0].contains("must be at least"));
+	}
+
+	#[test]
+	fn missing_settings_are_all_reported() {
+		let errors = errors(&config(Environment::Prod, "[database]\nbackend = \"memory\""));
+		for expected in [
+			"database.name (DB_NAME) must be set explicitly in the prod environment",
+			"redis.uri (REDIS_URI) must be set",
+			"logstream.ws_url (WS_URL) must be set",
+			"auth.jwt_secret (JWT_SECRET) must be set",
+			"database.backend = \"memory\" is not allowed in prod",
+		] {
+			assert!(errors.iter().any(|e| e == expected), "missing `{}` in {:?}", expected, errors);
+		}
+	}
+}
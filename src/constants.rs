@@ -1,10 +1,20 @@
-/// The URL of the git server
-pub(crate) const GIT_SERVER_URL: &str = "https://git.dotcodeschool.com";
-/// The name of the database
-pub(crate) const DB_NAME: &str = "dcs-test";
 /// The name of the collection that stores the repository documents
 pub(super) const REPO_COLLECTION: &str = "repositories";
 /// The name of the collection that stores the submission documents
 pub(super) const SUBMISSION_COLLECTION: &str = "submissions";
 /// The name of the collection that stores the user documents
 pub(super) const USER_COLLECTION: &str = "users";
+/// The name of the collection that stores the course documents
+pub(super) const COURSE_COLLECTION: &str = "courses";
+/// The name of the collection that stores prior versions of course documents
+pub(super) const COURSE_VERSION_COLLECTION: &str = "course_versions";
+/// The name of the collection that tracks repositories whose creation is in progress
+pub(super) const PENDING_REPO_COLLECTION: &str = "pending_repositories";
+/// The name of the collection that stores signatures of recent tester callbacks
+pub(super) const TESTER_NONCE_COLLECTION: &str = "tester_nonces";
+/// The name of the collection that records practice reminders already sent
+pub(super) const REMINDER_COLLECTION: &str = "reminders";
+/// The name of the collection that records which schema migrations have been applied
+pub(super) const MIGRATION_COLLECTION: &str = "_migrations";
+/// The name of the collection that stores responses to requests made with an idempotency key
+pub(super) const IDEMPOTENCY_COLLECTION: &str = "idempotency_keys";
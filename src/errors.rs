@@ -1,9 +1,21 @@
+use std::path::PathBuf;
+
+use actix_web::{
+	http::{
+		header::{ContentType, WWW_AUTHENTICATE},
+		StatusCode,
+	},
+	HttpResponse, ResponseError,
+};
+use log::error;
+use mongodb::error::{ErrorKind, WriteFailure};
+use serde::Serialize;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
 pub enum RepoCreationError {
 	#[error("Git server request failed: {0}")]
-	GitServerError(#[from] reqwest::Error),
+	GitServerError(#[from] GitHostError),
 
 	#[error("Database operation failed: {0}")]
 	DatabaseError(#[from] mongodb::error::Error),
@@ -17,8 +29,20 @@ pub enum RepoCreationError {
 	#[error("404 Not Found: {0}")]
 	NotFound(String),
 
+	#[error("Unknown repository template `{0}`")]
+	UnknownTemplate(String),
+
+	#[error("409 Conflict: {0}")]
+	Conflict(String),
+
+	#[error("422 Unprocessable Entity: {0}")]
+	Validation(String),
+
 	#[error("500 Internal Server Error: {0}")]
 	InternalServerError(String),
+
+	#[error("{0}")]
+	Auth(#[from] AuthError),
 }
 
 #[derive(Error, Debug)]
@@ -30,5 +54,321 @@ pub enum DbError {
 	InternalServerError(String),
 
 	#[error("404 Not Found: {0}")]
-	NotFound(#[from] actix_web::error::Error),
+	NotFound(String),
+
+	#[error("Invalid object id: {0}")]
+	InvalidObjectId(#[from] mongodb::bson::oid::Error),
+
+	#[error("409 Conflict: {0}")]
+	Conflict(String),
+
+	#[error("422 Unprocessable Entity: {0}")]
+	Validation(String),
+
+	#[error("{0}")]
+	Auth(#[from] AuthError),
+
+	#[error("Git server request failed: {0}")]
+	GitHost(#[from] GitHostError),
+}
+
+impl From<DbError> for RepoCreationError {
+	fn from(error: DbError) -> Self {
+		match error {
+			DbError::DatabaseError(e) => RepoCreationError::DatabaseError(e),
+			DbError::InternalServerError(e) => RepoCreationError::InternalServerError(e),
+			DbError::NotFound(e) => RepoCreationError::NotFound(e),
+			DbError::InvalidObjectId(e) => RepoCreationError::InvalidObjectId(e),
+			DbError::Conflict(e) => RepoCreationError::Conflict(e),
+			DbError::Validation(e) => RepoCreationError::Validation(e),
+			DbError::Auth(e) => RepoCreationError::Auth(e),
+			DbError::GitHost(e) => RepoCreationError::GitServerError(e),
+		}
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum GitHostError {
+	#[error("Git server request failed: {0}")]
+	Http(#[from] reqwest::Error),
+
+	#[error("Git command failed: {0}")]
+	Io(#[from] std::io::Error),
+
+	#[error("Git command `{command}` failed: {stderr}")]
+	Command { command: String, stderr: String },
+
+	#[error("Repository not found: {0}")]
+	NotFound(String),
+
+	#[error("Repository already exists: {0}")]
+	Conflict(String),
+}
+
+#[derive(Error, Debug)]
+pub enum QueueError {
+	#[error("Redis request failed: {0}")]
+	Redis(#[from] redis::RedisError),
+
+	#[error("Invalid job `{0}`: {1}")]
+	InvalidJob(String, String),
+}
+
+impl From<QueueError> for DbError {
+	fn from(error: QueueError) -> Self {
+		DbError::InternalServerError(format!("Submission queue failed: {}", error))
+	}
+}
+
+#[derive(Error, Debug)]
+pub enum NotifierError {
+	#[error("Webhook request failed: {0}")]
+	Http(#[from] reqwest::Error),
+
+	#[error("SMTP delivery failed: {0}")]
+	Smtp(#[from] lettre::transport::smtp::Error),
+
+	#[error("Invalid email address: {0}")]
+	Address(#[from] lettre::address::AddressError),
+
+	#[error("Failed to build email: {0}")]
+	Message(#[from] lettre::error::Error),
+
+	#[error("User `{0}` has no email address")]
+	MissingRecipient(String),
+}
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+	#[error("Failed to read config file {path}: {source}")]
+	Io { path: PathBuf, source: std::io::Error },
+
+	#[error("Failed to parse config file {path}: {source}")]
+	Parse { path: PathBuf, source: toml::de::Error },
+
+	#[error("Invalid configuration:\n  - {}", .0.join("\n  - "))]
+	Invalid(Vec<String>),
+}
+
+#[derive(Error, Debug)]
+pub enum MigrationError {
+	#[error("Database error: {0}")]
+	Database(#[from] mongodb::error::Error),
+
+	#[error("Another instance is running migrations, gave up waiting for it")]
+	Locked,
+
+	#[error("Unknown migration version {0}")]
+	UnknownVersion(u32),
+}
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+	#[error("No course.toml, course.yaml or course.yml found in {0}")]
+	MissingManifest(PathBuf),
+
+	#[error("Failed to read {path}: {source}")]
+	Io { path: PathBuf, source: std::io::Error },
+
+	#[error("Failed to parse {path}: {source}")]
+	Toml { path: PathBuf, source: toml::de::Error },
+
+	#[error("Failed to parse {path}: {source}")]
+	Yaml { path: PathBuf, source: serde_yaml::Error },
+
+	#[error("Invalid course manifest: {0}")]
+	Invalid(String),
+
+	#[error(transparent)]
+	Db(#[from] DbError),
+}
+
+#[derive(Error, Debug)]
+pub enum AuthError {
+	#[error("401 Unauthorized: missing bearer token")]
+	MissingToken,
+
+	#[error("401 Unauthorized: {0}")]
+	InvalidToken(String),
+
+	#[error("401 Unauthorized: {0}")]
+	InvalidSignature(String),
+
+	#[error("401 Unauthorized: request has already been processed")]
+	Replayed,
+
+	#[error("403 Forbidden")]
+	Forbidden,
+}
+
+/// The error returned by every HTTP handler. Rendered as an RFC 7807 problem details document
+/// whose `code` member is a stable, machine readable identifier for the kind of error.
+#[derive(Error, Debug)]
+pub enum ApiError {
+	#[error("{0}")]
+	BadRequest(String),
+
+	#[error("{0}")]
+	NotFound(String),
+
+	#[error("{0}")]
+	Conflict(String),
+
+	#[error("{0}")]
+	Validation(String),
+
+	#[error("{0}")]
+	Auth(#[from] AuthError),
+
+	#[error("{0}")]
+	GitServer(String),
+
+	#[error("{0}")]
+	Database(String),
+
+	#[error("{0}")]
+	Internal(String),
+}
+
+/// An RFC 7807 problem details document
+#[derive(Serialize)]
+struct ProblemDetails<'a> {
+	r#type: String,
+	title: &'a str,
+	status: u16,
+	detail: String,
+	code: &'static str,
+}
+
+impl ApiError {
+	/// The stable identifier clients can branch on
+	pub fn code(&self) -> &'static str {
+		match self {
+			ApiError::BadRequest(_) => "bad_request",
+			ApiError::NotFound(_) => "not_found",
+			ApiError::Conflict(_) => "conflict",
+			ApiError::Validation(_) => "validation_failed",
+			ApiError::Auth(AuthError::MissingToken) => "missing_token",
+			ApiError::Auth(AuthError::InvalidToken(_)) => "invalid_token",
+			ApiError::Auth(AuthError::InvalidSignature(_)) => "invalid_signature",
+			ApiError::Auth(AuthError::Replayed) => "replayed_request",
+			ApiError::Auth(AuthError::Forbidden) => "forbidden",
+			ApiError::GitServer(_) => "git_server_error",
+			ApiError::Database(_) => "database_error",
+			ApiError::Internal(_) => "internal_error",
+		}
+	}
+
+	/// A human readable description safe to return to clients. Details of server side failures are
+	/// logged rather than exposed.
+	fn detail(&self) -> String {
+		match self {
+			ApiError::GitServer(_) => "Failed to communicate with git server".to_string(),
+			ApiError::Database(_) => "Database operation failed".to_string(),
+			ApiError::Internal(_) => "Internal server error".to_string(),
+			ApiError::Auth(AuthError::Forbidden) =>
+				"You do not have access to this resource".to_string(),
+			error => error.to_string(),
+		}
+	}
+}
+
+impl ResponseError for ApiError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			ApiError::BadRequest(_) => StatusCode::BAD_REQUEST,
+			ApiError::NotFound(_) => StatusCode::NOT_FOUND,
+			ApiError::Conflict(_) => StatusCode::CONFLICT,
+			ApiError::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+			ApiError::Auth(AuthError::Forbidden) => StatusCode::FORBIDDEN,
+			ApiError::Auth(_) => StatusCode::UNAUTHORIZED,
+			ApiError::GitServer(_) => StatusCode::BAD_GATEWAY,
+			ApiError::Database(_) | ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+		}
+	}
+
+	fn error_response(&self) -> HttpResponse {
+		let status = self.status_code();
+		if status.is_server_error() {
+			error!("{} ({}): {}", status, self.code(), self);
+		}
+
+		let problem = ProblemDetails {
+			r#type: format!("urn:dotcodeschool:problem:{}", self.code()),
+			title: status.canonical_reason().unwrap_or_default(),
+			status: status.as_u16(),
+			detail: self.detail(),
+			code: self.code(),
+		};
+
+		let mut response = HttpResponse::build(status);
+		response.insert_header(ContentType(
+			"application/problem+json".parse().expect("valid media type"),
+		));
+		if matches!(self, ApiError::Auth(AuthError::MissingToken | AuthError::InvalidToken(_))) {
+			response.insert_header((WWW_AUTHENTICATE, "Bearer"));
+		}
+		response.json(problem)
+	}
+}
+
+impl From<DbError> for ApiError {
+	fn from(error: DbError) -> Self {
+		match error {
+			DbError::DatabaseError(e) if is_duplicate_key_error(&e) =>
+				ApiError::Conflict("Resource already exists".to_string()),
+			DbError::DatabaseError(e) => ApiError::Database(e.to_string()),
+			DbError::InternalServerError(e) => ApiError::Internal(e),
+			DbError::NotFound(e) => ApiError::NotFound(e),
+			DbError::Conflict(e) => ApiError::Conflict(e),
+			DbError::Validation(e) => ApiError::Validation(e),
+			DbError::InvalidObjectId(e) =>
+				ApiError::Validation(format!("Invalid object id: {}", e)),
+			DbError::Auth(e) => ApiError::Auth(e),
+			DbError::GitHost(e) => ApiError::from(e),
+		}
+	}
+}
+
+impl From<RepoCreationError> for ApiError {
+	fn from(error: RepoCreationError) -> Self {
+		match error {
+			RepoCreationError::GitServerError(e) => ApiError::from(e),
+			RepoCreationError::DatabaseError(e) => ApiError::from(DbError::DatabaseError(e)),
+			RepoCreationError::InvalidObjectId(e) =>
+				ApiError::Validation(format!("Invalid object id: {}", e)),
+			RepoCreationError::InsertionError(e) | RepoCreationError::InternalServerError(e) =>
+				ApiError::Internal(e),
+			RepoCreationError::NotFound(e) => ApiError::NotFound(e),
+			RepoCreationError::Conflict(e) => ApiError::Conflict(e),
+			RepoCreationError::Validation(e) => ApiError::Validation(e),
+			e @ RepoCreationError::UnknownTemplate(_) => ApiError::Validation(e.to_string()),
+			RepoCreationError::Auth(e) => ApiError::Auth(e),
+		}
+	}
+}
+
+impl From<GitHostError> for ApiError {
+	fn from(error: GitHostError) -> Self {
+		match error {
+			GitHostError::Conflict(repo_name) =>
+				ApiError::Conflict(format!("Repository `{}` already exists", repo_name)),
+			e => ApiError::GitServer(e.to_string()),
+		}
+	}
+}
+
+/// Whether a MongoDB error was caused by a unique index violation
+pub fn is_duplicate_key_error(error: &mongodb::error::Error) -> bool {
+	const DUPLICATE_KEY: i32 = 11000;
+
+	match error.kind.as_ref() {
+		ErrorKind::Write(WriteFailure::WriteError(e)) => e.code == DUPLICATE_KEY,
+		ErrorKind::Command(e) => e.code == DUPLICATE_KEY,
+		ErrorKind::InsertMany(e) => e
+			.write_errors
+			.as_ref()
+			.is_some_and(|errors| errors.iter().any(|e| e.code == DUPLICATE_KEY)),
+		_ => false,
+	}
 }
@@ -0,0 +1,167 @@
+use std::{
+	collections::{BTreeMap, HashSet},
+	sync::{Arc, Mutex, MutexGuard},
+};
+
+use async_trait::async_trait;
+
+use super::GitHost;
+use crate::errors::GitHostError;
+
+/// A call made against a [`RecordingGitHost`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum GitHostCall {
+	Create { repo_name: String, template: String },
+	Delete { repo_name: String },
+	Reseed { repo_name: String, template: String },
+	Archive { repo_name: String },
+	Restore { repo_name: String },
+	CommitExists { repo_name: String, commit_sha: String },
+	ListRepos,
+}
+
+#[derive(Default)]
+struct State {
+	calls: Vec<GitHostCall>,
+	/// Repositories on the fake host, mapped to the commits they contain
+	repos: BTreeMap<String, HashSet<String>>,
+	/// Archived repositories, mapped to the commits they contain
+	archived: BTreeMap<String, HashSet<String>>,
+	fail_creates: bool,
+	fail_deletes: bool,
+}
+
+/// An in-memory [`GitHost`] that records every call made against it. Used in tests and local
+/// development to exercise repository creation without a git server. Clones share the same state.
+#[derive(Clone, Default)]
+pub(crate) struct RecordingGitHost {
+	state: Arc<Mutex<State>>,
+}
+
+impl RecordingGitHost {
+	pub(crate) fn new() -> Self {
+		Self::default()
+	}
+
+	/// All calls made so far, in order
+	#[cfg(test)]
+	pub(crate) fn calls(&self) -> Vec<GitHostCall> {
+		self.state().calls.clone()
+	}
+
+	/// Record that `commit_sha` has been pushed to `repo_name`
+	#[cfg(test)]
+	pub(crate) fn push_commit(&self, repo_name: &str, commit_sha: &str) {
+		self.state()
+			.repos
+			.entry(repo_name.to_string())
+			.or_default()
+			.insert(commit_sha.to_string());
+	}
+
+	/// Make every subsequent `create_repo` call fail
+	#[cfg(test)]
+	pub(crate) fn fail_creates(&self, fail: bool) {
+		self.state().fail_creates = fail;
+	}
+
+	/// Make every subsequent `delete_repo` call fail
+	#[cfg(test)]
+	pub(crate) fn fail_deletes(&self, fail: bool) {
+		self.state().fail_deletes = fail;
+	}
+
+	fn state(&self) -> MutexGuard<'_, State> {
+		self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+	}
+}
+
+#[async_trait]
+impl GitHost for RecordingGitHost {
+	async fn create_repo(&self, repo_name: &str, template: &str) -> Result<(), GitHostError> {
+		let mut state = self.state();
+		state.calls.push(GitHostCall::Create {
+			repo_name: repo_name.to_string(),
+			template: template.to_string(),
+		});
+
+		if state.fail_creates {
+			return Err(GitHostError::Io(std::io::Error::other("injected create failure")));
+		}
+		if state.repos.contains_key(repo_name) || state.archived.contains_key(repo_name) {
+			return Err(GitHostError::Conflict(repo_name.to_string()));
+		}
+		state.repos.insert(repo_name.to_string(), HashSet::new());
+		Ok(())
+	}
+
+	async fn delete_repo(&self, repo_name: &str) -> Result<(), GitHostError> {
+		let mut state = self.state();
+		state.calls.push(GitHostCall::Delete { repo_name: repo_name.to_string() });
+
+		if state.fail_deletes {
+			return Err(GitHostError::Io(std::io::Error::other("injected delete failure")));
+		}
+		let removed = state.repos.remove(repo_name).or_else(|| state.archived.remove(repo_name));
+		removed.map(|_| ()).ok_or_else(|| GitHostError::NotFound(repo_name.to_string()))
+	}
+
+	async fn reseed_repo(&self, repo_name: &str, template: &str) -> Result<(), GitHostError> {
+		let mut state = self.state();
+		state.calls.push(GitHostCall::Reseed {
+			repo_name: repo_name.to_string(),
+			template: template.to_string(),
+		});
+
+		match state.repos.get_mut(repo_name) {
+			Some(commits) => {
+				commits.clear();
+				Ok(())
+			},
+			None => Err(GitHostError::NotFound(repo_name.to_string())),
+		}
+	}
+
+	async fn archive_repo(&self, repo_name: &str) -> Result<(), GitHostError> {
+		let mut state = self.state();
+		state.calls.push(GitHostCall::Archive { repo_name: repo_name.to_string() });
+
+		let commits = state
+			.repos
+			.remove(repo_name)
+			.ok_or_else(|| GitHostError::NotFound(repo_name.to_string()))?;
+		state.archived.insert(repo_name.to_string(), commits);
+		Ok(())
+	}
+
+	async fn restore_repo(&self, repo_name: &str) -> Result<(), GitHostError> {
+		let mut state = self.state();
+		state.calls.push(GitHostCall::Restore { repo_name: repo_name.to_string() });
+
+		let commits = state
+			.archived
+			.remove(repo_name)
+			.ok_or_else(|| GitHostError::NotFound(repo_name.to_string()))?;
+		state.repos.insert(repo_name.to_string(), commits);
+		Ok(())
+	}
+
+	async fn commit_exists(&self, repo_name: &str, commit_sha: &str) -> Result<bool, GitHostError> {
+		let mut state = self.state();
+		state.calls.push(GitHostCall::CommitExists {
+			repo_name: repo_name.to_string(),
+			commit_sha: commit_sha.to_string(),
+		});
+
+		match state.repos.get(repo_name) {
+			Some(commits) => Ok(commits.contains(commit_sha)),
+			None => Err(GitHostError::NotFound(repo_name.to_string())),
+		}
+	}
+
+	async fn list_repos(&self) -> Result<Vec<String>, GitHostError> {
+		let mut state = self.state();
+		state.calls.push(GitHostCall::ListRepos);
+		Ok(state.repos.keys().cloned().collect())
+	}
+}
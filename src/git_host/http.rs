@@ -0,0 +1,116 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use log::{info, warn};
+use reqwest::StatusCode;
+
+use super::GitHost;
+use crate::errors::GitHostError;
+
+/// A [`GitHost`] that talks to the dotcodeschool git server over its HTTP API
+pub(crate) struct HttpGitHost {
+	client: reqwest::Client,
+	base_url: String,
+	bearer_token: Option<String>,
+}
+
+impl HttpGitHost {
+	pub(crate) fn new(base_url: impl Into<String>, bearer_token: Option<String>) -> Self {
+		match bearer_token {
+			Some(_) => info!("Using bearer token to authenticate with git server"),
+			None => warn!("No bearer token found, proceeding without authentication"),
+		}
+		HttpGitHost { client: reqwest::Client::new(), base_url: base_url.into(), bearer_token }
+	}
+
+	/// Add the bearer token to the request if one is configured
+	fn authenticate(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+		match &self.bearer_token {
+			Some(token) => request.bearer_auth(token),
+			None => request,
+		}
+	}
+
+	fn url(&self, path: &str) -> String {
+		format!("{}/api/v0/{}", self.base_url, path)
+	}
+
+	/// POST `{"repo_name": repo_name}` to `path`, mapping a 404 to [`GitHostError::NotFound`]
+	async fn post_repo_action(&self, path: &str, repo_name: &str) -> Result<(), GitHostError> {
+		let json = HashMap::from([("repo_name", repo_name)]);
+
+		let request = self.authenticate(self.client.post(self.url(path)));
+
+		let response = request.json(&json).send().await?;
+		if response.status() == StatusCode::NOT_FOUND {
+			return Err(GitHostError::NotFound(repo_name.to_string()));
+		}
+		response.error_for_status()?;
+
+		Ok(())
+	}
+}
+
+#[async_trait]
+impl GitHost for HttpGitHost {
+	async fn create_repo(&self, repo_name: &str, template: &str) -> Result<(), GitHostError> {
+		let json = HashMap::from([("repo_name", repo_name), ("template_repo", template)]);
+
+		let request = self.client.post(self.url("create_repository"));
+		let request = self.authenticate(request);
+
+		let response = request.json(&json).send().await?;
+		if response.status() == StatusCode::CONFLICT {
+			return Err(GitHostError::Conflict(repo_name.to_string()));
+		}
+		response.error_for_status()?;
+
+		Ok(())
+	}
+
+	async fn delete_repo(&self, repo_name: &str) -> Result<(), GitHostError> {
+		self.post_repo_action("delete_repository", repo_name).await
+	}
+
+	async fn reseed_repo(&self, repo_name: &str, template: &str) -> Result<(), GitHostError> {
+		let json = HashMap::from([("repo_name", repo_name), ("template_repo", template)]);
+
+		let request = self.authenticate(self.client.post(self.url("reseed_repository")));
+
+		let response = request.json(&json).send().await?;
+		if response.status() == StatusCode::NOT_FOUND {
+			return Err(GitHostError::NotFound(repo_name.to_string()));
+		}
+		response.error_for_status()?;
+
+		Ok(())
+	}
+
+	async fn archive_repo(&self, repo_name: &str) -> Result<(), GitHostError> {
+		self.post_repo_action("archive_repository", repo_name).await
+	}
+
+	async fn restore_repo(&self, repo_name: &str) -> Result<(), GitHostError> {
+		self.post_repo_action("restore_repository", repo_name).await
+	}
+
+	async fn commit_exists(&self, repo_name: &str, commit_sha: &str) -> Result<bool, GitHostError> {
+		let path = format!("repository/{}/commit/{}", repo_name, commit_sha);
+		let request = self.authenticate(self.client.get(self.url(&path)));
+
+		let response = request.send().await?;
+		if response.status() == StatusCode::NOT_FOUND {
+			return Ok(false);
+		}
+		response.error_for_status()?;
+
+		Ok(true)
+	}
+
+	async fn list_repos(&self) -> Result<Vec<String>, GitHostError> {
+		let request = self.authenticate(self.client.get(self.url("repositories")));
+
+		let response = request.send().await?.error_for_status()?;
+		Ok(response.json().await?)
+	}
+}
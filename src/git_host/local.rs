@@ -0,0 +1,285 @@
+use std::{
+	path::{Path, PathBuf},
+	process::Command,
+};
+
+use actix_web::rt::task::spawn_blocking;
+use async_trait::async_trait;
+use log::{info, warn};
+
+use super::GitHost;
+use crate::errors::GitHostError;
+
+/// A [`GitHost`] that keeps bare repositories on the local filesystem. New repositories are seeded
+/// from `templates_dir/<template>`, which may either be a git repository or a plain directory of
+/// files.
+pub(crate) struct LocalGitHost {
+	repos_dir: PathBuf,
+	templates_dir: PathBuf,
+}
+
+impl LocalGitHost {
+	pub(crate) fn new(repos_dir: impl Into<PathBuf>, templates_dir: impl Into<PathBuf>) -> Self {
+		LocalGitHost { repos_dir: repos_dir.into(), templates_dir: templates_dir.into() }
+	}
+
+	fn repo_path(&self, repo_name: &str) -> PathBuf {
+		self.repos_dir.join(format!("{}.git", repo_name))
+	}
+
+	/// Where an archived repository is kept. The directory is not served, since it does not end in
+	/// `.git`, and is skipped by `list_repos`.
+	fn archived_path(&self, repo_name: &str) -> PathBuf {
+		self.repos_dir.join(ARCHIVE_DIR).join(format!("{}.git", repo_name))
+	}
+}
+
+/// Directory under `repos_dir` holding archived repositories
+const ARCHIVE_DIR: &str = "archived";
+
+#[async_trait]
+impl GitHost for LocalGitHost {
+	async fn create_repo(&self, repo_name: &str, template: &str) -> Result<(), GitHostError> {
+		let dest = self.repo_path(repo_name);
+		let archived = self.archived_path(repo_name);
+		let template_dir = self.templates_dir.join(template);
+		let repo_name = repo_name.to_string();
+
+		blocking(move || {
+			if archived.exists() {
+				return Err(GitHostError::Conflict(repo_name));
+			}
+			if !template_dir.is_dir() {
+				return Err(GitHostError::NotFound(template_dir.display().to_string()));
+			}
+			if let Some(parent) = dest.parent() {
+				std::fs::create_dir_all(parent)?;
+			}
+
+			// Claim the name before seeding, so that of two concurrent creations only one succeeds
+			match std::fs::create_dir(&dest) {
+				Ok(()) => {},
+				Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists =>
+					return Err(GitHostError::Conflict(repo_name)),
+				Err(e) => return Err(e.into()),
+			}
+			if let Err(e) = seed(&template_dir, &dest) {
+				let _ = std::fs::remove_dir_all(&dest);
+				return Err(e);
+			}
+
+			info!("Created local repository `{}` at {}", repo_name, dest.display());
+			Ok(())
+		})
+		.await
+	}
+
+	async fn delete_repo(&self, repo_name: &str) -> Result<(), GitHostError> {
+		let paths = [self.repo_path(repo_name), self.archived_path(repo_name)];
+		let repo_name = repo_name.to_string();
+
+		blocking(move || {
+			let Some(path) = paths.iter().find(|path| path.exists()) else {
+				return Err(GitHostError::NotFound(repo_name));
+			};
+			std::fs::remove_dir_all(path)?;
+			Ok(())
+		})
+		.await
+	}
+
+	async fn reseed_repo(&self, repo_name: &str, template: &str) -> Result<(), GitHostError> {
+		let dest = self.repo_path(repo_name);
+		let template_dir = self.templates_dir.join(template);
+		let staging =
+			self.repos_dir.join(format!(".{}.reseed-{}", repo_name, uuid::Uuid::new_v4()));
+		let retired =
+			self.repos_dir.join(format!(".{}.retired-{}", repo_name, uuid::Uuid::new_v4()));
+		let repo_name = repo_name.to_string();
+
+		blocking(move || {
+			if !dest.exists() {
+				return Err(GitHostError::NotFound(repo_name));
+			}
+			if !template_dir.is_dir() {
+				return Err(GitHostError::NotFound(template_dir.display().to_string()));
+			}
+
+			// Seed the new repository next to the old one, which is only replaced once the seed
+			// has succeeded. Neither path ends in `.git`, so neither is served in the meantime.
+			if let Err(e) = seed(&template_dir, &staging) {
+				let _ = std::fs::remove_dir_all(&staging);
+				return Err(e);
+			}
+			if let Err(e) = std::fs::rename(&dest, &retired) {
+				let _ = std::fs::remove_dir_all(&staging);
+				return Err(e.into());
+			}
+			if let Err(e) = std::fs::rename(&staging, &dest) {
+				std::fs::rename(&retired, &dest)?;
+				let _ = std::fs::remove_dir_all(&staging);
+				return Err(e.into());
+			}
+			if let Err(e) = std::fs::remove_dir_all(&retired) {
+				warn!("Failed to remove replaced repository {}: {}", retired.display(), e);
+			}
+
+			info!("Reseeded local repository `{}` at {}", repo_name, dest.display());
+			Ok(())
+		})
+		.await
+	}
+
+	async fn archive_repo(&self, repo_name: &str) -> Result<(), GitHostError> {
+		let (from, to) = (self.repo_path(repo_name), self.archived_path(repo_name));
+		blocking(move || move_repo(from, to)).await
+	}
+
+	async fn restore_repo(&self, repo_name: &str) -> Result<(), GitHostError> {
+		let (from, to) = (self.archived_path(repo_name), self.repo_path(repo_name));
+		blocking(move || move_repo(from, to)).await
+	}
+
+	async fn commit_exists(&self, repo_name: &str, commit_sha: &str) -> Result<bool, GitHostError> {
+		let path = self.repo_path(repo_name);
+		let object = format!("{}^{{commit}}", commit_sha);
+		let repo_name = repo_name.to_string();
+
+		blocking(move || {
+			if !path.exists() {
+				return Err(GitHostError::NotFound(repo_name));
+			}
+			let status = Command::new("git")
+				.arg("-C")
+				.arg(&path)
+				.args(["cat-file", "-e", &object])
+				.status()?;
+			Ok(status.success())
+		})
+		.await
+	}
+
+	async fn list_repos(&self) -> Result<Vec<String>, GitHostError> {
+		let repos_dir = self.repos_dir.clone();
+
+		blocking(move || {
+			if !repos_dir.exists() {
+				return Ok(vec![]);
+			}
+			let mut repos = vec![];
+			for entry in std::fs::read_dir(&repos_dir)? {
+				let name = entry?.file_name().to_string_lossy().into_owned();
+				if let Some(repo_name) = name.strip_suffix(".git") {
+					repos.push(repo_name.to_string());
+				}
+			}
+			repos.sort();
+			Ok(repos)
+		})
+		.await
+	}
+}
+
+/// Run a filesystem or git operation on the blocking thread pool
+async fn blocking<T, F>(f: F) -> Result<T, GitHostError>
+where
+	F: FnOnce() -> Result<T, GitHostError> + Send + 'static,
+	T: Send + 'static,
+{
+	spawn_blocking(f)
+		.await
+		.map_err(|e| GitHostError::Io(std::io::Error::other(e.to_string())))?
+}
+
+/// Create a bare repository at `dest` from `template_dir`, cloning it if it is a git repository
+fn seed(template_dir: &Path, dest: &Path) -> Result<(), GitHostError> {
+	if template_dir.join(".git").exists() || template_dir.join("HEAD").is_file() {
+		git(None, &["clone", "--bare", "--no-hardlinks", path_str(template_dir)?, path_str(dest)?])
+	} else {
+		seed_from_files(template_dir, dest)
+	}
+}
+
+/// Move a repository between the served and archive directories
+fn move_repo(from: PathBuf, to: PathBuf) -> Result<(), GitHostError> {
+	if !from.exists() {
+		return Err(GitHostError::NotFound(from.display().to_string()));
+	}
+	if to.exists() {
+		return Err(GitHostError::Conflict(to.display().to_string()));
+	}
+	if let Some(parent) = to.parent() {
+		std::fs::create_dir_all(parent)?;
+	}
+	std::fs::rename(&from, &to)?;
+	info!("Moved local repository {} to {}", from.display(), to.display());
+	Ok(())
+}
+
+/// Create a bare repository at `dest` whose initial commit contains the files in `template_dir`
+fn seed_from_files(template_dir: &Path, dest: &Path) -> Result<(), GitHostError> {
+	let work_dir = std::env::temp_dir().join(format!("dcs-template-{}", uuid::Uuid::new_v4()));
+	copy_dir(template_dir, &work_dir)?;
+
+	let result = (|| {
+		git(Some(&work_dir), &["init", "--quiet"])?;
+		git(Some(&work_dir), &["add", "--all"])?;
+		git(
+			Some(&work_dir),
+			&[
+				"-c",
+				"user.name=dotcodeschool",
+				"-c",
+				"user.email=bot@dotcodeschool.com",
+				"commit",
+				"--quiet",
+				"--allow-empty",
+				"-m",
+				"Initial commit",
+			],
+		)?;
+		git(None, &["clone", "--bare", "--quiet", path_str(&work_dir)?, path_str(dest)?])
+	})();
+
+	std::fs::remove_dir_all(&work_dir)?;
+	result
+}
+
+/// Recursively copy the contents of `from` into `to`
+fn copy_dir(from: &Path, to: &Path) -> std::io::Result<()> {
+	std::fs::create_dir_all(to)?;
+	for entry in std::fs::read_dir(from)? {
+		let entry = entry?;
+		let target = to.join(entry.file_name());
+		if entry.file_type()?.is_dir() {
+			copy_dir(&entry.path(), &target)?;
+		} else {
+			std::fs::copy(entry.path(), target)?;
+		}
+	}
+	Ok(())
+}
+
+/// Run a git command, optionally inside `dir`, and fail if it exits unsuccessfully
+fn git(dir: Option<&Path>, args: &[&str]) -> Result<(), GitHostError> {
+	let mut command = Command::new("git");
+	if let Some(dir) = dir {
+		command.arg("-C").arg(dir);
+	}
+	let output = command.args(args).output()?;
+
+	if output.status.success() {
+		Ok(())
+	} else {
+		Err(GitHostError::Command {
+			command: format!("git {}", args.join(" ")),
+			stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+		})
+	}
+}
+
+fn path_str(path: &Path) -> Result<&str, GitHostError> {
+	path.to_str().ok_or_else(|| {
+		GitHostError::Io(std::io::Error::other(format!("Non UTF-8 path: {}", path.display())))
+	})
+}
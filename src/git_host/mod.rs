@@ -0,0 +1,62 @@
+mod fake;
+mod http;
+mod local;
+
+use async_trait::async_trait;
+
+use crate::errors::GitHostError;
+
+#[cfg(test)]
+pub(crate) use fake::GitHostCall;
+pub(crate) use fake::RecordingGitHost;
+pub(crate) use http::HttpGitHost;
+pub(crate) use local::LocalGitHost;
+
+/// Operations the backend needs from the service hosting learners' git repositories
+#[async_trait]
+pub(crate) trait GitHost: Send + Sync {
+	/// Create a repository named `repo_name` from the course template `template`
+	async fn create_repo(&self, repo_name: &str, template: &str) -> Result<(), GitHostError>;
+
+	/// Delete the repository named `repo_name`, whether it is archived or not
+	async fn delete_repo(&self, repo_name: &str) -> Result<(), GitHostError>;
+
+	/// Replace the contents of the repository named `repo_name` with the course template
+	/// `template`, discarding its history
+	async fn reseed_repo(&self, repo_name: &str, template: &str) -> Result<(), GitHostError>;
+
+	/// Archive the repository named `repo_name`, keeping its contents but refusing pushes and
+	/// leaving it out of [`GitHost::list_repos`]
+	async fn archive_repo(&self, repo_name: &str) -> Result<(), GitHostError>;
+
+	/// Restore the repository named `repo_name` from the archive
+	async fn restore_repo(&self, repo_name: &str) -> Result<(), GitHostError>;
+
+	/// Check whether `commit_sha` exists in the repository named `repo_name`
+	async fn commit_exists(&self, repo_name: &str, commit_sha: &str) -> Result<bool, GitHostError>;
+
+	/// List the names of all repositories on the host
+	async fn list_repos(&self) -> Result<Vec<String>, GitHostError>;
+}
+
+/// The git hosting backend to use, selected by `git.backend` in the config
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum GitHostBackend {
+	Http,
+	Local,
+	Fake,
+}
+
+impl std::str::FromStr for GitHostBackend {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"http" => Ok(GitHostBackend::Http),
+			"local" => Ok(GitHostBackend::Local),
+			"fake" => Ok(GitHostBackend::Fake),
+			other => Err(format!("Unknown git host backend `{}`", other)),
+		}
+	}
+}
@@ -1,16 +1,36 @@
 use actix_web::HttpResponse;
 
 use crate::{
-	errors::{DbError, RepoCreationError},
-	models::{Course, Repository},
-	types::{CreateRepoResponse, CreateSubmissionResponse, UpdateRepoResponse},
+	integrity::IntegrityReport,
+	models::{Course, CourseVersion, Repository, Submission},
+	store::IndexReport,
+	types::{
+		ClaimedJobResponse, CoursePageResponse, CreateRepoResponse, CreateSubmissionResponse,
+		IncludedResponse, RepositoryStatsResponse, SubmissionPageResponse, UpdateRepoResponse,
+		UserRepositoryResponse, UserResponse,
+	},
 };
 
 /// Constructs an HTTP response for a successful course data retrieval
-pub(super) fn fetch_course_success_response(course: Course) -> HttpResponse {
+pub(super) fn fetch_course_success_response(course: IncludedResponse<Course>) -> HttpResponse {
 	HttpResponse::Ok().json(course)
 }
 
+/// Constructs an HTTP response for a successful retrieval of a page of the course catalog
+pub(super) fn list_courses_success_response(page: CoursePageResponse) -> HttpResponse {
+	HttpResponse::Ok().json(page)
+}
+
+/// Constructs an HTTP response for a course created, updated, published or unpublished by an admin
+pub(super) fn course_update_success_response(course: Course) -> HttpResponse {
+	HttpResponse::Ok().json(course)
+}
+
+/// Constructs an HTTP response for a successful retrieval of the prior versions of a course
+pub(super) fn list_course_versions_success_response(versions: Vec<CourseVersion>) -> HttpResponse {
+	HttpResponse::Ok().json(versions)
+}
+
 /// Constructs an HTTP response for a successful repository creation
 pub(super) fn repository_creation_success_response(
 	repo_name: String,
@@ -19,23 +39,6 @@ pub(super) fn repository_creation_success_response(
 	HttpResponse::Ok().json(CreateRepoResponse { repo_name, repo_template: template.to_string() })
 }
 
-/// Handles errors during repository creation and returns the appropriate HTTP response
-pub(super) fn handle_repo_creation_error(error: RepoCreationError) -> HttpResponse {
-	match error {
-		RepoCreationError::GitServerError(_) =>
-			HttpResponse::InternalServerError().body("Failed to communicate with git server"),
-		RepoCreationError::DatabaseError(_) =>
-			HttpResponse::InternalServerError().body("Failed to save repository to database"),
-		RepoCreationError::InvalidObjectId(_) =>
-			HttpResponse::InternalServerError().body("Invalid object id"),
-		RepoCreationError::InsertionError(_) =>
-			HttpResponse::InternalServerError().body("Failed to insert repository into database"),
-		RepoCreationError::NotFound(_) => HttpResponse::NotFound().body("404 Not Found"),
-		RepoCreationError::InternalServerError(_) =>
-			HttpResponse::InternalServerError().body("500 Internal Server Error"),
-	}
-}
-
 /// Constructs an HTTP response for a successful submission creation
 pub(super) fn submission_creation_success_response(
 	submission_reponse: CreateSubmissionResponse,
@@ -43,31 +46,109 @@ pub(super) fn submission_creation_success_response(
 	HttpResponse::Ok().json(submission_reponse)
 }
 
-/// Handles errors during submission creation and returns the appropriate HTTP response
-pub(super) fn handle_db_error(error: DbError) -> HttpResponse {
-	match error {
-		DbError::DatabaseError(_) =>
-			HttpResponse::InternalServerError().body("Failed to save submission to database"),
-		DbError::InternalServerError(_) =>
-			HttpResponse::InternalServerError().body("500 Internal Server Error"),
-		DbError::NotFound(_) => HttpResponse::NotFound().body("404 Not Found"),
-	}
+/// Constructs an HTTP response for successful retrieval of repository
+pub(super) fn get_repository_success_response(
+	repository: IncludedResponse<Repository>,
+) -> HttpResponse {
+	HttpResponse::Ok().json(repository)
 }
 
-/// Constructs an HTTP response for successful retrieval of repository
-pub(super) fn get_repository_success_response(repository: Repository) -> HttpResponse {
+/// Constructs an HTTP response for a repository migrated to the latest tester of its course
+pub(super) fn tester_migration_success_response(repository: Repository) -> HttpResponse {
+	HttpResponse::Ok().json(repository)
+}
+
+/// Constructs an HTTP response for a repository whose course was restarted
+pub(super) fn repository_reset_response(repository: Repository) -> HttpResponse {
+	HttpResponse::Ok().json(repository)
+}
+
+/// Constructs an HTTP response for a repository archived by its owner
+pub(super) fn repository_archived_response() -> HttpResponse {
+	HttpResponse::NoContent().finish()
+}
+
+/// Constructs an HTTP response for an archived repository restored by an admin
+pub(super) fn repository_restored_response(repository: Repository) -> HttpResponse {
 	HttpResponse::Ok().json(repository)
 }
 
+/// Constructs an HTTP response for a relationship integrity check or repair
+pub(super) fn integrity_report_response(report: IntegrityReport) -> HttpResponse {
+	HttpResponse::Ok().json(report)
+}
+
+/// Constructs an HTTP response for a comparison of declared and existing indexes
+pub(super) fn index_report_response(report: IndexReport) -> HttpResponse {
+	HttpResponse::Ok().json(report)
+}
+
+/// Constructs an HTTP response for a test result recorded by a tester
+pub(super) fn test_result_recorded_response() -> HttpResponse {
+	HttpResponse::NoContent().finish()
+}
+
+/// Constructs an HTTP response for a submission progress report recorded by a tester
+pub(super) fn submission_report_recorded_response(submission: Submission) -> HttpResponse {
+	HttpResponse::Ok().json(submission)
+}
+
+/// Constructs an HTTP response for a submission job claimed by a tester worker, or for an empty
+/// queue
+pub(super) fn job_claimed_response(job: Option<ClaimedJobResponse>) -> HttpResponse {
+	match job {
+		Some(job) => HttpResponse::Ok().json(job),
+		None => HttpResponse::NoContent().finish(),
+	}
+}
+
+/// Constructs an HTTP response for a heartbeat extending the claim of a tester worker on a job
+pub(super) fn job_heartbeat_response() -> HttpResponse {
+	HttpResponse::NoContent().finish()
+}
+
+/// Constructs an HTTP response for successful retrieval of a submission
+pub(super) fn get_submission_success_response(submission: Submission) -> HttpResponse {
+	HttpResponse::Ok().json(submission)
+}
+
+/// Constructs an HTTP response for successful retrieval of a page of submissions
+pub(super) fn list_submissions_success_response(page: SubmissionPageResponse) -> HttpResponse {
+	HttpResponse::Ok().json(page)
+}
+
+/// Constructs an HTTP response for successful retrieval of repository statistics
+pub(super) fn repository_stats_success_response(stats: RepositoryStatsResponse) -> HttpResponse {
+	HttpResponse::Ok().json(stats)
+}
+
 /// Constructs an HTTP response for a successful repository update
 pub(super) fn repository_update_success_response(repository: Repository) -> HttpResponse {
 	HttpResponse::Ok().json(UpdateRepoResponse {
 		repo_name: repository.repo_name,
 		repo_template: repository.repo_template,
 		tester_url: repository.tester_url,
+		tester_version: repository.tester_version,
 		test_ok: repository.test_ok,
 		relationships: repository.relationships,
 		expected_practice_frequency: repository.expected_practice_frequency,
 		is_reminder_enabled: repository.is_reminder_enabled,
 	})
 }
+
+/// Constructs an HTTP response for successful retrieval of a user
+pub(super) fn get_user_success_response(user: IncludedResponse<UserResponse>) -> HttpResponse {
+	HttpResponse::Ok().json(user)
+}
+
+/// Constructs an HTTP response for a successful user update
+pub(super) fn user_update_success_response(user: UserResponse) -> HttpResponse {
+	HttpResponse::Ok().json(user)
+}
+
+/// Constructs an HTTP response for successful retrieval of a user's repositories
+pub(super) fn list_user_repositories_success_response(
+	repositories: Vec<UserRepositoryResponse>,
+) -> HttpResponse {
+	HttpResponse::Ok().json(repositories)
+}
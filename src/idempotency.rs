@@ -0,0 +1,160 @@
+use std::{
+	future::{ready, Future, Ready},
+	time::Duration,
+};
+
+use actix_web::{
+	dev::Payload,
+	http::header::{HeaderName, HeaderValue},
+	FromRequest, HttpRequest, HttpResponse,
+};
+use log::{info, warn};
+use serde::{de::DeserializeOwned, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+	auth::Identity,
+	errors::{ApiError, DbError},
+	models::IdempotencyRecord,
+	store::Store,
+};
+
+/// Header carrying a client chosen key that identifies retries of the same request
+pub(crate) const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+/// Header set on responses replayed from an earlier request with the same idempotency key
+pub(crate) const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// Longest idempotency key accepted
+const MAX_KEY_LEN: usize = 255;
+
+/// The `Idempotency-Key` of a request, if it has one. Keys are at most 255 visible ASCII
+/// characters; requests with any other key are rejected.
+pub(crate) struct IdempotencyKey(pub Option<String>);
+
+impl FromRequest for IdempotencyKey {
+	type Error = ApiError;
+	type Future = Ready<Result<Self, Self::Error>>;
+
+	fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+		let Some(header) = req.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+			return ready(Ok(IdempotencyKey(None)));
+		};
+
+		let key = header.to_str().ok().filter(|key| {
+			!key.is_empty() &&
+				key.len() <= MAX_KEY_LEN &&
+				key.bytes().all(|byte| byte.is_ascii_graphic())
+		});
+		ready(match key {
+			Some(key) => Ok(IdempotencyKey(Some(key.to_string()))),
+			None => Err(ApiError::BadRequest(format!(
+				"{} must be 1 to {} visible ASCII characters",
+				IDEMPOTENCY_KEY_HEADER, MAX_KEY_LEN
+			))),
+		})
+	}
+}
+
+/// Run `handle` at most once per idempotency key, caller and endpoint, for `ttl`. A retry of a
+/// completed request returns the stored response instead, along with `true`. A retry while the
+/// request is still being processed, or with a different request body, is rejected. A failed
+/// request, or one whose response could not be stored, releases its key so that it can be retried.
+///
+/// Requests without a key are always handled.
+pub(crate) async fn run_idempotent<T, E, F, Fut>(
+	store: &dyn Store,
+	identity: &Identity,
+	key: &IdempotencyKey,
+	endpoint: &str,
+	request: &impl Serialize,
+	ttl: Duration,
+	handle: F,
+) -> Result<(T, bool), E>
+where
+	T: Serialize + DeserializeOwned,
+	E: From<DbError>,
+	F: FnOnce() -> Fut,
+	Fut: Future<Output = Result<T, E>>,
+{
+	let Some(key) = &key.0 else {
+		return Ok((handle().await?, false));
+	};
+
+	let ttl =
+		chrono::Duration::from_std(ttl).map_err(|e| DbError::InternalServerError(e.to_string()))?;
+	let body = serde_json::to_vec(request)
+		.map_err(|e| DbError::InternalServerError(format!("Failed to hash request: {}", e)))?;
+	let record = IdempotencyRecord {
+		user_id: identity.user_id,
+		endpoint: endpoint.to_string(),
+		key: key.clone(),
+		fingerprint: hex::encode(Sha256::digest(&body)),
+		response: None,
+		expires_at: chrono::Utc::now() + ttl,
+	};
+
+	if let Some(existing) = store.reserve_idempotency_key(record.clone()).await? {
+		if existing.fingerprint != record.fingerprint {
+			return Err(DbError::Validation(format!(
+				"Idempotency key `{}` was already used with a different request",
+				key
+			))
+			.into());
+		}
+		let Some(response) = existing.response else {
+			return Err(DbError::Conflict(format!(
+				"A request with idempotency key `{}` is still in progress",
+				key
+			))
+			.into());
+		};
+		info!("Replaying response to `{}` for idempotency key `{}`", endpoint, key);
+		let response = serde_json::from_str(&response).map_err(|e| {
+			DbError::InternalServerError(format!("Failed to read stored response: {}", e))
+		})?;
+		return Ok((response, true));
+	}
+
+	match handle().await {
+		Ok(response) => {
+			// The request succeeded, so a failure to store its response must not fail it. The key
+			// is released instead, so that a retry is handled again rather than rejected as in
+			// progress until the key expires.
+			let stored = serde_json::to_string(&response).map_err(|e| e.to_string());
+			let result = match stored {
+				Ok(stored) => store
+					.complete_idempotency_key(&identity.user_id, endpoint, key, &stored)
+					.await
+					.map_err(|e| e.to_string()),
+				Err(e) => Err(e),
+			};
+			if let Err(e) = result {
+				warn!("Failed to store response for idempotency key `{}`: {}", key, e);
+				release(store, identity, endpoint, key).await;
+			}
+			Ok((response, false))
+		},
+		Err(e) => {
+			release(store, identity, endpoint, key).await;
+			Err(e)
+		},
+	}
+}
+
+/// Release an idempotency key reserved by [`run_idempotent`], so that the request can be retried
+async fn release(store: &dyn Store, identity: &Identity, endpoint: &str, key: &str) {
+	if let Err(e) = store.release_idempotency_key(&identity.user_id, endpoint, key).await {
+		warn!("Failed to release idempotency key `{}`: {}", key, e);
+	}
+}
+
+/// Mark a response as replayed from an earlier request with the same idempotency key
+pub(crate) fn mark_replayed(mut response: HttpResponse, replayed: bool) -> HttpResponse {
+	if replayed {
+		response.headers_mut().insert(
+			HeaderName::from_static(IDEMPOTENT_REPLAYED_HEADER),
+			HeaderValue::from_static("true"),
+		);
+	}
+	response
+}
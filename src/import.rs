@@ -0,0 +1,291 @@
+use std::path::{Path, PathBuf};
+
+use log::info;
+use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+
+use crate::{
+	errors::ImportError,
+	models::{Author, Course, Stage},
+	store::Store,
+	types::{CourseStatus, Difficulty},
+	utils::{save_course_version, validate_course},
+};
+
+/// Manifest file names looked up in a course directory, in order of preference
+const MANIFEST_FILES: [&str; 3] = ["course.toml", "course.yaml", "course.yml"];
+
+/// A course as authored in version control. Keys are snake_case; stage descriptions may be given
+/// inline or as a path to a file relative to the manifest.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CourseManifest {
+	slug: String,
+	name: String,
+	title: String,
+	author: Author,
+	tester_url: String,
+	tester_version: Option<String>,
+	language: Option<String>,
+	difficulty: Option<Difficulty>,
+	#[serde(default)]
+	tags: Vec<String>,
+	/// Whether the course is listed in the catalog. Left unchanged on existing courses if omitted;
+	/// new courses default to drafts.
+	status: Option<CourseStatus>,
+	#[serde(default)]
+	stages: Vec<StageManifest>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StageManifest {
+	slug: String,
+	title: String,
+	description: Option<String>,
+	description_file: Option<PathBuf>,
+	tester_stage_id: String,
+}
+
+/// What importing a manifest did, or would do on a dry run
+#[derive(Debug)]
+pub(crate) enum ImportOutcome {
+	Created { slug: String },
+	Updated { slug: String, version: String, changes: Vec<String> },
+	Unchanged { slug: String, version: String },
+}
+
+impl std::fmt::Display for ImportOutcome {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			ImportOutcome::Created { slug } => write!(f, "course `{}`: new course", slug),
+			ImportOutcome::Updated { slug, version, changes } => {
+				write!(f, "course `{}`: version {}", slug, version)?;
+				for change in changes {
+					write!(f, "\n  ~ {}", change)?;
+				}
+				Ok(())
+			},
+			ImportOutcome::Unchanged { slug, version } =>
+				write!(f, "course `{}`: up to date at version {}", slug, version),
+		}
+	}
+}
+
+/// Find and parse the course manifest in `dir`
+fn read_manifest(dir: &Path) -> Result<CourseManifest, ImportError> {
+	let path = MANIFEST_FILES
+		.iter()
+		.map(|name| dir.join(name))
+		.find(|path| path.is_file())
+		.ok_or_else(|| ImportError::MissingManifest(dir.to_path_buf()))?;
+
+	let contents = std::fs::read_to_string(&path)
+		.map_err(|e| ImportError::Io { path: path.clone(), source: e })?;
+
+	if path.extension().is_some_and(|extension| extension == "toml") {
+		toml::from_str(&contents).map_err(|e| ImportError::Toml { path, source: e })
+	} else {
+		serde_yaml::from_str(&contents).map_err(|e| ImportError::Yaml { path, source: e })
+	}
+}
+
+/// Build the course document described by a manifest, reading stage descriptions from their files
+fn build_course(dir: &Path, manifest: CourseManifest) -> Result<Course, ImportError> {
+	let stages = manifest
+		.stages
+		.into_iter()
+		.map(|stage| {
+			let description = match (stage.description, stage.description_file) {
+				(Some(description), None) => description,
+				(None, Some(file)) => {
+					let path = dir.join(file);
+					std::fs::read_to_string(&path)
+						.map_err(|e| ImportError::Io { path: path.clone(), source: e })?
+				},
+				_ =>
+					return Err(ImportError::Invalid(format!(
+						"stage `{}` must set exactly one of `description` and `description_file`",
+						stage.slug
+					))),
+			};
+
+			Ok(Stage {
+				slug: stage.slug,
+				title: stage.title,
+				description,
+				tester_stage_id: stage.tester_stage_id,
+			})
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	Ok(Course {
+		version: "1".to_string(),
+		id: ObjectId::new(),
+		slug: manifest.slug,
+		name: manifest.name,
+		title: manifest.title,
+		author: manifest.author,
+		tester_url: manifest.tester_url,
+		tester_version: manifest.tester_version,
+		language: manifest.language,
+		difficulty: manifest.difficulty,
+		tags: manifest.tags,
+		stages,
+		status: manifest.status.unwrap_or(CourseStatus::Draft),
+		relationships: vec![],
+	})
+}
+
+/// Describe the fields that differ between the stored course and the imported one
+fn diff_courses(stored: &Course, imported: &Course) -> Vec<String> {
+	let mut changes = vec![];
+	let mut field = |name: &str, before: String, after: String| {
+		if before != after {
+			changes.push(format!("{}: {} -> {}", name, before, after));
+		}
+	};
+
+	field("name", format!("{:?}", stored.name), format!("{:?}", imported.name));
+	field("title", format!("{:?}", stored.title), format!("{:?}", imported.title));
+	field(
+		"author",
+		format!("{:?} <{}>", stored.author.name, stored.author.url),
+		format!("{:?} <{}>", imported.author.name, imported.author.url),
+	);
+	field("tester_url", stored.tester_url.clone(), imported.tester_url.clone());
+	field(
+		"tester_version",
+		format!("{:?}", stored.tester_version),
+		format!("{:?}", imported.tester_version),
+	);
+	field("language", format!("{:?}", stored.language), format!("{:?}", imported.language));
+	field("difficulty", format!("{:?}", stored.difficulty), format!("{:?}", imported.difficulty));
+	field("tags", format!("{:?}", stored.tags), format!("{:?}", imported.tags));
+	field("status", stored.status.to_string(), imported.status.to_string());
+
+	for stage in &imported.stages {
+		match stored.stages.iter().find(|stored| stored.slug == stage.slug) {
+			None => changes.push(format!("stage `{}` added", stage.slug)),
+			Some(stored) if stored != stage =>
+				changes.push(format!("stage `{}` changed", stage.slug)),
+			_ => {},
+		}
+	}
+	for stage in &stored.stages {
+		if !imported.stages.iter().any(|imported| imported.slug == stage.slug) {
+			changes.push(format!("stage `{}` removed", stage.slug));
+		}
+	}
+	let order = |course: &Course| course.stages.iter().map(|s| s.slug.clone()).collect::<Vec<_>>();
+	let stored_order: Vec<_> = order(stored)
+		.into_iter()
+		.filter(|slug| imported.stages.iter().any(|s| &s.slug == slug))
+		.collect();
+	let imported_order: Vec<_> = order(imported)
+		.into_iter()
+		.filter(|slug| stored.stages.iter().any(|s| &s.slug == slug))
+		.collect();
+	if stored_order != imported_order {
+		changes.push("stages reordered".to_string());
+	}
+
+	changes
+}
+
+/// Import the course manifest in `dir`, creating the course or saving a new version of it if it
+/// differs from the stored one. With `dry_run`, only reports what would change.
+pub(crate) async fn import_course(
+	store: &dyn Store,
+	dir: &Path,
+	dry_run: bool,
+) -> Result<ImportOutcome, ImportError> {
+	let manifest = read_manifest(dir)?;
+	let keep_status = manifest.status.is_none();
+	let mut course = build_course(dir, manifest)?;
+	validate_course(&course)?;
+
+	let Some(stored) = store.get_course_by_slug(&course.slug).await? else {
+		let slug = course.slug.clone();
+		if !dry_run {
+			store.create_course(course).await?;
+			info!("Imported new course `{}`", slug);
+		}
+		return Ok(ImportOutcome::Created { slug });
+	};
+
+	if keep_status {
+		course.status = stored.status;
+	}
+	// Manifests do not describe relationships, so those of the stored course are kept
+	course.relationships = stored.relationships.clone();
+
+	let changes = diff_courses(&stored, &course);
+	if changes.is_empty() {
+		return Ok(ImportOutcome::Unchanged { slug: stored.slug, version: stored.version });
+	}
+
+	let version = if dry_run {
+		format!(
+			"{} -> {} (dry run)",
+			stored.version,
+			stored.version.parse::<u64>().unwrap_or(0) + 1
+		)
+	} else {
+		let saved = save_course_version(store, &stored, course).await?;
+		info!("Imported course `{}` as version {}", saved.slug, saved.version);
+		format!("{} -> {}", stored.version, saved.version)
+	};
+
+	Ok(ImportOutcome::Updated { slug: stored.slug, version, changes })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::{models::Relationship, store::InMemoryStore, types::DocumentType};
+
+	const MANIFEST: &str = r#"
+slug = "rust-state-machine"
+name = "Rust State Machine"
+title = "Build a blockchain state machine in Rust"
+author = { name = "Shawn Tabrizi", url = "https://github.com/shawntabrizi" }
+tester_url = "https://tester.example.com"
+
+[[stages]]
+slug = "setup"
+title = "Setup"
+description = "Create the project"
+tester_stage_id = "t1"
+"#;
+
+	/// Write `manifest` as the course manifest of a fresh directory
+	fn course_dir(manifest: &str) -> PathBuf {
+		let dir = std::env::temp_dir().join(format!("dcs-import-{}", uuid::Uuid::new_v4()));
+		std::fs::create_dir_all(&dir).unwrap();
+		std::fs::write(dir.join("course.toml"), manifest).unwrap();
+		dir
+	}
+
+	#[actix_web::test]
+	async fn reimporting_a_course_keeps_its_relationships() {
+		let store = InMemoryStore::new();
+		let dir = course_dir(MANIFEST);
+		import_course(&store, &dir, false).await.unwrap();
+
+		let course = store.get_course_by_slug("rust-state-machine").await.unwrap().unwrap();
+		let relationships =
+			vec![Relationship { id: ObjectId::new(), r#type: DocumentType::Repository }];
+		store.set_course_relationships(&course.id, &relationships).await.unwrap();
+
+		std::fs::write(dir.join("course.toml"), MANIFEST.replace("Create the", "Start the"))
+			.unwrap();
+		let outcome = import_course(&store, &dir, false).await.unwrap();
+		assert!(matches!(outcome, ImportOutcome::Updated { .. }), "{}", outcome);
+
+		let course = store.get_course_by_slug("rust-state-machine").await.unwrap().unwrap();
+		assert_eq!(course.version, "2");
+		assert_eq!(course.relationships, relationships);
+		std::fs::remove_dir_all(dir).unwrap();
+	}
+}
@@ -0,0 +1,342 @@
+use std::collections::HashSet;
+
+use log::{info, warn};
+use mongodb::bson::oid::ObjectId;
+use serde::Serialize;
+use strum_macros::Display;
+
+use crate::{
+	constants::{COURSE_COLLECTION, REPO_COLLECTION, USER_COLLECTION},
+	errors::DbError,
+	git_host::GitHost,
+	models::Relationship,
+	store::Store,
+	types::{CourseFilter, DocumentType},
+};
+
+/// What is wrong with a reference between documents
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub(crate) enum IntegrityProblem {
+	/// The referenced document does not exist in any collection
+	Dangling,
+	/// The referenced document exists, but the reference names the wrong document type
+	Mistyped,
+	/// A repository's owner does not list it among their repositories
+	MissingBacklink,
+	/// The relationships are stored in a format written by older versions
+	LegacyFormat,
+	/// A repository on the git host has no repository document
+	Untracked,
+}
+
+/// A single broken reference
+#[derive(Debug, Serialize)]
+pub(crate) struct IntegrityIssue {
+	/// The collection of the document holding the reference
+	pub collection: &'static str,
+	/// The id of the document holding the reference, or the name of a repository
+	pub document: String,
+	/// The relationship the reference is stored under
+	pub relationship: &'static str,
+	pub problem: IntegrityProblem,
+	/// The id the reference points to
+	pub target: Option<ObjectId>,
+	pub repaired: bool,
+}
+
+impl std::fmt::Display for IntegrityIssue {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(
+			f,
+			"{} `{}` {}: {}",
+			self.collection, self.document, self.relationship, self.problem
+		)?;
+		if let Some(target) = self.target {
+			write!(f, " reference to {}", target)?;
+		}
+		if self.repaired {
+			write!(f, " (repaired)")?;
+		}
+		Ok(())
+	}
+}
+
+/// Outcome of an integrity check
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct IntegrityReport {
+	/// Documents checked, per collection
+	pub repositories: usize,
+	pub users: usize,
+	pub courses: usize,
+	pub issues: Vec<IntegrityIssue>,
+}
+
+impl IntegrityReport {
+	/// Issues that were not repaired, either because repairs were not requested or because they
+	/// need a human to decide, such as a repository whose owner no longer exists
+	pub fn unrepaired(&self) -> usize {
+		self.issues.iter().filter(|issue| !issue.repaired).count()
+	}
+}
+
+/// What a reference resolves to
+enum Verdict {
+	Valid,
+	/// The referenced document exists with this type
+	Mistyped(DocumentType),
+	Dangling,
+}
+
+/// The ids of every document, per collection
+struct Index {
+	repositories: HashSet<ObjectId>,
+	users: HashSet<ObjectId>,
+	courses: HashSet<ObjectId>,
+}
+
+impl Index {
+	fn contains(&self, document_type: DocumentType, id: &ObjectId) -> bool {
+		match document_type {
+			DocumentType::Repository => self.repositories.contains(id),
+			DocumentType::User => self.users.contains(id),
+			DocumentType::Course => self.courses.contains(id),
+		}
+	}
+
+	/// Resolve a reference that must point to a document of type `expected`, or to a document of
+	/// the type it names if any type is allowed
+	fn check(&self, relationship: &Relationship, expected: Option<DocumentType>) -> Verdict {
+		if let Some(expected) = expected {
+			return match self.contains(expected, &relationship.id) {
+				true if relationship.r#type == expected => Verdict::Valid,
+				true => Verdict::Mistyped(expected),
+				false => Verdict::Dangling,
+			};
+		}
+
+		if self.contains(relationship.r#type, &relationship.id) {
+			return Verdict::Valid;
+		}
+		[DocumentType::Repository, DocumentType::User, DocumentType::Course]
+			.into_iter()
+			.find(|document_type| self.contains(*document_type, &relationship.id))
+			.map_or(Verdict::Dangling, Verdict::Mistyped)
+	}
+}
+
+/// Check a list of references, fixing the type of mistyped ones and dropping dangling ones when
+/// repairing. Returns the repaired list.
+fn check_list(
+	index: &Index,
+	name: &'static str,
+	relationships: &[Relationship],
+	expected: Option<DocumentType>,
+	repair: bool,
+	mut report: impl FnMut(&'static str, IntegrityProblem, ObjectId),
+) -> Vec<Relationship> {
+	let mut checked: Vec<Relationship> = vec![];
+	for relationship in relationships {
+		let mut relationship = relationship.clone();
+		match index.check(&relationship, expected) {
+			Verdict::Valid => {},
+			Verdict::Mistyped(document_type) => {
+				report(name, IntegrityProblem::Mistyped, relationship.id);
+				relationship.r#type = document_type;
+			},
+			Verdict::Dangling => {
+				report(name, IntegrityProblem::Dangling, relationship.id);
+				if repair {
+					continue;
+				}
+			},
+		}
+		if !checked.iter().any(|seen| seen.id == relationship.id) {
+			checked.push(relationship);
+		}
+	}
+	checked
+}
+
+/// Check every relationship between repositories, users and courses, reporting references to
+/// documents that do not exist or that have the wrong type. With `repair`, mistyped references are
+/// corrected, dangling ones removed, missing repository backlinks added and legacy user documents
+/// rewritten. Dangling owners and courses of repositories, and repositories on the git host without
+/// a document, are only reported.
+///
+/// Loads every repository, user and course, so it is meant to be run occasionally by an admin.
+pub(crate) async fn check_integrity(
+	store: &dyn Store,
+	git_host: &dyn GitHost,
+	repair: bool,
+) -> Result<IntegrityReport, DbError> {
+	let repositories = store.list_repositories().await?;
+	let users = store.list_users().await?;
+	let filter = CourseFilter { include_drafts: true, ..Default::default() };
+	let courses = store.list_courses(&filter, 0, i64::MAX as u64).await?;
+	let legacy_users: HashSet<ObjectId> =
+		store.list_users_with_legacy_relationships().await?.into_iter().collect();
+
+	let index = Index {
+		repositories: repositories.iter().filter_map(|repository| repository.id).collect(),
+		users: users.iter().map(|(id, _)| *id).collect(),
+		courses: courses.iter().map(|course| course.id).collect(),
+	};
+
+	let mut report = IntegrityReport {
+		repositories: repositories.len(),
+		users: users.len(),
+		courses: courses.len(),
+		issues: vec![],
+	};
+	let mut issue = |collection, document: String, relationship, problem, target, repaired| {
+		report.issues.push(IntegrityIssue {
+			collection,
+			document,
+			relationship,
+			problem,
+			target,
+			repaired,
+		})
+	};
+
+	// The owner of every repository, to check the owner lists it
+	let mut owned = vec![];
+
+	for repository in &repositories {
+		let mut relationships = repository.relationships.clone();
+		let mut changed = false;
+
+		for (name, slot, expected) in [
+			("user", &mut relationships.user, DocumentType::User),
+			("course", &mut relationships.course, DocumentType::Course),
+		] {
+			let Some(relationship) = slot else {
+				continue;
+			};
+			match index.check(relationship, Some(expected)) {
+				Verdict::Valid => {},
+				Verdict::Mistyped(document_type) => {
+					relationship.r#type = document_type;
+					changed = true;
+					let target = Some(relationship.id);
+					issue(
+						REPO_COLLECTION,
+						repository.repo_name.clone(),
+						name,
+						IntegrityProblem::Mistyped,
+						target,
+						repair,
+					);
+				},
+				Verdict::Dangling => issue(
+					REPO_COLLECTION,
+					repository.repo_name.clone(),
+					name,
+					IntegrityProblem::Dangling,
+					Some(relationship.id),
+					false,
+				),
+			}
+		}
+
+		// Archived repositories are removed from their owner's repositories
+		if let (Some(id), Some(owner), None) =
+			(repository.id, &relationships.user, repository.archived_at)
+		{
+			if index.users.contains(&owner.id) {
+				owned.push((owner.id, id));
+			}
+		}
+		if changed && repair {
+			store
+				.set_repository_relationships(&repository.repo_name, &relationships)
+				.await?;
+		}
+	}
+
+	for (id, user) in &users {
+		let mut problems = vec![];
+		let mut found = |name, problem, target| problems.push((name, problem, Some(target)));
+
+		let mut repositories = check_list(
+			&index,
+			"repositories",
+			&user.repositories,
+			Some(DocumentType::Repository),
+			repair,
+			&mut found,
+		);
+		let relationships =
+			check_list(&index, "relationships", &user.relationships, None, repair, &mut found);
+
+		for (_, repo_id) in owned.iter().filter(|(owner, _)| owner == id) {
+			if !repositories.iter().any(|seen| &seen.id == repo_id) {
+				problems.push(("repositories", IntegrityProblem::MissingBacklink, Some(*repo_id)));
+				repositories.push(Relationship { id: *repo_id, r#type: DocumentType::Repository });
+			}
+		}
+		if legacy_users.contains(id) {
+			problems.push(("relationships", IntegrityProblem::LegacyFormat, None));
+		}
+
+		if problems.is_empty() {
+			continue;
+		}
+		for (name, problem, target) in problems {
+			issue(USER_COLLECTION, id.to_hex(), name, problem, target, repair);
+		}
+		if repair {
+			store.set_user_relationships(id, &repositories, &relationships).await?;
+		}
+	}
+
+	for course in &courses {
+		let mut problems = vec![];
+		let relationships = check_list(
+			&index,
+			"relationships",
+			&course.relationships,
+			None,
+			repair,
+			|name, problem, target| problems.push((name, problem, target)),
+		);
+
+		if problems.is_empty() {
+			continue;
+		}
+		for (name, problem, target) in problems {
+			issue(COURSE_COLLECTION, course.id.to_hex(), name, problem, Some(target), repair);
+		}
+		if repair {
+			store.set_course_relationships(&course.id, &relationships).await?;
+		}
+	}
+
+	// Deleting an untracked repository could lose a learner's work if it was the document that got
+	// lost, so they are left for an admin to look into. Those still being created are skipped.
+	match git_host.list_repos().await {
+		Ok(hosted) => {
+			let pending = store.list_pending_repositories(chrono::Utc::now()).await?;
+			for repo_name in hosted {
+				if repositories.iter().any(|repository| repository.repo_name == repo_name) ||
+					pending.iter().any(|pending| pending.repo_name == repo_name)
+				{
+					continue;
+				}
+				issue(REPO_COLLECTION, repo_name, "git", IntegrityProblem::Untracked, None, false);
+			}
+		},
+		Err(e) => warn!("Failed to list repositories on the git host, not checking them: {}", e),
+	}
+
+	if !report.issues.is_empty() {
+		info!(
+			"Integrity check found {} issues, {} left unrepaired",
+			report.issues.len(),
+			report.unrepaired()
+		);
+	}
+	Ok(report)
+}
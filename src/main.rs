@@ -1,116 +1,886 @@
+mod archive;
+mod auth;
+mod config;
 mod constants;
 mod errors;
+mod git_host;
 mod helpers;
+mod idempotency;
+mod import;
+mod integrity;
+mod migrations;
 mod models;
+mod naming;
+mod notifier;
+mod queue;
+mod reconcile;
+mod reminders;
+mod stats;
+mod store;
+#[cfg(test)]
+mod tests;
 mod types;
 mod utils;
 
-use actix_web::{get, post, put, web, App, HttpServer, Responder};
+use actix_web::{
+	delete, get, middleware::from_fn, post, put, web, App, HttpRequest, HttpResponse, HttpServer,
+};
+use auth::Identity;
+use clap::{Parser, Subcommand};
+use config::Config;
 use dotenv::dotenv;
+use errors::{ApiError, MigrationError};
+use git_host::{GitHost, GitHostBackend, HttpGitHost, LocalGitHost, RecordingGitHost};
 use helpers::{
-	fetch_course_success_response, get_repository_success_response, handle_db_error,
-	handle_repo_creation_error, repository_creation_success_response,
+	course_update_success_response, fetch_course_success_response, get_repository_success_response,
+	get_submission_success_response, get_user_success_response, index_report_response,
+	integrity_report_response, job_claimed_response, job_heartbeat_response,
+	list_course_versions_success_response, list_courses_success_response,
+	list_submissions_success_response, list_user_repositories_success_response,
+	repository_archived_response, repository_creation_success_response, repository_reset_response,
+	repository_restored_response, repository_stats_success_response,
 	repository_update_success_response, submission_creation_success_response,
+	submission_report_recorded_response, test_result_recorded_response,
+	tester_migration_success_response, user_update_success_response,
 };
-use log::info;
+use idempotency::{mark_replayed, run_idempotent, IdempotencyKey};
+use log::{error, info, warn};
+use migrations::Migrator;
 use mongodb::Client;
+use naming::RepoNamer;
+use notifier::{LogNotifier, Notifier, NotifierBackend, SmtpNotifier, WebhookNotifier};
+use queue::{InMemoryQueue, QueueBackend, RedisQueue, SubmissionQueue};
+use std::{path::PathBuf, sync::Arc, time::Duration};
+use store::{InMemoryStore, MongoStore, Store, StoreBackend};
 use types::*;
 use utils::{
-	do_create_repo, do_create_submission, fetch_course, get_repo_from_db, update_repository,
+	archive_repository, check_relationship_integrity, claim_submission_job, create_course,
+	do_create_repo, do_create_submission, fetch_course, fetch_course_by_slug,
+	get_authorized_submission, get_index_report, get_repository, get_repository_stats, get_user,
+	heartbeat_submission_job, list_course_versions, list_courses, list_repository_submissions,
+	list_user_repositories, migrate_repository_tester, record_submission_report,
+	record_test_result, reset_repository, restore_repository, set_course_status, update_course,
+	update_repository, update_user,
 };
 
 #[get("/course/{course_id}")]
-async fn get_course_v0(data: web::Data<AppState>, course_id: web::Path<String>) -> impl Responder {
-	match fetch_course(&data.client, &course_id).await {
-		Ok(course) => fetch_course_success_response(course),
-		Err(e) => handle_db_error(e),
-	}
+async fn get_course_v0(
+	data: web::Data<AppState>,
+	identity: Option<Identity>,
+	course_id: web::Path<String>,
+	include: web::Query<IncludeQuery>,
+) -> Result<HttpResponse, ApiError> {
+	let course = fetch_course(data.store.as_ref(), identity.as_ref(), &course_id, &include).await?;
+	Ok(fetch_course_success_response(course))
+}
+
+#[get("/course/by-slug/{slug}")]
+async fn get_course_by_slug_v0(
+	data: web::Data<AppState>,
+	identity: Option<Identity>,
+	slug: web::Path<String>,
+	include: web::Query<IncludeQuery>,
+) -> Result<HttpResponse, ApiError> {
+	let course =
+		fetch_course_by_slug(data.store.as_ref(), identity.as_ref(), &slug, &include).await?;
+	Ok(fetch_course_success_response(course))
+}
+
+/// List the course catalog, optionally searching and filtering it
+#[get("/courses")]
+async fn list_courses_v0(
+	data: web::Data<AppState>,
+	identity: Option<Identity>,
+	query: web::Query<CourseQuery>,
+) -> Result<HttpResponse, ApiError> {
+	let page = list_courses(data.store.as_ref(), identity.as_ref(), &query).await?;
+	Ok(list_courses_success_response(page))
 }
 
-/// Create a repository on the git server
+/// Create a course as a draft. Admin only.
+#[post("/course")]
+async fn create_course_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+	json: web::Json<CreateCourseRequest>,
+) -> Result<HttpResponse, ApiError> {
+	let course = create_course(data.store.as_ref(), &identity, &json).await?;
+	Ok(course_update_success_response(course))
+}
+
+/// Update a course, archiving its current version. Admin only.
+#[put("/course/{course_id}")]
+async fn update_course_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+	course_id: web::Path<String>,
+	json: web::Json<UpdateCourseRequest>,
+) -> Result<HttpResponse, ApiError> {
+	let course = update_course(data.store.as_ref(), &identity, &course_id, &json).await?;
+	Ok(course_update_success_response(course))
+}
+
+/// List a course in the catalog. Admin only.
+#[post("/course/{course_id}/publish")]
+async fn publish_course_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+	course_id: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+	let course =
+		set_course_status(data.store.as_ref(), &identity, &course_id, CourseStatus::Published)
+			.await?;
+	Ok(course_update_success_response(course))
+}
+
+/// Remove a course from the catalog. Admin only.
+#[post("/course/{course_id}/unpublish")]
+async fn unpublish_course_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+	course_id: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+	let course =
+		set_course_status(data.store.as_ref(), &identity, &course_id, CourseStatus::Draft).await?;
+	Ok(course_update_success_response(course))
+}
+
+/// List the prior versions of a course. Admin only.
+#[get("/course/{course_id}/versions")]
+async fn list_course_versions_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+	course_id: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+	let versions = list_course_versions(data.store.as_ref(), &identity, &course_id).await?;
+	Ok(list_course_versions_success_response(versions))
+}
+
+/// Create a repository on the git server. Retries carrying the same `Idempotency-Key` return the
+/// repository created by the first request.
 #[post("/repository")]
 async fn create_repository_v0(
 	data: web::Data<AppState>,
+	identity: Identity,
+	key: IdempotencyKey,
 	json: web::Json<CreateRepoRequest>,
-) -> impl Responder {
-	match do_create_repo(&data.client, &json).await {
-		Ok(repo_name) => repository_creation_success_response(repo_name, &json.repo_template),
-		Err(e) => handle_repo_creation_error(e),
-	}
+) -> Result<HttpResponse, ApiError> {
+	let (repo_name, replayed) = run_idempotent(
+		data.store.as_ref(),
+		&identity,
+		&key,
+		"create_repository",
+		&json.0,
+		Duration::from_secs(data.config.idempotency.ttl_secs),
+		|| {
+			do_create_repo(
+				data.store.as_ref(),
+				data.git_host.as_ref(),
+				data.repo_namer.as_ref(),
+				&identity,
+				&json,
+			)
+		},
+	)
+	.await?;
+	Ok(mark_replayed(
+		repository_creation_success_response(repo_name, &json.repo_template),
+		replayed,
+	))
 }
 
 #[get("/repository/{repo_name}")]
 async fn get_repository_v0(
 	data: web::Data<AppState>,
+	identity: Identity,
 	repo_name: web::Path<String>,
-) -> impl Responder {
-	match get_repo_from_db(&data.client, repo_name.as_str()).await {
-		Ok(repository) => get_repository_success_response(repository),
-		Err(e) => handle_db_error(e),
-	}
+	include: web::Query<IncludeQuery>,
+) -> Result<HttpResponse, ApiError> {
+	let repository =
+		get_repository(data.store.as_ref(), &identity, repo_name.as_str(), &include).await?;
+	Ok(get_repository_success_response(repository))
+}
+
+/// Practice streaks and activity statistics for a repository
+#[get("/repository/{repo_name}/stats")]
+async fn get_repository_stats_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+	repo_name: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+	let stats = get_repository_stats(data.store.as_ref(), &identity, repo_name.as_str()).await?;
+	Ok(repository_stats_success_response(stats))
 }
 
 /// Update a repository
 #[put("/repository/{repo_name}")]
 async fn update_repository_v0(
 	data: web::Data<AppState>,
+	identity: Identity,
 	repo_name: web::Path<String>,
 	json: web::Json<UpdateRepoRequest>,
-) -> impl Responder {
-	match update_repository(&data.client, repo_name.as_str(), &json).await {
-		Ok(updated_repo) => repository_update_success_response(updated_repo),
-		Err(e) => handle_db_error(e),
-	}
+) -> Result<HttpResponse, ApiError> {
+	let updated_repo =
+		update_repository(data.store.as_ref(), &identity, repo_name.as_str(), &json).await?;
+	Ok(repository_update_success_response(updated_repo))
+}
+
+/// Move a repository onto the current version of its course and that version's tester. Admin only.
+#[post("/repository/{repo_name}/migrate-tester")]
+async fn migrate_repository_tester_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+	repo_name: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+	let repository =
+		migrate_repository_tester(data.store.as_ref(), &identity, repo_name.as_str()).await?;
+	Ok(tester_migration_success_response(repository))
+}
+
+/// Restart the course on a repository the caller owns, optionally reseeding it from its template
+#[post("/repository/{repo_name}/reset")]
+async fn reset_repository_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+	repo_name: web::Path<String>,
+	json: web::Json<ResetRepoRequest>,
+) -> Result<HttpResponse, ApiError> {
+	let repository =
+		reset_repository(data.store.as_ref(), data.git_host.as_ref(), &identity, &repo_name, &json)
+			.await?;
+	Ok(repository_reset_response(repository))
+}
+
+/// Archive a repository the caller owns, to be purged once the grace period has passed
+#[delete("/repository/{repo_name}")]
+async fn delete_repository_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+	repo_name: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+	archive_repository(data.store.as_ref(), data.git_host.as_ref(), &identity, &repo_name).await?;
+	Ok(repository_archived_response())
+}
+
+/// Restore an archived repository that has not been purged yet. Admin only.
+#[post("/repository/{repo_name}/restore")]
+async fn restore_repository_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+	repo_name: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+	let repository =
+		restore_repository(data.store.as_ref(), data.git_host.as_ref(), &identity, &repo_name)
+			.await?;
+	Ok(repository_restored_response(repository))
+}
+
+/// Report dangling or mistyped references between documents. Admin only.
+#[get("/admin/integrity")]
+async fn check_integrity_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+) -> Result<HttpResponse, ApiError> {
+	let report =
+		check_relationship_integrity(data.store.as_ref(), data.git_host.as_ref(), &identity, false)
+			.await?;
+	Ok(integrity_report_response(report))
+}
+
+/// Repair the references between documents that can be repaired automatically. Admin only.
+#[post("/admin/integrity/repair")]
+async fn repair_integrity_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+) -> Result<HttpResponse, ApiError> {
+	let report =
+		check_relationship_integrity(data.store.as_ref(), data.git_host.as_ref(), &identity, true)
+			.await?;
+	Ok(integrity_report_response(report))
+}
+
+/// Compare the indexes in the database with those the backend declares. Admin only.
+#[get("/admin/indexes")]
+async fn get_index_report_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+) -> Result<HttpResponse, ApiError> {
+	let report = get_index_report(data.store.as_ref(), &identity).await?;
+	Ok(index_report_response(report))
 }
 
 #[post("/submission")]
 async fn create_submission_v0(
 	data: web::Data<AppState>,
+	identity: Identity,
+	key: IdempotencyKey,
 	json: web::Json<CreateSubmissionRequest>,
-) -> impl Responder {
-	match do_create_submission(&data.client, &data.redis_uri, &data.ws_url, &json).await {
-		Ok(submission_response) => submission_creation_success_response(submission_response),
-		Err(e) => handle_db_error(e),
-	}
+) -> Result<HttpResponse, ApiError> {
+	let (submission_response, replayed) = run_idempotent(
+		data.store.as_ref(),
+		&identity,
+		&key,
+		"create_submission",
+		&json.0,
+		Duration::from_secs(data.config.idempotency.ttl_secs),
+		|| {
+			do_create_submission(
+				data.store.as_ref(),
+				data.git_host.as_ref(),
+				data.queue.as_ref(),
+				&identity,
+				&data.config,
+				&json,
+			)
+		},
+	)
+	.await?;
+	Ok(mark_replayed(submission_creation_success_response(submission_response), replayed))
+}
+
+#[get("/submission/{logstream_id}")]
+async fn get_submission_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+	logstream_id: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+	let submission =
+		get_authorized_submission(data.store.as_ref(), &identity, logstream_id.as_str()).await?;
+	Ok(get_submission_success_response(submission))
+}
+
+/// List the submissions of a repository, newest first
+#[get("/repository/{repo_name}/submissions")]
+async fn list_submissions_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+	repo_name: web::Path<String>,
+	query: web::Query<PaginationQuery>,
+) -> Result<HttpResponse, ApiError> {
+	let page =
+		list_repository_submissions(data.store.as_ref(), &identity, repo_name.as_str(), &query)
+			.await?;
+	Ok(list_submissions_success_response(page))
+}
+
+#[get("/user/{user_id}")]
+async fn get_user_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+	user_id: web::Path<String>,
+	include: web::Query<IncludeQuery>,
+) -> Result<HttpResponse, ApiError> {
+	let user = get_user(data.store.as_ref(), &identity, &user_id, &include).await?;
+	Ok(get_user_success_response(user))
+}
+
+/// Update a user's profile
+#[put("/user/{user_id}")]
+async fn update_user_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+	user_id: web::Path<String>,
+	json: web::Json<UpdateUserRequest>,
+) -> Result<HttpResponse, ApiError> {
+	let user = update_user(data.store.as_ref(), &identity, &user_id, &json).await?;
+	Ok(user_update_success_response(user))
+}
+
+/// List a user's repositories with their course and progress
+#[get("/user/{user_id}/repositories")]
+async fn list_user_repositories_v0(
+	data: web::Data<AppState>,
+	identity: Identity,
+	user_id: web::Path<String>,
+) -> Result<HttpResponse, ApiError> {
+	let repositories = list_user_repositories(data.store.as_ref(), &identity, &user_id).await?;
+	Ok(list_user_repositories_success_response(repositories))
+}
+
+/// Record the progress of a submission. Only accepts requests signed by a configured tester, see
+/// [`auth::verify_tester_signature`].
+#[post("/tester/submissions/{logstream_id}")]
+async fn record_submission_report_v0(
+	data: web::Data<AppState>,
+	req: HttpRequest,
+	logstream_id: web::Path<String>,
+	body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+	let nonce = auth::verify_tester_signature(&data.config.tester, &req, &body)?;
+	let json: SubmissionReportRequest = serde_json::from_slice(&body)
+		.map_err(|e| ApiError::BadRequest(format!("Invalid submission report: {}", e)))?;
+
+	let submission = record_submission_report(
+		data.store.as_ref(),
+		data.queue.as_ref(),
+		nonce,
+		logstream_id.as_str(),
+		&json,
+	)
+	.await?;
+	Ok(submission_report_recorded_response(submission))
+}
+
+/// Record the outcome of a test run. Only accepts requests signed by a configured tester, see
+/// [`auth::verify_tester_signature`].
+#[post("/tester/results")]
+async fn record_tester_result_v0(
+	data: web::Data<AppState>,
+	req: HttpRequest,
+	body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+	let nonce = auth::verify_tester_signature(&data.config.tester, &req, &body)?;
+	let json: TesterResultRequest = serde_json::from_slice(&body)
+		.map_err(|e| ApiError::BadRequest(format!("Invalid test result: {}", e)))?;
+
+	record_test_result(data.store.as_ref(), data.queue.as_ref(), nonce, &json).await?;
+	Ok(test_result_recorded_response())
+}
+
+/// Hand the next queued submission to a tester worker, or respond with no content if the queue is
+/// empty. Only accepts requests signed by a configured tester, see
+/// [`auth::verify_tester_signature`].
+#[post("/tester/jobs/claim")]
+async fn claim_submission_job_v0(
+	data: web::Data<AppState>,
+	req: HttpRequest,
+	body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+	let nonce = auth::verify_tester_signature(&data.config.tester, &req, &body)?;
+	let json: WorkerRequest = serde_json::from_slice(&body)
+		.map_err(|e| ApiError::BadRequest(format!("Invalid claim request: {}", e)))?;
+
+	let job = claim_submission_job(
+		data.store.as_ref(),
+		data.queue.as_ref(),
+		nonce,
+		&json,
+		Duration::from_secs(data.config.queue.visibility_timeout_secs),
+	)
+	.await?;
+	Ok(job_claimed_response(job))
+}
+
+/// Extend a tester worker's claim on the job of a submission. Only accepts requests signed by a
+/// configured tester, see [`auth::verify_tester_signature`].
+#[post("/tester/jobs/{logstream_id}/heartbeat")]
+async fn heartbeat_submission_job_v0(
+	data: web::Data<AppState>,
+	req: HttpRequest,
+	logstream_id: web::Path<String>,
+	body: web::Bytes,
+) -> Result<HttpResponse, ApiError> {
+	let nonce = auth::verify_tester_signature(&data.config.tester, &req, &body)?;
+	let json: WorkerRequest = serde_json::from_slice(&body)
+		.map_err(|e| ApiError::BadRequest(format!("Invalid heartbeat: {}", e)))?;
+
+	heartbeat_submission_job(
+		data.store.as_ref(),
+		data.queue.as_ref(),
+		nonce,
+		logstream_id.as_str(),
+		&json,
+	)
+	.await?;
+	Ok(job_heartbeat_response())
 }
 
 pub struct AppState {
-	client: Client,
-	redis_uri: String,
-	ws_url: String,
+	config: Arc<Config>,
+	store: Arc<dyn Store>,
+	git_host: Arc<dyn GitHost>,
+	repo_namer: Arc<dyn RepoNamer>,
+	queue: Arc<dyn SubmissionQueue>,
+}
+
+/// Connect to the MongoDB deployment in the config
+async fn connect_mongo(config: &Config) -> Client {
+	let uri = config.database.uri.as_deref().expect("checked by Config::validate");
+	Client::with_uri_str(uri).await.expect("Failed to connect to MongoDB")
+}
+
+/// Initialize the storage backend selected in the config
+async fn init_store(config: &Config) -> Arc<dyn Store> {
+	match config.database.backend {
+		StoreBackend::Mongo => {
+			let client = connect_mongo(config).await;
+			info!("Using MongoDB database `{}`", config.db_name());
+			let store = MongoStore::new(&client, config.db_name());
+			match store.ensure_indexes().await {
+				Ok(report) => {
+					for index in &report.missing {
+						warn!("Missing index {}", index);
+					}
+					for index in &report.extra {
+						warn!("Undeclared index {}", index);
+					}
+				},
+				Err(e) => error!("Failed to ensure indexes: {}", e),
+			}
+			Arc::new(store)
+		},
+		StoreBackend::Memory => {
+			info!("Using in-memory store, data will not be persisted");
+			match &config.database.seed_file {
+				Some(path) => Arc::new(InMemoryStore::from_seed_file(path).unwrap_or_else(|e| {
+					panic!("Failed to load seed file {}: {}", path.display(), e)
+				})),
+				None => Arc::new(InMemoryStore::new()),
+			}
+		},
+	}
+}
+
+/// Initialize the git hosting backend selected in the config
+fn init_git_host(config: &Config) -> Arc<dyn GitHost> {
+	let git = &config.git;
+	match git.backend {
+		GitHostBackend::Http =>
+			Arc::new(HttpGitHost::new(git.server_url.clone(), git.bearer_token.clone())),
+		GitHostBackend::Local => {
+			let repos_dir = git.repos_dir.clone().expect("checked by Config::validate");
+			let templates_dir = git.templates_dir.clone().expect("checked by Config::validate");
+			Arc::new(LocalGitHost::new(repos_dir, templates_dir))
+		},
+		GitHostBackend::Fake => {
+			info!("Using fake git host, repositories will not be created");
+			Arc::new(RecordingGitHost::new())
+		},
+	}
+}
+
+/// Initialize the submission queue backend selected in the config
+async fn init_queue(config: &Config) -> Arc<dyn SubmissionQueue> {
+	let queue = &config.queue;
+	let visibility_timeout = Duration::from_secs(queue.visibility_timeout_secs);
+	match queue.backend {
+		QueueBackend::Redis => {
+			info!("Using Redis stream `{}` for the submission queue", queue.stream);
+			let redis_queue = RedisQueue::connect(
+				config.redis_uri(),
+				&queue.stream,
+				visibility_timeout,
+				queue.max_deliveries,
+			)
+			.await
+			.unwrap_or_else(|e| panic!("Failed to connect to Redis: {}", e));
+			Arc::new(redis_queue)
+		},
+		QueueBackend::Memory => {
+			info!("Using in-memory submission queue, jobs will not be persisted");
+			Arc::new(InMemoryQueue::new(visibility_timeout, queue.max_deliveries))
+		},
+	}
+}
+
+/// Initialize the notifier used for practice reminders selected in the config
+fn init_notifier(config: &Config) -> Arc<dyn Notifier> {
+	let reminders = &config.reminders;
+	match reminders.notifier {
+		NotifierBackend::Log => {
+			info!("Using log notifier, reminders will only be logged");
+			Arc::new(LogNotifier)
+		},
+		NotifierBackend::Smtp => {
+			let smtp = &reminders.smtp;
+			let credentials = smtp.username.clone().zip(smtp.password.clone());
+			let notifier = SmtpNotifier::new(
+				smtp.host.as_deref().expect("checked by Config::validate"),
+				smtp.port,
+				credentials,
+				smtp.from.as_deref().expect("checked by Config::validate"),
+			)
+			.unwrap_or_else(|e| panic!("Failed to configure SMTP notifier: {}", e));
+			Arc::new(notifier)
+		},
+		NotifierBackend::Webhook => {
+			let webhook = &reminders.webhook;
+			Arc::new(WebhookNotifier::new(
+				webhook.url.clone().expect("checked by Config::validate"),
+				webhook.bearer_token.clone(),
+			))
+		},
+	}
+}
+
+/// Register the API routes, and the error handlers of the extractors they use, on an app that
+/// already holds an [`AppState`]
+fn configure_app(cfg: &mut web::ServiceConfig) {
+	cfg.app_data(
+		web::JsonConfig::default()
+			.error_handler(|e, _| ApiError::BadRequest(format!("Invalid JSON body: {}", e)).into()),
+	)
+	.app_data(
+		web::QueryConfig::default().error_handler(|e, _| {
+			ApiError::BadRequest(format!("Invalid query string: {}", e)).into()
+		}),
+	)
+	.app_data(
+		web::PathConfig::default()
+			.error_handler(|e, _| ApiError::NotFound(format!("Invalid path: {}", e)).into()),
+	)
+	.service(
+		web::scope("/api/v0")
+			.wrap(from_fn(auth::authenticate))
+			.service(create_repository_v0)
+			.service(create_submission_v0)
+			.service(get_submission_v0)
+			.service(list_submissions_v0)
+			.service(record_submission_report_v0)
+			.service(get_course_v0)
+			.service(get_course_by_slug_v0)
+			.service(list_courses_v0)
+			.service(create_course_v0)
+			.service(update_course_v0)
+			.service(publish_course_v0)
+			.service(unpublish_course_v0)
+			.service(list_course_versions_v0)
+			.service(get_repository_v0)
+			.service(get_repository_stats_v0)
+			.service(record_tester_result_v0)
+			.service(claim_submission_job_v0)
+			.service(heartbeat_submission_job_v0)
+			.service(update_repository_v0)
+			.service(migrate_repository_tester_v0)
+			.service(reset_repository_v0)
+			.service(delete_repository_v0)
+			.service(restore_repository_v0)
+			.service(check_integrity_v0)
+			.service(repair_integrity_v0)
+			.service(get_index_report_v0)
+			.service(get_user_v0)
+			.service(update_user_v0)
+			.service(list_user_repositories_v0),
+	);
+}
+
+#[derive(Parser)]
+#[command(about = "The dotcodeschool backend")]
+struct Cli {
+	#[command(subcommand)]
+	command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+	/// Run the API server (the default)
+	Serve,
+	/// Create or update a course from the `course.toml` or `course.yaml` manifest in a directory
+	ImportCourse {
+		/// Directory containing the course manifest
+		dir: PathBuf,
+		/// Print the changes without saving them
+		#[arg(long)]
+		dry_run: bool,
+	},
+	/// Report references between repositories, users and courses that are dangling or mistyped
+	CheckIntegrity {
+		/// Correct the references that can be corrected automatically
+		#[arg(long)]
+		repair: bool,
+	},
+	/// Report indexes that are declared but missing from the database, or present but not declared
+	Indexes,
+	/// Apply, revert or list schema migrations of the MongoDB database
+	Migrate {
+		#[command(subcommand)]
+		action: MigrateAction,
+	},
+}
+
+#[derive(Subcommand)]
+enum MigrateAction {
+	/// Apply pending migrations
+	Up {
+		/// Stop after applying this version
+		#[arg(long)]
+		to: Option<u32>,
+	},
+	/// Revert the latest migration
+	Down {
+		/// Revert every migration newer than this version instead, 0 reverting all of them
+		#[arg(long)]
+		to: Option<u32>,
+	},
+	/// List migrations and whether they have been applied
+	Status,
+}
+
+/// Run a `migrate` subcommand against the configured MongoDB database
+async fn migrate(config: &Config, action: MigrateAction) -> Result<(), MigrationError> {
+	let migrator = Migrator::new(connect_mongo(config).await.database(config.db_name()));
+
+	match action {
+		MigrateAction::Up { to } => {
+			let applied = migrator.up(to).await?;
+			println!("Applied {} migrations {:?}", applied.len(), applied);
+		},
+		MigrateAction::Down { to } => {
+			let reverted = migrator.down(to).await?;
+			println!("Reverted {} migrations {:?}", reverted.len(), reverted);
+		},
+		MigrateAction::Status =>
+			for status in migrator.status().await? {
+				println!("{}", status);
+			},
+	}
+	Ok(())
 }
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
 	env_logger::init();
 	dotenv().ok();
+	let cli = Cli::parse();
+
+	let config = match Config::load() {
+		Ok(config) => Arc::new(config),
+		Err(e) => {
+			error!("{}", e);
+			std::process::exit(1);
+		},
+	};
+	info!("Loaded configuration for the {} environment", config.environment);
+
+	match cli.command {
+		Some(Command::Migrate { action }) => {
+			if config.database.backend != StoreBackend::Mongo {
+				error!("Migrations only apply to the mongo store backend");
+				std::process::exit(1);
+			}
+			if let Err(e) = migrate(&config, action).await {
+				error!("{}", e);
+				std::process::exit(1);
+			}
+			return Ok(());
+		},
+		Some(Command::Serve) | None if config.database.migrate_on_startup =>
+			match config.database.backend {
+				StoreBackend::Mongo => {
+					if let Err(e) = migrate(&config, MigrateAction::Up { to: None }).await {
+						error!("Failed to migrate the database: {}", e);
+						std::process::exit(1);
+					}
+				},
+				StoreBackend::Memory => info!("In-memory store needs no migrations"),
+			},
+		_ => {},
+	}
+
+	let store = init_store(&config).await;
+
+	match cli.command {
+		Some(Command::ImportCourse { dir, dry_run }) =>
+			match import::import_course(store.as_ref(), &dir, dry_run).await {
+				Ok(outcome) => {
+					println!("{}", outcome);
+					return Ok(());
+				},
+				Err(e) => {
+					error!("{}", e);
+					std::process::exit(1);
+				},
+			},
+		Some(Command::CheckIntegrity { repair }) => match integrity::check_integrity(
+			store.as_ref(),
+			init_git_host(&config).as_ref(),
+			repair,
+		)
+		.await
+		{
+			Ok(report) => {
+				for issue in &report.issues {
+					println!("{}", issue);
+				}
+				println!(
+					"Checked {} repositories, {} users and {} courses: {} issues, {} unrepaired",
+					report.repositories,
+					report.users,
+					report.courses,
+					report.issues.len(),
+					report.unrepaired()
+				);
+				std::process::exit(if report.unrepaired() == 0 { 0 } else { 1 });
+			},
+			Err(e) => {
+				error!("{}", e);
+				std::process::exit(1);
+			},
+		},
+		Some(Command::Indexes) => match store.index_report().await {
+			Ok(report) => {
+				for index in &report.missing {
+					println!("missing {}", index);
+				}
+				for index in &report.extra {
+					println!("extra   {}", index);
+				}
+				println!(
+					"{} indexes present, {} missing, {} extra",
+					report.present.len(),
+					report.missing.len(),
+					report.extra.len()
+				);
+				std::process::exit(if report.is_complete() { 0 } else { 1 });
+			},
+			Err(e) => {
+				error!("{}", e);
+				std::process::exit(1);
+			},
+		},
+		Some(Command::Migrate { .. }) | Some(Command::Serve) | None => {},
+	}
+
+	let git_host = init_git_host(&config);
+	let repo_namer = config.git.naming.namer();
+	let queue = init_queue(&config).await;
+
+	if config.reconcile.enabled {
+		reconcile::spawn_reconciler(
+			store.clone(),
+			git_host.clone(),
+			Duration::from_secs(config.reconcile.interval_secs),
+			Duration::from_secs(config.reconcile.grace_period_secs),
+		);
+	}
 
-	let uri = std::env::var("MONGODB_URI").expect("MONGODB_URI must be set");
-	let redis_uri = std::env::var("REDIS_URI").expect("REDIS_URI must be set");
-	let ws_url = std::env::var("WS_URL").expect("WS_URL must be set");
+	if config.archive.purge_enabled {
+		archive::spawn_purger(
+			store.clone(),
+			git_host.clone(),
+			Duration::from_secs(config.archive.purge_interval_secs),
+			Duration::from_secs(config.archive.grace_period_days * 24 * 60 * 60),
+		);
+	}
+
+	queue::spawn_queue_monitor(
+		store.clone(),
+		queue.clone(),
+		Duration::from_secs(config.queue.monitor_interval_secs),
+	);
 
-	let client = Client::with_uri_str(&uri).await.expect("Failed to connect to MongoDB");
+	if config.reminders.enabled {
+		reminders::spawn_reminder_scheduler(
+			store.clone(),
+			init_notifier(&config),
+			Duration::from_secs(config.reminders.interval_secs),
+		);
+	}
 
-	let port = std::env::var("PORT").unwrap_or_else(|_| "8080".to_string());
-	let bind_address = format!("0.0.0.0:{}", port);
+	let bind_address = config.bind_address();
 
 	info!("Starting server on {}", &bind_address);
 
 	HttpServer::new(move || {
 		App::new()
 			.app_data(web::Data::new(AppState {
-				client: client.clone(),
-				redis_uri: redis_uri.clone(),
-				ws_url: ws_url.clone(),
+				config: config.clone(),
+				store: store.clone(),
+				git_host: git_host.clone(),
+				repo_namer: repo_namer.clone(),
+				queue: queue.clone(),
 			}))
-			.service(
-				web::scope("/api/v0")
-					.service(create_repository_v0)
-					.service(create_submission_v0)
-					.service(get_course_v0)
-					.service(get_repository_v0)
-					.service(update_repository_v0),
-			)
+			.configure(configure_app)
 	})
 	.bind(&bind_address)
 	.unwrap_or_else(|_| panic!("Failed to bind to {}", bind_address))
@@ -0,0 +1,63 @@
+use async_trait::async_trait;
+use mongodb::{
+	bson::{doc, Bson, Document},
+	Collection, Database,
+};
+
+use super::Migration;
+use crate::{
+	constants::{REPO_COLLECTION, SUBMISSION_COLLECTION},
+	errors::MigrationError,
+	types::SubmissionStatus,
+};
+
+/// Write the defaults of fields added to repositories and submissions since they were first
+/// stored, so that every document has them and they can be queried and validated
+pub(super) struct BackfillAddedFields;
+
+/// Set `field` to `value` on every document of `collection` missing it
+async fn backfill(
+	collection: &Collection<Document>,
+	field: &str,
+	value: impl Into<Bson>,
+) -> Result<(), MigrationError> {
+	collection
+		.update_many(doc! { field: { "$exists": false } }, doc! { "$set": { field: value.into() } })
+		.await?;
+	Ok(())
+}
+
+#[async_trait]
+impl Migration for BackfillAddedFields {
+	fn version(&self) -> u32 {
+		1
+	}
+
+	fn name(&self) -> &'static str {
+		"backfill_added_fields"
+	}
+
+	async fn up(&self, db: &Database) -> Result<(), MigrationError> {
+		let repositories = db.collection(REPO_COLLECTION);
+		backfill(&repositories, "progress", doc! { "current_stage": Bson::Null, "stages": [] })
+			.await?;
+		backfill(&repositories, "course_version", Bson::Null).await?;
+		backfill(&repositories, "tester_version", Bson::Null).await?;
+		backfill(&repositories, "relationships", Document::new()).await?;
+
+		let submissions = db.collection(SUBMISSION_COLLECTION);
+		// Submissions from before statuses were tracked have long since run, with an outcome that
+		// was never recorded. Queuing them would leave them waiting for a worker forever.
+		backfill(&submissions, "status", SubmissionStatus::Errored.to_string()).await?;
+		backfill(&submissions, "stages", Bson::Array(vec![])).await?;
+		backfill(&submissions, "started_at", Bson::Null).await?;
+		backfill(&submissions, "finished_at", Bson::Null).await?;
+
+		Ok(())
+	}
+
+	async fn down(&self, _db: &Database) -> Result<(), MigrationError> {
+		// The backfilled values are the defaults used when the fields are missing
+		Ok(())
+	}
+}
@@ -0,0 +1,46 @@
+use async_trait::async_trait;
+use mongodb::{bson::doc, Database};
+
+use super::Migration;
+use crate::{constants::USER_COLLECTION, errors::MigrationError};
+
+/// Move the repositories of users written by older versions from `relationships.repositories.data`,
+/// where they were typed `repositories`, into `repositories`
+pub(super) struct UserRepositoryRelationships;
+
+#[async_trait]
+impl Migration for UserRepositoryRelationships {
+	fn version(&self) -> u32 {
+		2
+	}
+
+	fn name(&self) -> &'static str {
+		"user_repository_relationships"
+	}
+
+	async fn up(&self, db: &Database) -> Result<(), MigrationError> {
+		let users = db.collection::<mongodb::bson::Document>(USER_COLLECTION);
+
+		let pipeline = vec![doc! { "$set": {
+			"repositories": { "$setUnion": [
+				{ "$ifNull": ["$repositories", []] },
+				{ "$map": {
+					"input": { "$ifNull": ["$relationships.repositories.data", []] },
+					"as": "repository",
+					"in": { "id": "$$repository.id", "type": "repository" },
+				}},
+			]},
+			"relationships": { "$literal": [] },
+		}}];
+		users
+			.update_many(doc! { "relationships.repositories": { "$exists": true } }, pipeline)
+			.await?;
+
+		Ok(())
+	}
+
+	async fn down(&self, _db: &Database) -> Result<(), MigrationError> {
+		// Every version of the backend reads `repositories`
+		Ok(())
+	}
+}
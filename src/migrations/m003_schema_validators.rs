@@ -0,0 +1,97 @@
+use async_trait::async_trait;
+use mongodb::{
+	bson::{doc, Document},
+	Database,
+};
+use strum::VariantNames;
+
+use super::Migration;
+use crate::{
+	constants::{REPO_COLLECTION, SUBMISSION_COLLECTION},
+	errors::MigrationError,
+	types::SubmissionStatus,
+};
+
+/// Reject repositories and submissions missing the fields every version of the backend needs to
+/// read them. Validation is moderate, so documents that were already invalid can still be updated.
+pub(super) struct SchemaValidators;
+
+fn repository_schema() -> Document {
+	doc! { "$jsonSchema": {
+		"bsonType": "object",
+		"required": [
+			"repo_name",
+			"repo_template",
+			"tester_url",
+			"relationships",
+			"expected_practice_frequency",
+			"is_reminder_enabled",
+		],
+		"properties": {
+			"repo_name": { "bsonType": "string" },
+			"repo_template": { "bsonType": "string" },
+			"tester_url": { "bsonType": "string" },
+			"relationships": { "bsonType": "object" },
+			"expected_practice_frequency": { "enum": ["every_day", "once_a_week", "once_a_month"] },
+			"is_reminder_enabled": { "bsonType": "bool" },
+			"test_ok": { "bsonType": ["bool", "null"] },
+		},
+	}}
+}
+
+fn submission_schema() -> Document {
+	doc! { "$jsonSchema": {
+		"bsonType": "object",
+		"required": ["repo_name", "commit_sha", "logstream_id", "logstream_url", "created_at"],
+		"properties": {
+			"repo_name": { "bsonType": "string" },
+			"commit_sha": { "bsonType": "string" },
+			"logstream_id": { "bsonType": "string" },
+			"logstream_url": { "bsonType": "string" },
+			"status": { "enum": SubmissionStatus::VARIANTS },
+			"stages": { "bsonType": "array" },
+		},
+	}}
+}
+
+/// Replace the validator of a collection, creating the collection if it does not exist yet. An
+/// empty validator removes validation.
+async fn set_validator(
+	db: &Database,
+	name: &str,
+	validator: Document,
+) -> Result<(), MigrationError> {
+	if !db.list_collection_names().await?.iter().any(|existing| existing == name) {
+		db.create_collection(name).await?;
+	}
+
+	db.run_command(doc! {
+		"collMod": name,
+		"validator": validator,
+		"validationLevel": "moderate",
+		"validationAction": "error",
+	})
+	.await?;
+	Ok(())
+}
+
+#[async_trait]
+impl Migration for SchemaValidators {
+	fn version(&self) -> u32 {
+		3
+	}
+
+	fn name(&self) -> &'static str {
+		"schema_validators"
+	}
+
+	async fn up(&self, db: &Database) -> Result<(), MigrationError> {
+		set_validator(db, REPO_COLLECTION, repository_schema()).await?;
+		set_validator(db, SUBMISSION_COLLECTION, submission_schema()).await
+	}
+
+	async fn down(&self, db: &Database) -> Result<(), MigrationError> {
+		set_validator(db, REPO_COLLECTION, Document::new()).await?;
+		set_validator(db, SUBMISSION_COLLECTION, Document::new()).await
+	}
+}
@@ -0,0 +1,133 @@
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures_util::TryStreamExt;
+use log::warn;
+use mongodb::{
+	bson::{doc, Bson, Document},
+	Collection, Database,
+};
+
+use super::Migration;
+use crate::{
+	constants::{
+		COURSE_VERSION_COLLECTION, PENDING_REPO_COLLECTION, REMINDER_COLLECTION, REPO_COLLECTION,
+		SUBMISSION_COLLECTION,
+	},
+	errors::MigrationError,
+};
+
+/// Store timestamps, written as RFC 3339 strings by earlier versions, as BSON dates so that they
+/// can be compared and sorted in queries
+pub(super) struct BsonTimestamps;
+
+/// The timestamp fields of each collection
+const TIMESTAMPS: &[(&str, &[&str])] = &[
+	(REPO_COLLECTION, &["created_at", "last_submission_at", "archived_at"]),
+	(COURSE_VERSION_COLLECTION, &["archived_at"]),
+	(SUBMISSION_COLLECTION, &["created_at", "started_at", "finished_at"]),
+	(REMINDER_COLLECTION, &["sent_at"]),
+	(PENDING_REPO_COLLECTION, &["created_at"]),
+];
+
+/// The timestamp fields of the stage progress recorded in `progress.stages` of repositories
+const STAGE_TIMESTAMPS: &[&str] = &["first_attempted_at", "completed_at"];
+
+fn to_date(value: &Bson) -> Option<Bson> {
+	let Bson::String(value) = value else {
+		return None;
+	};
+	match DateTime::parse_from_rfc3339(value) {
+		Ok(at) => Some(Bson::DateTime(at.with_timezone(&Utc).into())),
+		Err(e) => {
+			warn!("Leaving unreadable timestamp `{}` as it is: {}", value, e);
+			None
+		},
+	}
+}
+
+fn to_string(value: &Bson) -> Option<Bson> {
+	let Bson::DateTime(value) = value else {
+		return None;
+	};
+	Some(Bson::String(value.to_chrono().to_rfc3339_opts(SecondsFormat::AutoSi, true)))
+}
+
+/// Apply `convert` to the `fields` of `document`, returning the fields it changed
+fn convert_fields(
+	document: &Document,
+	fields: &[&str],
+	convert: fn(&Bson) -> Option<Bson>,
+) -> Document {
+	fields
+		.iter()
+		.filter_map(|field| Some((field.to_string(), convert(document.get(field)?)?)))
+		.collect()
+}
+
+/// Apply `convert` to the timestamps of every document of `collection`, including the stage
+/// progress of repositories
+async fn convert_collection(
+	collection: &Collection<Document>,
+	fields: &[&str],
+	convert: fn(&Bson) -> Option<Bson>,
+) -> Result<(), MigrationError> {
+	let mut documents = collection.find(doc! {}).await?;
+	while let Some(document) = documents.try_next().await? {
+		let mut set = convert_fields(&document, fields, convert);
+
+		if let Ok(stages) = document.get_document("progress").and_then(|p| p.get_array("stages")) {
+			let mut changed = false;
+			let stages: Vec<Bson> = stages
+				.iter()
+				.map(|stage| match stage {
+					Bson::Document(stage) => {
+						let converted = convert_fields(stage, STAGE_TIMESTAMPS, convert);
+						changed |= !converted.is_empty();
+						let mut stage = stage.clone();
+						stage.extend(converted);
+						Bson::Document(stage)
+					},
+					other => other.clone(),
+				})
+				.collect();
+			if changed {
+				set.insert("progress.stages", stages);
+			}
+		}
+
+		if let (false, Some(id)) = (set.is_empty(), document.get("_id")) {
+			collection.update_one(doc! { "_id": id.clone() }, doc! { "$set": set }).await?;
+		}
+	}
+	Ok(())
+}
+
+async fn convert_all(
+	db: &Database,
+	convert: fn(&Bson) -> Option<Bson>,
+) -> Result<(), MigrationError> {
+	for (collection, fields) in TIMESTAMPS {
+		convert_collection(&db.collection(collection), fields, convert).await?;
+	}
+	Ok(())
+}
+
+#[async_trait]
+impl Migration for BsonTimestamps {
+	fn version(&self) -> u32 {
+		4
+	}
+
+	fn name(&self) -> &'static str {
+		"bson_timestamps"
+	}
+
+	async fn up(&self, db: &Database) -> Result<(), MigrationError> {
+		convert_all(db, to_date).await
+	}
+
+	async fn down(&self, db: &Database) -> Result<(), MigrationError> {
+		// Earlier versions only read timestamps stored as strings
+		convert_all(db, to_string).await
+	}
+}
@@ -0,0 +1,68 @@
+use async_trait::async_trait;
+use futures_util::TryStreamExt;
+use log::{info, warn};
+use mongodb::{
+	bson::{doc, Bson, Document},
+	Collection, Database,
+};
+
+use super::Migration;
+use crate::{
+	constants::{REPO_COLLECTION, SUBMISSION_COLLECTION},
+	errors::MigrationError,
+};
+
+/// Set `created_at` and `last_submission_at` on repositories created before they were tracked.
+/// The creation time is taken from the document's id, and the latest submission from the
+/// `submissions` collection.
+pub(super) struct RepositoryTimestamps;
+
+#[async_trait]
+impl Migration for RepositoryTimestamps {
+	fn version(&self) -> u32 {
+		5
+	}
+
+	fn name(&self) -> &'static str {
+		"repository_timestamps"
+	}
+
+	async fn up(&self, db: &Database) -> Result<(), MigrationError> {
+		let repositories: Collection<Document> = db.collection(REPO_COLLECTION);
+		let submissions: Collection<Document> = db.collection(SUBMISSION_COLLECTION);
+
+		let mut backfilled = 0;
+		let mut untracked = repositories.find(doc! { "created_at": { "$exists": false } }).await?;
+		while let Some(repository) = untracked.try_next().await? {
+			let repo_name = repository.get_str("repo_name").unwrap_or_default();
+			let Ok(id) = repository.get_object_id("_id") else {
+				warn!("Repository `{}` has no object id, not backfilling timestamps", repo_name);
+				continue;
+			};
+
+			let latest = submissions
+				.find_one(doc! { "repo_name": repo_name })
+				.sort(doc! { "created_at": -1 })
+				.await?;
+			let last_submission_at =
+				latest.and_then(|submission| submission.get("created_at").cloned());
+
+			let update = doc! { "$set": {
+				"created_at": id.timestamp(),
+				"last_submission_at": last_submission_at.unwrap_or(Bson::Null),
+			}};
+			repositories.update_one(doc! { "_id": id }, update).await?;
+			backfilled += 1;
+		}
+
+		if backfilled > 0 {
+			info!("Backfilled timestamps of {} repositories", backfilled);
+		}
+		Ok(())
+	}
+
+	async fn down(&self, _db: &Database) -> Result<(), MigrationError> {
+		// Both timestamps are optional on repositories
+		Ok(())
+	}
+}
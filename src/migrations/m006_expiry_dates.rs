@@ -0,0 +1,56 @@
+use async_trait::async_trait;
+use mongodb::{
+	bson::{doc, Document},
+	Collection, Database,
+};
+
+use super::Migration;
+use crate::{
+	constants::{IDEMPOTENCY_COLLECTION, TESTER_NONCE_COLLECTION},
+	errors::MigrationError,
+};
+
+/// Store the `expires_at` of tester nonces and idempotency keys, written as unix seconds by earlier
+/// versions, as BSON dates so that the TTL indexes on them delete expired documents
+pub(super) struct ExpiryDates;
+
+/// The collections whose documents expire
+const COLLECTIONS: &[&str] = &[TESTER_NONCE_COLLECTION, IDEMPOTENCY_COLLECTION];
+
+#[async_trait]
+impl Migration for ExpiryDates {
+	fn version(&self) -> u32 {
+		6
+	}
+
+	fn name(&self) -> &'static str {
+		"expiry_dates"
+	}
+
+	async fn up(&self, db: &Database) -> Result<(), MigrationError> {
+		let to_date = vec![doc! { "$set": {
+			"expires_at": { "$toDate": { "$multiply": [{ "$toLong": "$expires_at" }, 1000] } },
+		}}];
+		for collection in COLLECTIONS {
+			let collection: Collection<Document> = db.collection(collection);
+			collection
+				.update_many(doc! { "expires_at": { "$type": "number" } }, to_date.clone())
+				.await?;
+		}
+		Ok(())
+	}
+
+	async fn down(&self, db: &Database) -> Result<(), MigrationError> {
+		// Earlier versions only read expiry times stored as unix seconds
+		let to_seconds = vec![doc! { "$set": {
+			"expires_at": { "$toLong": { "$divide": [{ "$toLong": "$expires_at" }, 1000] } },
+		}}];
+		for collection in COLLECTIONS {
+			let collection: Collection<Document> = db.collection(collection);
+			collection
+				.update_many(doc! { "expires_at": { "$type": "date" } }, to_seconds.clone())
+				.await?;
+		}
+		Ok(())
+	}
+}
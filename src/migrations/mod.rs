@@ -0,0 +1,274 @@
+mod m001_backfill_added_fields;
+mod m002_user_repository_relationships;
+mod m003_schema_validators;
+mod m004_bson_timestamps;
+mod m005_repository_timestamps;
+mod m006_expiry_dates;
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use log::{info, warn};
+use mongodb::{
+	bson::{doc, Document},
+	Collection, Database,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	constants::MIGRATION_COLLECTION,
+	errors::{is_duplicate_key_error, MigrationError},
+};
+
+/// A change to the shape of the documents in the database, such as backfilling a field added to a
+/// model or installing a validator. Migrations are applied in order of version, and each is
+/// recorded in the `_migrations` collection once applied.
+#[async_trait]
+pub(crate) trait Migration: Send + Sync {
+	/// Position of the migration. Versions are never reused or reordered once released.
+	fn version(&self) -> u32;
+
+	/// Short description, recorded alongside the version
+	fn name(&self) -> &'static str;
+
+	async fn up(&self, db: &Database) -> Result<(), MigrationError>;
+
+	/// Undo [`Migration::up`]. Migrations that only add data the code already tolerates being
+	/// absent may leave it in place.
+	async fn down(&self, db: &Database) -> Result<(), MigrationError>;
+}
+
+/// Every migration, in order of version
+fn migrations() -> Vec<Box<dyn Migration>> {
+	vec![
+		Box::new(m001_backfill_added_fields::BackfillAddedFields),
+		Box::new(m002_user_repository_relationships::UserRepositoryRelationships),
+		Box::new(m003_schema_validators::SchemaValidators),
+		Box::new(m004_bson_timestamps::BsonTimestamps),
+		Box::new(m005_repository_timestamps::RepositoryTimestamps),
+		Box::new(m006_expiry_dates::ExpiryDates),
+	]
+}
+
+/// A migration recorded as applied
+#[derive(Debug, Serialize, Deserialize)]
+struct AppliedMigration {
+	version: u32,
+	name: String,
+	applied_at: DateTime<Utc>,
+}
+
+/// Whether a migration has been applied
+#[derive(Debug)]
+pub(crate) struct MigrationStatus {
+	pub version: u32,
+	pub name: String,
+	pub applied_at: Option<DateTime<Utc>>,
+	/// Recorded as applied, but not known to this build, meaning the database was migrated by a
+	/// newer version of the backend
+	pub unknown: bool,
+}
+
+impl std::fmt::Display for MigrationStatus {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{:03} {:<40}", self.version, self.name)?;
+		match (self.applied_at, self.unknown) {
+			(Some(at), false) => write!(f, "applied {}", at.to_rfc3339()),
+			(Some(at), true) => write!(f, "applied {} by a newer version", at.to_rfc3339()),
+			(None, _) => write!(f, "pending"),
+		}
+	}
+}
+
+/// How long a lock may be held before it is considered abandoned by a crashed instance
+const LOCK_TTL: chrono::Duration = chrono::Duration::minutes(10);
+
+/// How long to wait for another instance to finish migrating
+const LOCK_WAIT: Duration = Duration::from_secs(60);
+
+/// Applies and reverts [`Migration`]s against a MongoDB database. Only one instance migrates at a
+/// time; others wait for it to finish.
+pub(crate) struct Migrator {
+	db: Database,
+	migrations: Vec<Box<dyn Migration>>,
+}
+
+impl Migrator {
+	pub(crate) fn new(db: Database) -> Self {
+		Migrator { db, migrations: migrations() }
+	}
+
+	fn records(&self) -> Collection<AppliedMigration> {
+		self.db.collection(MIGRATION_COLLECTION)
+	}
+
+	async fn applied(&self) -> Result<Vec<AppliedMigration>, MigrationError> {
+		Ok(self
+			.records()
+			.find(doc! { "version": { "$exists": true } })
+			.sort(doc! { "version": 1 })
+			.await?
+			.try_collect()
+			.await?)
+	}
+
+	/// Every known or recorded migration, in order of version
+	pub(crate) async fn status(&self) -> Result<Vec<MigrationStatus>, MigrationError> {
+		let applied = self.applied().await?;
+
+		let mut status: Vec<MigrationStatus> = self
+			.migrations
+			.iter()
+			.map(|migration| MigrationStatus {
+				version: migration.version(),
+				name: migration.name().to_string(),
+				applied_at: applied
+					.iter()
+					.find(|record| record.version == migration.version())
+					.map(|record| record.applied_at),
+				unknown: false,
+			})
+			.collect();
+		for record in applied {
+			if !self.migrations.iter().any(|migration| migration.version() == record.version) {
+				status.push(MigrationStatus {
+					version: record.version,
+					name: record.name,
+					applied_at: Some(record.applied_at),
+					unknown: true,
+				});
+			}
+		}
+		status.sort_by_key(|status| status.version);
+
+		Ok(status)
+	}
+
+	/// Apply every pending migration up to and including `target`, or all of them. Returns the
+	/// versions applied.
+	pub(crate) async fn up(&self, target: Option<u32>) -> Result<Vec<u32>, MigrationError> {
+		if let Some(target) = target {
+			self.find(target)?;
+		}
+
+		self.lock().await?;
+		let result = async {
+			let applied = self.applied().await?;
+			if let Some(latest) = applied.last() {
+				if !self.migrations.iter().any(|migration| migration.version() == latest.version) {
+					warn!(
+						"Database was migrated to version {} by a newer version of the backend",
+						latest.version
+					);
+				}
+			}
+
+			let mut versions = vec![];
+			for migration in &self.migrations {
+				let version = migration.version();
+				if target.is_some_and(|target| version > target) {
+					break;
+				}
+				if applied.iter().any(|record| record.version == version) {
+					continue;
+				}
+
+				info!("Applying migration {:03} {}", version, migration.name());
+				migration.up(&self.db).await?;
+				self.records()
+					.insert_one(AppliedMigration {
+						version,
+						name: migration.name().to_string(),
+						applied_at: Utc::now(),
+					})
+					.await?;
+				versions.push(version);
+			}
+			Ok(versions)
+		}
+		.await;
+		self.unlock().await?;
+
+		result
+	}
+
+	/// Revert applied migrations newer than `target`, latest first, or only the latest one if no
+	/// target is given. Returns the versions reverted.
+	pub(crate) async fn down(&self, target: Option<u32>) -> Result<Vec<u32>, MigrationError> {
+		if let Some(target) = target.filter(|target| *target > 0) {
+			self.find(target)?;
+		}
+
+		self.lock().await?;
+		let result = async {
+			let applied = self.applied().await?;
+			let to_revert: Vec<&AppliedMigration> = match target {
+				Some(target) =>
+					applied.iter().rev().take_while(|record| record.version > target).collect(),
+				None => applied.last().into_iter().collect(),
+			};
+
+			let mut versions = vec![];
+			for record in to_revert {
+				let migration = self.find(record.version)?;
+
+				info!("Reverting migration {:03} {}", record.version, migration.name());
+				migration.down(&self.db).await?;
+				self.records().delete_one(doc! { "version": record.version }).await?;
+				versions.push(record.version);
+			}
+			Ok(versions)
+		}
+		.await;
+		self.unlock().await?;
+
+		result
+	}
+
+	fn find(&self, version: u32) -> Result<&dyn Migration, MigrationError> {
+		self.migrations
+			.iter()
+			.find(|migration| migration.version() == version)
+			.map(|migration| migration.as_ref())
+			.ok_or(MigrationError::UnknownVersion(version))
+	}
+
+	/// Take the migration lock, a document with a fixed id that only one instance can insert.
+	/// Waits for other instances to release it, and takes over locks older than [`LOCK_TTL`].
+	async fn lock(&self) -> Result<(), MigrationError> {
+		let locks: Collection<Document> = self.db.collection(MIGRATION_COLLECTION);
+		let started = std::time::Instant::now();
+
+		loop {
+			let stale =
+				mongodb::bson::DateTime::from_millis((Utc::now() - LOCK_TTL).timestamp_millis());
+			let result = locks
+				.update_one(
+					doc! { "_id": "lock", "locked_at": { "$lt": stale } },
+					doc! { "$set": { "locked_at": mongodb::bson::DateTime::now() } },
+				)
+				.upsert(true)
+				.await;
+
+			match result {
+				Ok(_) => return Ok(()),
+				Err(e) if is_duplicate_key_error(&e) => {
+					if started.elapsed() > LOCK_WAIT {
+						return Err(MigrationError::Locked);
+					}
+					info!("Waiting for another instance to finish migrating");
+					actix_web::rt::time::sleep(Duration::from_secs(1)).await;
+				},
+				Err(e) => return Err(e.into()),
+			}
+		}
+	}
+
+	async fn unlock(&self) -> Result<(), MigrationError> {
+		let locks: Collection<Document> = self.db.collection(MIGRATION_COLLECTION);
+		locks.delete_one(doc! { "_id": "lock" }).await?;
+		Ok(())
+	}
+}
@@ -1,34 +1,149 @@
-use std::collections::HashMap;
-
 use chrono::Utc;
 use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
 
-use crate::{types::DocumentType, ExpectedPracticeFrequency};
+use crate::{
+	types::{CourseStatus, Difficulty, DocumentType, SubmissionStatus},
+	ExpectedPracticeFrequency,
+};
 
 /// A repository document. This is used to store information about the owner of the repository, the
 /// template used to create the repository, and the relationships between the repository and other
 /// documents.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Repository {
+	#[serde(rename = "_id", default, skip_serializing_if = "Option::is_none")]
+	pub id: Option<ObjectId>,
 	pub repo_name: String,
 	pub repo_template: String,
 	pub tester_url: String,
+	/// The tester release pinned by the course version the repository is on, if any
+	#[serde(default)]
+	pub tester_version: Option<String>,
+	/// The version of the course the repository was created against, or last migrated to. Its
+	/// tester is used for the repository's submissions.
+	#[serde(default)]
+	pub course_version: Option<String>,
 	pub test_ok: Option<bool>,
-	pub relationships: HashMap<String, Relationship>,
+	pub relationships: RepositoryRelationships,
 	pub expected_practice_frequency: ExpectedPracticeFrequency,
 	pub is_reminder_enabled: bool,
+	#[serde(default)]
+	pub progress: RepositoryProgress,
+	/// Missing on repositories created before it was tracked until they are backfilled
+	#[serde(default, with = "timestamp::option")]
+	pub created_at: Option<chrono::DateTime<Utc>>,
+	#[serde(default, with = "timestamp::option")]
+	pub last_submission_at: Option<chrono::DateTime<Utc>>,
+	/// When the owner deleted the repository. Archived repositories are hidden until they are
+	/// restored by an admin or purged.
+	#[serde(default, skip_serializing_if = "Option::is_none", with = "timestamp::option")]
+	pub archived_at: Option<chrono::DateTime<Utc>>,
+}
+
+/// The documents a repository belongs to. Stored as an object keyed by relationship name.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepositoryRelationships {
+	/// The user who owns the repository
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub user: Option<Relationship>,
+	/// The course the repository was created from
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub course: Option<Relationship>,
+}
+
+impl RepositoryRelationships {
+	/// Every relationship of the repository
+	pub fn iter(&self) -> impl Iterator<Item = &Relationship> {
+		self.user.iter().chain(&self.course)
+	}
+}
+
+/// How far a repository has got through the stages of its course. Updated from the stage results
+/// reported for its submissions.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RepositoryProgress {
+	/// The slug of the first stage not yet completed, or `None` once every stage is completed
+	pub current_stage: Option<String>,
+	/// Every stage attempted so far, in course order
+	pub stages: Vec<StageProgress>,
+}
+
+/// Progress on a single stage of a course
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageProgress {
+	pub stage: String,
+	#[serde(with = "timestamp")]
+	pub first_attempted_at: chrono::DateTime<Utc>,
+	#[serde(with = "timestamp::option")]
+	pub completed_at: Option<chrono::DateTime<Utc>>,
 }
 
 /// A user document. This is used to store information about the user, the repositories they own,
 /// and the relationships between the user and other documents.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "StoredUser")]
 pub struct User {
 	pub name: String,
+	#[serde(default)]
+	pub email: Option<String>,
+	/// The repositories the user owns
 	pub repositories: Vec<Relationship>,
 	pub relationships: Vec<Relationship>,
 }
 
+/// A user document as stored. Users written before relationships were typed keep their
+/// repositories under `relationships.repositories.data` instead of `repositories`; they are read
+/// into `repositories` until the integrity checker rewrites them.
+#[derive(Deserialize)]
+struct StoredUser {
+	name: String,
+	#[serde(default)]
+	email: Option<String>,
+	#[serde(default)]
+	repositories: Vec<Relationship>,
+	#[serde(default)]
+	relationships: StoredUserRelationships,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredUserRelationships {
+	List(Vec<Relationship>),
+	Legacy { repositories: LegacyRelationshipList },
+}
+
+impl Default for StoredUserRelationships {
+	fn default() -> Self {
+		StoredUserRelationships::List(vec![])
+	}
+}
+
+#[derive(Deserialize)]
+struct LegacyRelationshipList {
+	#[serde(default)]
+	data: Vec<Relationship>,
+}
+
+impl From<StoredUser> for User {
+	fn from(stored: StoredUser) -> Self {
+		let mut repositories = stored.repositories;
+		let relationships = match stored.relationships {
+			StoredUserRelationships::List(relationships) => relationships,
+			StoredUserRelationships::Legacy { repositories: legacy } => {
+				for relationship in legacy.data {
+					if !repositories.iter().any(|seen| seen.id == relationship.id) {
+						repositories.push(relationship);
+					}
+				}
+				vec![]
+			},
+		};
+
+		User { name: stored.name, email: stored.email, repositories, relationships }
+	}
+}
+
 /// Information about the course author.
 #[derive(Serialize, Deserialize, Debug, Default, PartialEq, Eq, Clone)]
 pub struct Author {
@@ -49,10 +164,56 @@ pub struct Course {
 	pub title: String,
 	pub author: Author,
 	pub tester_url: String,
+	/// The tester release to run, such as a tag of the tester repository. Pinned per course
+	/// version, so repositories keep running the tester of the version they are on. `None` runs
+	/// the latest.
+	#[serde(default)]
+	pub tester_version: Option<String>,
+	/// The programming language the course is taught in
+	#[serde(default)]
+	pub language: Option<String>,
+	#[serde(default)]
+	pub difficulty: Option<Difficulty>,
+	#[serde(default)]
+	pub tags: Vec<String>,
+	#[serde(default)]
+	pub stages: Vec<Stage>,
+	#[serde(default)]
+	pub status: CourseStatus,
 	#[serde(default)]
 	pub relationships: Vec<Relationship>,
 }
 
+/// A prior version of a course, kept whenever the course is updated
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CourseVersion {
+	pub course_id: ObjectId,
+	pub version: String,
+	pub course: Course,
+	#[serde(with = "timestamp")]
+	pub archived_at: chrono::DateTime<Utc>,
+}
+
+/// A stage of a course. Learners work through a course's stages in order.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stage {
+	pub slug: String,
+	pub title: String,
+	pub description: String,
+	/// The id the tester reports results for this stage under
+	pub tester_stage_id: String,
+}
+
+/// A document resolved from a relationship, of the type the relationship names
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RelatedDocument {
+	Repository(Repository),
+	/// User documents do not carry their id
+	User(ObjectId, User),
+	Course(Course),
+}
+
 /// A relationship between documents. This is used to store the ID of the document and the type of
 /// document in the relationship.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
@@ -61,6 +222,35 @@ pub struct Relationship {
 	pub r#type: DocumentType,
 }
 
+/// A signature seen on a tester callback. Kept until `expires_at`, when MongoDB deletes it, so that
+/// a captured request cannot be replayed while its timestamp is still accepted.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TesterNonce {
+	pub tester_id: String,
+	pub signature: String,
+	#[serde(with = "timestamp")]
+	pub expires_at: chrono::DateTime<Utc>,
+}
+
+/// A request made with an idempotency key, and its response once it has completed. Kept until
+/// `expires_at`, when MongoDB deletes it, so that a retried request gets the original response
+/// instead of being processed again.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdempotencyRecord {
+	pub user_id: ObjectId,
+	/// The endpoint the key was used on. The same key may be used on different endpoints.
+	pub endpoint: String,
+	pub key: String,
+	/// Hash of the request body, to reject a key reused with a different request
+	pub fingerprint: String,
+	/// The JSON response, or `None` while the request is still being processed
+	pub response: Option<String>,
+	#[serde(with = "timestamp")]
+	pub expires_at: chrono::DateTime<Utc>,
+}
+
+/// A submission document. Records a single test run of a commit, from the moment it is queued
+/// until the tester reports it finished.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Submission {
 	pub repo_name: String,
@@ -68,5 +258,108 @@ pub struct Submission {
 	pub logstream_id: String,
 	pub logstream_url: String,
 	pub relationships: Vec<Relationship>,
+	#[serde(with = "timestamp")]
+	pub created_at: chrono::DateTime<Utc>,
+	#[serde(default)]
+	pub status: SubmissionStatus,
+	#[serde(default)]
+	pub stages: Vec<StageResult>,
+	#[serde(default, with = "timestamp::option")]
+	pub started_at: Option<chrono::DateTime<Utc>>,
+	#[serde(default, with = "timestamp::option")]
+	pub finished_at: Option<chrono::DateTime<Utc>>,
+	/// Set on submissions made before the repository was last reset. They are kept as history but
+	/// no longer count towards the repository's progress.
+	#[serde(default)]
+	pub previous_attempt: bool,
+}
+
+/// The outcome of a single course stage within a submission
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StageResult {
+	pub stage: String,
+	pub passed: bool,
+	#[serde(default)]
+	pub duration_ms: Option<u64>,
+	#[serde(default)]
+	pub message: Option<String>,
+}
+
+/// A practice reminder sent for a repository. At most one is recorded per repository and period,
+/// where the period is the day, ISO week or month matching the repository's practice frequency.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReminderRecord {
+	pub repo_name: String,
+	pub period: String,
+	#[serde(with = "timestamp")]
+	pub sent_at: chrono::DateTime<Utc>,
+}
+
+/// A repository whose creation has started but not yet completed. Written before the repository is
+/// created on the git server and removed once the database writes have committed, so that a crash
+/// in between can be detected and repaired.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingRepository {
+	pub repo_name: String,
+	pub repo_template: String,
+	pub user_id: ObjectId,
+	#[serde(with = "timestamp")]
 	pub created_at: chrono::DateTime<Utc>,
 }
+
+/// Serde helpers storing timestamps as BSON dates, so that MongoDB compares and sorts them in
+/// queries, while JSON keeps them as RFC 3339 strings. Timestamps stored as strings by earlier
+/// versions, before the `bson_timestamps` migration, are still read.
+pub(crate) mod timestamp {
+	use chrono::{DateTime, Utc};
+	use mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime;
+	use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+	/// A timestamp as found in a document
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum Stored {
+		Date(#[serde(with = "chrono_datetime_as_bson_datetime")] DateTime<Utc>),
+		Rfc3339(DateTime<Utc>),
+	}
+
+	pub(crate) fn serialize<S: Serializer>(
+		at: &DateTime<Utc>,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		if serializer.is_human_readable() {
+			at.serialize(serializer)
+		} else {
+			chrono_datetime_as_bson_datetime::serialize(at, serializer)
+		}
+	}
+
+	pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<DateTime<Utc>, D::Error> {
+		match Stored::deserialize(deserializer)? {
+			Stored::Date(at) | Stored::Rfc3339(at) => Ok(at),
+		}
+	}
+
+	/// [`timestamp`](super::timestamp) for optional timestamps
+	pub(crate) mod option {
+		use super::*;
+
+		#[derive(Serialize, Deserialize)]
+		struct Timestamp(#[serde(with = "super")] DateTime<Utc>);
+
+		pub(crate) fn serialize<S: Serializer>(
+			at: &Option<DateTime<Utc>>,
+			serializer: S,
+		) -> Result<S::Ok, S::Error> {
+			at.map(Timestamp).serialize(serializer)
+		}
+
+		pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+			deserializer: D,
+		) -> Result<Option<DateTime<Utc>>, D::Error> {
+			Ok(Option::<Timestamp>::deserialize(deserializer)?.map(|Timestamp(at)| at))
+		}
+	}
+}
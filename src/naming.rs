@@ -0,0 +1,157 @@
+use std::sync::Arc;
+
+use rand::prelude::*;
+use serde::Deserialize;
+
+use crate::models::Course;
+
+/// Chooses the names of new repositories. A name identifies the repository on the git server and
+/// in URLs, so it must be unique; creation retries with a new name when one is already taken.
+pub(crate) trait RepoNamer: Send + Sync {
+	/// Propose a name for a new repository of `course`
+	fn name(&self, course: &Course) -> String;
+}
+
+/// 16 random hex characters, e.g. `3f9a0c1be27d4458`
+pub(crate) struct RandomHexNamer;
+
+impl RepoNamer for RandomHexNamer {
+	fn name(&self, _course: &Course) -> String {
+		let mut repo_id = hex::encode(rand::thread_rng().gen::<[u8; 16]>());
+		repo_id.truncate(16);
+		repo_id
+	}
+}
+
+/// The course slug followed by 8 random hex characters, e.g. `rust-state-machine-3f9a0c1b`
+pub(crate) struct CoursePrefixedNamer;
+
+impl RepoNamer for CoursePrefixedNamer {
+	fn name(&self, course: &Course) -> String {
+		format!("{}-{}", course.slug, hex::encode(rand::thread_rng().gen::<[u8; 4]>()))
+	}
+}
+
+const ADJECTIVES: [&str; 32] = [
+	"amber", "bold", "brave", "bright", "calm", "clever", "cosmic", "crisp", "daring", "eager",
+	"fancy", "gentle", "golden", "happy", "jolly", "keen", "lively", "lucky", "mellow", "nimble",
+	"noble", "proud", "quick", "quiet", "rapid", "shiny", "silent", "steady", "swift", "tidy",
+	"vivid", "witty",
+];
+
+const NOUNS: [&str; 32] = [
+	"anchor", "badger", "beacon", "canyon", "comet", "crab", "falcon", "ferret", "forest",
+	"galaxy", "harbor", "heron", "island", "lagoon", "lantern", "meadow", "nebula", "otter",
+	"panda", "pebble", "pine", "puffin", "quasar", "raven", "river", "rocket", "summit", "thistle",
+	"tiger", "tundra", "walrus", "willow",
+];
+
+/// An adjective, a noun and a 4 digit number, e.g. `swift-otter-0427`
+pub(crate) struct ReadableNamer;
+
+impl RepoNamer for ReadableNamer {
+	fn name(&self, _course: &Course) -> String {
+		let mut rng = rand::thread_rng();
+		format!(
+			"{}-{}-{:04}",
+			ADJECTIVES.choose(&mut rng).expect("not empty"),
+			NOUNS.choose(&mut rng).expect("not empty"),
+			rng.gen_range(0..10_000)
+		)
+	}
+}
+
+/// How new repositories are named, selected by `git.naming` in the config
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum RepoNamingStrategy {
+	Hex,
+	Course,
+	Readable,
+}
+
+impl RepoNamingStrategy {
+	pub(crate) fn namer(self) -> Arc<dyn RepoNamer> {
+		match self {
+			RepoNamingStrategy::Hex => Arc::new(RandomHexNamer),
+			RepoNamingStrategy::Course => Arc::new(CoursePrefixedNamer),
+			RepoNamingStrategy::Readable => Arc::new(ReadableNamer),
+		}
+	}
+}
+
+impl std::str::FromStr for RepoNamingStrategy {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"hex" => Ok(RepoNamingStrategy::Hex),
+			"course" => Ok(RepoNamingStrategy::Course),
+			"readable" => Ok(RepoNamingStrategy::Readable),
+			other => Err(format!("Unknown repository naming strategy `{}`", other)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::json;
+
+	use super::*;
+
+	fn course() -> Course {
+		serde_json::from_value(json!({
+			"version": "1",
+			"_id": { "$oid": "66b5f3a2c1d2e3f4a5b6c7d8" },
+			"slug": "rust-state-machine",
+			"name": "rsm",
+			"title": "Rust State Machine",
+			"author": { "name": "author", "url": "https://example.com" },
+			"testerUrl": "https://tester.example.com",
+			"stages": [],
+		}))
+		.expect("valid course")
+	}
+
+	fn is_hex(s: &str) -> bool {
+		s.bytes().all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte))
+	}
+
+	#[test]
+	fn hex_names_are_16_hex_characters() {
+		let name = RandomHexNamer.name(&course());
+		assert_eq!(name.len(), 16);
+		assert!(is_hex(&name), "{}", name);
+	}
+
+	#[test]
+	fn course_names_are_the_slug_and_8_hex_characters() {
+		let name = CoursePrefixedNamer.name(&course());
+		let suffix = name.strip_prefix("rust-state-machine-").expect("prefixed with the slug");
+		assert_eq!(suffix.len(), 8);
+		assert!(is_hex(suffix), "{}", name);
+	}
+
+	#[test]
+	fn readable_names_are_an_adjective_a_noun_and_4_digits() {
+		for _ in 0..100 {
+			let name = ReadableNamer.name(&course());
+			let parts: Vec<&str> = name.split('-').collect();
+			let [adjective, noun, number] = parts[..] else {
+				panic!("expected three parts in `{}`", name);
+			};
+			assert!(ADJECTIVES.contains(&adjective), "{}", name);
+			assert!(NOUNS.contains(&noun), "{}", name);
+			assert_eq!(number.len(), 4);
+			assert!(number.bytes().all(|byte| byte.is_ascii_digit()), "{}", name);
+		}
+	}
+
+	#[test]
+	fn strategies_are_parsed_by_name() {
+		assert_eq!("hex".parse(), Ok(RepoNamingStrategy::Hex));
+		assert_eq!("course".parse(), Ok(RepoNamingStrategy::Course));
+		assert_eq!("readable".parse(), Ok(RepoNamingStrategy::Readable));
+		assert!("uuid".parse::<RepoNamingStrategy>().is_err());
+	}
+}
@@ -0,0 +1,22 @@
+use async_trait::async_trait;
+use log::info;
+
+use super::{Notifier, Reminder};
+use crate::errors::NotifierError;
+
+/// A [`Notifier`] that only logs reminders. Used for local development.
+pub(crate) struct LogNotifier;
+
+#[async_trait]
+impl Notifier for LogNotifier {
+	async fn notify(&self, reminder: &Reminder) -> Result<(), NotifierError> {
+		info!(
+			"Reminder for user `{}` about repository `{}`: {}\n{}",
+			reminder.user_id,
+			reminder.repo_name,
+			reminder.subject(),
+			reminder.body()
+		);
+		Ok(())
+	}
+}
@@ -0,0 +1,79 @@
+mod logger;
+mod smtp;
+mod webhook;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::Serialize;
+
+use crate::{errors::NotifierError, types::ExpectedPracticeFrequency};
+
+pub(crate) use logger::LogNotifier;
+pub(crate) use smtp::SmtpNotifier;
+pub(crate) use webhook::WebhookNotifier;
+
+/// A nudge to a learner who has not practiced as often as they planned to
+#[derive(Clone, Debug, Serialize)]
+pub(crate) struct Reminder {
+	pub user_id: ObjectId,
+	pub user_name: String,
+	pub email: Option<String>,
+	pub repo_name: String,
+	pub repo_template: String,
+	pub expected_practice_frequency: ExpectedPracticeFrequency,
+	pub last_activity_at: DateTime<Utc>,
+}
+
+impl Reminder {
+	pub(crate) fn subject(&self) -> String {
+		format!("Time to get back to {}", self.repo_template)
+	}
+
+	pub(crate) fn body(&self) -> String {
+		let frequency = match self.expected_practice_frequency {
+			ExpectedPracticeFrequency::EveryDay => "every day",
+			ExpectedPracticeFrequency::OnceAWeek => "once a week",
+			ExpectedPracticeFrequency::OnceAMonth => "once a month",
+		};
+
+		format!(
+			"Hi {},\n\nYou planned to practice {} but haven't pushed to `{}` since {}. Pick up where \
+			 you left off whenever you're ready!\n\nYou can turn these reminders off in your \
+			 repository settings.\n",
+			self.user_name,
+			frequency,
+			self.repo_name,
+			self.last_activity_at.format("%B %-d, %Y"),
+		)
+	}
+}
+
+/// Delivers practice reminders to learners
+#[async_trait]
+pub(crate) trait Notifier: Send + Sync {
+	/// Deliver a single reminder
+	async fn notify(&self, reminder: &Reminder) -> Result<(), NotifierError>;
+}
+
+/// The notifier to use, selected by `reminders.notifier` in the config
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum NotifierBackend {
+	Log,
+	Smtp,
+	Webhook,
+}
+
+impl std::str::FromStr for NotifierBackend {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"log" => Ok(NotifierBackend::Log),
+			"smtp" => Ok(NotifierBackend::Smtp),
+			"webhook" => Ok(NotifierBackend::Webhook),
+			other => Err(format!("Unknown notifier `{}`", other)),
+		}
+	}
+}
@@ -0,0 +1,53 @@
+use async_trait::async_trait;
+use lettre::{
+	message::{header::ContentType, Mailbox},
+	transport::smtp::authentication::Credentials,
+	AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+
+use super::{Notifier, Reminder};
+use crate::errors::NotifierError;
+
+/// A [`Notifier`] that emails reminders through an SMTP relay using STARTTLS
+pub(crate) struct SmtpNotifier {
+	transport: AsyncSmtpTransport<Tokio1Executor>,
+	from: Mailbox,
+}
+
+impl SmtpNotifier {
+	pub(crate) fn new(
+		host: &str,
+		port: u16,
+		credentials: Option<(String, String)>,
+		from: &str,
+	) -> Result<Self, NotifierError> {
+		let mut transport = AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?.port(port);
+		if let Some((username, password)) = credentials {
+			transport = transport.credentials(Credentials::new(username, password));
+		}
+		let from = from.parse()?;
+
+		Ok(SmtpNotifier { transport: transport.build(), from })
+	}
+}
+
+#[async_trait]
+impl Notifier for SmtpNotifier {
+	async fn notify(&self, reminder: &Reminder) -> Result<(), NotifierError> {
+		let to: Mailbox = reminder
+			.email
+			.as_deref()
+			.ok_or_else(|| NotifierError::MissingRecipient(reminder.user_id.to_string()))?
+			.parse()?;
+
+		let message = Message::builder()
+			.from(self.from.clone())
+			.to(to)
+			.subject(reminder.subject())
+			.header(ContentType::TEXT_PLAIN)
+			.body(reminder.body())?;
+
+		self.transport.send(message).await?;
+		Ok(())
+	}
+}
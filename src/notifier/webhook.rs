@@ -0,0 +1,30 @@
+use async_trait::async_trait;
+
+use super::{Notifier, Reminder};
+use crate::errors::NotifierError;
+
+/// A [`Notifier`] that POSTs each reminder as JSON to a URL, leaving delivery to another service
+pub(crate) struct WebhookNotifier {
+	client: reqwest::Client,
+	url: String,
+	bearer_token: Option<String>,
+}
+
+impl WebhookNotifier {
+	pub(crate) fn new(url: impl Into<String>, bearer_token: Option<String>) -> Self {
+		WebhookNotifier { client: reqwest::Client::new(), url: url.into(), bearer_token }
+	}
+}
+
+#[async_trait]
+impl Notifier for WebhookNotifier {
+	async fn notify(&self, reminder: &Reminder) -> Result<(), NotifierError> {
+		let mut request = self.client.post(&self.url).json(reminder);
+		if let Some(token) = &self.bearer_token {
+			request = request.bearer_auth(token);
+		}
+
+		request.send().await?.error_for_status()?;
+		Ok(())
+	}
+}
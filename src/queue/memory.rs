@@ -0,0 +1,112 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	sync::{Mutex, MutexGuard},
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+
+use super::{ClaimedJob, ExpiredJobs, SubmissionJob, SubmissionQueue};
+use crate::errors::QueueError;
+
+/// A job held by a worker
+struct Claim {
+	job: SubmissionJob,
+	delivery: u32,
+	worker: String,
+	deadline: Instant,
+}
+
+#[derive(Default)]
+struct State {
+	/// Jobs waiting to be claimed, with the number of times each was delivered before
+	ready: VecDeque<(SubmissionJob, u32)>,
+	/// Claimed jobs, keyed by logstream id
+	claimed: HashMap<String, Claim>,
+}
+
+/// A [`SubmissionQueue`] held in process memory, for local development. Jobs are lost on restart
+/// and only workers talking to this instance see them.
+pub(crate) struct InMemoryQueue {
+	state: Mutex<State>,
+	visibility_timeout: Duration,
+	max_deliveries: u32,
+}
+
+impl InMemoryQueue {
+	pub(crate) fn new(visibility_timeout: Duration, max_deliveries: u32) -> Self {
+		InMemoryQueue { state: Mutex::default(), visibility_timeout, max_deliveries }
+	}
+
+	fn state(&self) -> MutexGuard<'_, State> {
+		self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+	}
+}
+
+#[async_trait]
+impl SubmissionQueue for InMemoryQueue {
+	async fn enqueue(&self, job: SubmissionJob) -> Result<(), QueueError> {
+		self.state().ready.push_back((job, 0));
+		Ok(())
+	}
+
+	async fn claim(&self, worker: &str) -> Result<Option<ClaimedJob>, QueueError> {
+		let mut state = self.state();
+		let Some((job, deliveries)) = state.ready.pop_front() else {
+			return Ok(None);
+		};
+
+		let delivery = deliveries + 1;
+		state.claimed.insert(
+			job.logstream_id.clone(),
+			Claim {
+				job: job.clone(),
+				delivery,
+				worker: worker.to_string(),
+				deadline: Instant::now() + self.visibility_timeout,
+			},
+		);
+		Ok(Some(ClaimedJob { job, delivery }))
+	}
+
+	async fn heartbeat(&self, worker: &str, logstream_id: &str) -> Result<bool, QueueError> {
+		let mut state = self.state();
+		match state.claimed.get_mut(logstream_id) {
+			Some(claim) if claim.worker == worker => {
+				claim.deadline = Instant::now() + self.visibility_timeout;
+				Ok(true)
+			},
+			_ => Ok(false),
+		}
+	}
+
+	async fn complete(&self, logstream_id: &str) -> Result<(), QueueError> {
+		let mut state = self.state();
+		state.claimed.remove(logstream_id);
+		state.ready.retain(|(job, _)| job.logstream_id != logstream_id);
+		Ok(())
+	}
+
+	async fn reap(&self) -> Result<ExpiredJobs, QueueError> {
+		let mut state = self.state();
+		let now = Instant::now();
+		let expired: Vec<String> = state
+			.claimed
+			.iter()
+			.filter(|(_, claim)| claim.deadline <= now)
+			.map(|(logstream_id, _)| logstream_id.clone())
+			.collect();
+
+		let mut jobs = ExpiredJobs::default();
+		for logstream_id in expired {
+			let claim = state.claimed.remove(&logstream_id).expect("collected above");
+			if claim.delivery < self.max_deliveries {
+				state.ready.push_back((claim.job.clone(), claim.delivery));
+				jobs.redelivered.push(claim.job);
+			} else {
+				jobs.timed_out.push(claim.job);
+			}
+		}
+		Ok(jobs)
+	}
+}
@@ -0,0 +1,83 @@
+mod memory;
+mod monitor;
+mod redis_streams;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::QueueError;
+
+pub(crate) use memory::InMemoryQueue;
+pub(crate) use monitor::spawn_queue_monitor;
+pub(crate) use redis_streams::RedisQueue;
+
+/// A submission waiting to be run by a tester worker
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SubmissionJob {
+	pub logstream_id: String,
+	pub repo_name: String,
+	pub commit_sha: String,
+	pub logstream_url: String,
+	pub tester_url: String,
+	pub tester_version: Option<String>,
+}
+
+/// A job handed to a worker
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ClaimedJob {
+	pub job: SubmissionJob,
+	/// How many times the job has been handed out, including this time
+	pub delivery: u32,
+}
+
+/// Jobs whose worker stopped heartbeating, collected by [`SubmissionQueue::reap`]
+#[derive(Debug, Default)]
+pub(crate) struct ExpiredJobs {
+	/// Jobs made available to claim again
+	pub redelivered: Vec<SubmissionJob>,
+	/// Jobs removed from the queue after being delivered the maximum number of times
+	pub timed_out: Vec<SubmissionJob>,
+}
+
+/// Hands submissions to tester workers. A claimed job is hidden from other workers for the
+/// visibility timeout, which each heartbeat from its worker extends. A job whose worker goes
+/// quiet is redelivered, up to a maximum number of deliveries.
+#[async_trait]
+pub(crate) trait SubmissionQueue: Send + Sync {
+	/// Add a job to the back of the queue
+	async fn enqueue(&self, job: SubmissionJob) -> Result<(), QueueError>;
+
+	/// Hand the job at the front of the queue to `worker`, if there is one
+	async fn claim(&self, worker: &str) -> Result<Option<ClaimedJob>, QueueError>;
+
+	/// Extend the visibility timeout of the job of `logstream_id`. Returns `false` if `worker` no
+	/// longer holds the job, because it was completed or redelivered.
+	async fn heartbeat(&self, worker: &str, logstream_id: &str) -> Result<bool, QueueError>;
+
+	/// Remove the job of `logstream_id`, whether it is claimed or not
+	async fn complete(&self, logstream_id: &str) -> Result<(), QueueError>;
+
+	/// Collect the claimed jobs whose visibility timeout has passed, redelivering them or timing
+	/// them out
+	async fn reap(&self) -> Result<ExpiredJobs, QueueError>;
+}
+
+/// The submission queue backend to use, selected by `queue.backend` in the config
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum QueueBackend {
+	Redis,
+	Memory,
+}
+
+impl std::str::FromStr for QueueBackend {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"redis" => Ok(QueueBackend::Redis),
+			"memory" => Ok(QueueBackend::Memory),
+			other => Err(format!("Unknown queue backend `{}`", other)),
+		}
+	}
+}
@@ -0,0 +1,64 @@
+use std::{sync::Arc, time::Duration};
+
+use log::{error, info, warn};
+
+use super::SubmissionQueue;
+use crate::{errors::DbError, store::Store, types::SubmissionStatus, utils::advance_submission};
+
+/// Outcome of a single monitor pass
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct MonitorReport {
+	/// Submissions queued again after their worker stopped heartbeating
+	pub requeued: usize,
+	/// Submissions marked as timed out after being delivered the maximum number of times
+	pub timed_out: usize,
+	/// Submissions whose status could not be updated
+	pub failed: usize,
+}
+
+/// Collect the jobs whose worker stopped heartbeating and move their submissions back to queued,
+/// or to timed out once they have been delivered too often
+pub(crate) async fn reap_expired_jobs(
+	store: &dyn Store,
+	queue: &dyn SubmissionQueue,
+) -> Result<MonitorReport, DbError> {
+	let expired = queue.reap().await?;
+
+	let mut report = MonitorReport::default();
+	let updates = expired
+		.redelivered
+		.iter()
+		.map(|job| (job, SubmissionStatus::Queued))
+		.chain(expired.timed_out.iter().map(|job| (job, SubmissionStatus::TimedOut)));
+	for (job, status) in updates {
+		match advance_submission(store, &job.logstream_id, status, &[]).await {
+			Ok(_) if status == SubmissionStatus::Queued => report.requeued += 1,
+			Ok(_) => report.timed_out += 1,
+			Err(e) => {
+				warn!("Failed to move submission `{}` to {}: {}", job.logstream_id, status, e);
+				report.failed += 1;
+			},
+		}
+	}
+
+	Ok(report)
+}
+
+/// Run [`reap_expired_jobs`] every `interval` in the background
+pub(crate) fn spawn_queue_monitor(
+	store: Arc<dyn Store>,
+	queue: Arc<dyn SubmissionQueue>,
+	interval: Duration,
+) {
+	actix_web::rt::spawn(async move {
+		let mut ticker = actix_web::rt::time::interval(interval);
+		loop {
+			ticker.tick().await;
+			match reap_expired_jobs(store.as_ref(), queue.as_ref()).await {
+				Ok(report) if report == MonitorReport::default() => {},
+				Ok(report) => info!("Submission queue monitor pass finished: {:?}", report),
+				Err(e) => error!("Submission queue monitor pass failed: {}", e),
+			}
+		}
+	});
+}
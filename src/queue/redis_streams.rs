@@ -0,0 +1,288 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use log::warn;
+use redis::{
+	aio::ConnectionManager,
+	streams::{
+		StreamClaimOptions, StreamId, StreamPendingCountReply, StreamRangeReply, StreamReadOptions,
+		StreamReadReply,
+	},
+	AsyncCommands,
+};
+
+use super::{ClaimedJob, ExpiredJobs, SubmissionJob, SubmissionQueue};
+use crate::errors::QueueError;
+
+/// Consumer group shared by every tester worker
+const GROUP: &str = "testers";
+
+/// Most expired jobs collected by a single [`SubmissionQueue::reap`]
+const REAP_BATCH: usize = 100;
+
+/// Add a job to the stream and record the entry holding it, atomically since the entry id is only
+/// known once added.
+///
+/// Keys: the stream, the entries hash. Arguments: logstream id, job, deliveries.
+const ADD_SCRIPT: &str = r#"
+local id = redis.call('XADD', KEYS[1], '*', 'job', ARGV[2], 'deliveries', ARGV[3])
+redis.call('HSET', KEYS[2], ARGV[1], id)
+return id
+"#;
+
+/// Move a job from an expired entry to a new one, unless the entry was completed since it was
+/// found expired. Returns whether the job was moved.
+///
+/// Keys: the stream, the entries hash. Arguments: group, expired entry id, logstream id, job,
+/// deliveries.
+const REDELIVER_SCRIPT: &str = r#"
+if redis.call('XACK', KEYS[1], ARGV[1], ARGV[2]) == 0 then
+	return 0
+end
+redis.call('XDEL', KEYS[1], ARGV[2])
+local id = redis.call('XADD', KEYS[1], '*', 'job', ARGV[4], 'deliveries', ARGV[5])
+redis.call('HSET', KEYS[2], ARGV[3], id)
+return 1
+"#;
+
+/// A [`SubmissionQueue`] backed by a Redis stream. Each job is a stream entry delivered to workers
+/// through a consumer group, so a claimed job stays in the group's pending list until it is
+/// completed. A redelivered job is re-added as a new entry carrying its delivery count, and a hash
+/// maps each logstream id to the entry currently holding its job.
+pub(crate) struct RedisQueue {
+	connection: ConnectionManager,
+	stream: String,
+	entries: String,
+	visibility_timeout: Duration,
+	max_deliveries: u32,
+}
+
+impl RedisQueue {
+	/// Connect to Redis, creating the stream and its consumer group if they do not exist yet
+	pub(crate) async fn connect(
+		uri: &str,
+		stream: &str,
+		visibility_timeout: Duration,
+		max_deliveries: u32,
+	) -> Result<Self, QueueError> {
+		let client = redis::Client::open(uri)?;
+		let mut connection = ConnectionManager::new(client).await?;
+
+		let created: redis::RedisResult<()> =
+			connection.xgroup_create_mkstream(stream, GROUP, "0").await;
+		match created {
+			Ok(()) => {},
+			Err(e) if e.code() == Some("BUSYGROUP") => {},
+			Err(e) => return Err(e.into()),
+		}
+
+		Ok(RedisQueue {
+			connection,
+			stream: stream.to_string(),
+			entries: format!("{}:entries", stream),
+			visibility_timeout,
+			max_deliveries,
+		})
+	}
+
+	fn connection(&self) -> ConnectionManager {
+		self.connection.clone()
+	}
+
+	/// The id of the stream entry holding the job of `logstream_id`
+	async fn entry_id(&self, logstream_id: &str) -> Result<Option<String>, QueueError> {
+		Ok(self.connection().hget(&self.entries, logstream_id).await?)
+	}
+
+	/// Add `job` to the stream, recording that it was delivered `deliveries` times before
+	async fn add(&self, job: &SubmissionJob, deliveries: u32) -> Result<(), QueueError> {
+		let payload = serialize_job(job)?;
+		let _: String = redis::cmd("EVAL")
+			.arg(ADD_SCRIPT)
+			.arg(2)
+			.arg(&self.stream)
+			.arg(&self.entries)
+			.arg(&job.logstream_id)
+			.arg(payload)
+			.arg(deliveries)
+			.query_async(&mut self.connection())
+			.await?;
+		Ok(())
+	}
+
+	/// Move `job` from the expired entry `id` to a new entry, recording that it was delivered
+	/// `deliveries` times before. Returns `false` if the job was completed in the meantime.
+	async fn redeliver(
+		&self,
+		id: &str,
+		job: &SubmissionJob,
+		deliveries: u32,
+	) -> Result<bool, QueueError> {
+		let payload = serialize_job(job)?;
+		let moved: bool = redis::cmd("EVAL")
+			.arg(REDELIVER_SCRIPT)
+			.arg(2)
+			.arg(&self.stream)
+			.arg(&self.entries)
+			.arg(GROUP)
+			.arg(id)
+			.arg(&job.logstream_id)
+			.arg(payload)
+			.arg(deliveries)
+			.query_async(&mut self.connection())
+			.await?;
+		Ok(moved)
+	}
+
+	/// Remove the expired entry `id` holding the job of `logstream_id`. Returns `false` if the job
+	/// was completed in the meantime.
+	async fn retire(&self, id: &str, logstream_id: &str) -> Result<bool, QueueError> {
+		let (acked,): (usize,) = redis::pipe()
+			.atomic()
+			.xack(&self.stream, GROUP, &[id])
+			.xdel(&self.stream, &[id])
+			.ignore()
+			.hdel(&self.entries, logstream_id)
+			.ignore()
+			.query_async(&mut self.connection())
+			.await?;
+		Ok(acked > 0)
+	}
+
+	/// Redeliver or time out the job held by the expired entry `id`, adding it to `jobs`
+	async fn reap_entry(&self, id: &str, jobs: &mut ExpiredJobs) -> Result<(), QueueError> {
+		let range: StreamRangeReply = self.connection().xrange(&self.stream, id, id).await?;
+		// The entry is gone if the job was completed since it was listed. Acknowledging it again
+		// is harmless, and keeps an entry deleted while still pending from being listed forever.
+		let Some(entry) = range.ids.first() else {
+			return self.remove(id, None).await;
+		};
+		let (job, deliveries) = match parse_entry(entry) {
+			Ok(parsed) => parsed,
+			Err(e) => {
+				warn!("Dropping expired job: {}", e);
+				return self.remove(id, None).await;
+			},
+		};
+
+		let delivery = deliveries + 1;
+		if delivery < self.max_deliveries {
+			if self.redeliver(id, &job, delivery).await? {
+				jobs.redelivered.push(job);
+			}
+		} else if self.retire(id, &job.logstream_id).await? {
+			jobs.timed_out.push(job);
+		}
+		Ok(())
+	}
+
+	/// Remove the stream entry `id`. The job of `logstream_id`, if known, is forgotten as well.
+	async fn remove(&self, id: &str, logstream_id: Option<&str>) -> Result<(), QueueError> {
+		let mut pipe = redis::pipe();
+		pipe.atomic()
+			.xack(&self.stream, GROUP, &[id])
+			.ignore()
+			.xdel(&self.stream, &[id])
+			.ignore();
+		if let Some(logstream_id) = logstream_id {
+			pipe.hdel(&self.entries, logstream_id).ignore();
+		}
+		let () = pipe.query_async(&mut self.connection()).await?;
+		Ok(())
+	}
+}
+
+/// Write a job as stored in a stream entry
+fn serialize_job(job: &SubmissionJob) -> Result<String, QueueError> {
+	serde_json::to_string(job)
+		.map_err(|e| QueueError::InvalidJob(job.logstream_id.clone(), e.to_string()))
+}
+
+/// Read the job and the number of earlier deliveries from a stream entry
+fn parse_entry(entry: &StreamId) -> Result<(SubmissionJob, u32), QueueError> {
+	let invalid = |reason: String| QueueError::InvalidJob(entry.id.clone(), reason);
+
+	let payload: String = entry.get("job").ok_or_else(|| invalid("missing job".to_string()))?;
+	let job = serde_json::from_str(&payload).map_err(|e| invalid(e.to_string()))?;
+	let deliveries = entry.get("deliveries").unwrap_or(0);
+	Ok((job, deliveries))
+}
+
+#[async_trait]
+impl SubmissionQueue for RedisQueue {
+	async fn enqueue(&self, job: SubmissionJob) -> Result<(), QueueError> {
+		self.add(&job, 0).await
+	}
+
+	async fn claim(&self, worker: &str) -> Result<Option<ClaimedJob>, QueueError> {
+		let options = StreamReadOptions::default().group(GROUP, worker).count(1);
+		let reply: Option<StreamReadReply> =
+			self.connection().xread_options(&[&self.stream], &[">"], &options).await?;
+		let Some(entry) =
+			reply.into_iter().flat_map(|reply| reply.keys).flat_map(|key| key.ids).next()
+		else {
+			return Ok(None);
+		};
+
+		match parse_entry(&entry) {
+			Ok((job, deliveries)) => Ok(Some(ClaimedJob { job, delivery: deliveries + 1 })),
+			Err(e) => {
+				// An unreadable entry would otherwise sit in the pending list forever
+				self.remove(&entry.id, None).await?;
+				Err(e)
+			},
+		}
+	}
+
+	async fn heartbeat(&self, worker: &str, logstream_id: &str) -> Result<bool, QueueError> {
+		let Some(id) = self.entry_id(logstream_id).await? else {
+			return Ok(false);
+		};
+		let mut connection = self.connection();
+
+		let pending: StreamPendingCountReply =
+			connection.xpending_count(&self.stream, GROUP, &id, &id, 1).await?;
+		if !pending.ids.iter().any(|pending| pending.consumer == worker) {
+			return Ok(false);
+		}
+
+		// Claiming the entry for its current owner resets its idle time
+		let options = StreamClaimOptions::default().with_justid();
+		let claimed: Vec<String> = connection
+			.xclaim_options(&self.stream, GROUP, worker, 0, &[&id], options)
+			.await?;
+		Ok(!claimed.is_empty())
+	}
+
+	async fn complete(&self, logstream_id: &str) -> Result<(), QueueError> {
+		match self.entry_id(logstream_id).await? {
+			Some(id) => self.remove(&id, Some(logstream_id)).await,
+			None => Ok(()),
+		}
+	}
+
+	async fn reap(&self) -> Result<ExpiredJobs, QueueError> {
+		let mut connection = self.connection();
+		let pending: StreamPendingCountReply = redis::cmd("XPENDING")
+			.arg(&self.stream)
+			.arg(GROUP)
+			.arg("IDLE")
+			.arg(self.visibility_timeout.as_millis() as u64)
+			.arg("-")
+			.arg("+")
+			.arg(REAP_BATCH)
+			.query_async(&mut connection)
+			.await?;
+
+		// Jobs already moved are returned even if a later one fails, so that their submissions
+		// are updated. The failed job is still pending and is reaped again on the next pass.
+		let mut jobs = ExpiredJobs::default();
+		for pending in pending.ids {
+			if let Err(e) = self.reap_entry(&pending.id, &mut jobs).await {
+				warn!("Failed to reap expired job `{}`: {}", pending.id, e);
+				break;
+			}
+		}
+		Ok(jobs)
+	}
+}
@@ -0,0 +1,125 @@
+use std::{sync::Arc, time::Duration};
+
+use log::{error, info, warn};
+
+use crate::{
+	errors::{DbError, GitHostError},
+	git_host::GitHost,
+	models::PendingRepository,
+	store::Store,
+};
+
+/// Outcome of a single reconciliation pass
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ReconcileReport {
+	/// Repositories whose database writes had committed and only needed their marker cleared
+	pub completed: usize,
+	/// Repositories whose database writes never committed and were deleted from the git server
+	pub rolled_back: usize,
+	/// Repositories whose database writes never committed but which could not be deleted from the
+	/// git server. Their markers are kept, so the deletion is retried on the next pass.
+	pub orphaned: usize,
+	/// Repositories that could not be repaired and will be retried on the next pass
+	pub failed: usize,
+}
+
+/// Repair repositories whose creation was interrupted more than `grace_period` ago.
+///
+/// A pending marker outliving its creation means the process died, or the rollback failed, part way
+/// through `do_create_repo`. If the repository document exists the transaction committed, so the
+/// creation is finished off; otherwise the git repository is orphaned and is deleted.
+pub(crate) async fn reconcile_pending_repositories(
+	store: &dyn Store,
+	git_host: &dyn GitHost,
+	grace_period: Duration,
+) -> Result<ReconcileReport, DbError> {
+	let grace_period = chrono::Duration::from_std(grace_period)
+		.map_err(|e| DbError::InternalServerError(e.to_string()))?;
+	let stale = store.list_pending_repositories(chrono::Utc::now() - grace_period).await?;
+
+	let mut report = ReconcileReport::default();
+	for pending in stale {
+		match reconcile_one(store, git_host, &pending).await {
+			Ok(Reconciled::Completed) => report.completed += 1,
+			Ok(Reconciled::RolledBack) => report.rolled_back += 1,
+			Ok(Reconciled::Orphaned(e)) => {
+				error!(
+					"Orphaned repository `{}` is still on the git server, keeping it pending: {}",
+					pending.repo_name, e
+				);
+				report.orphaned += 1;
+			},
+			Err(e) => {
+				warn!("Failed to reconcile repository `{}`: {}", pending.repo_name, e);
+				report.failed += 1;
+			},
+		}
+	}
+
+	Ok(report)
+}
+
+/// How a pending repository was reconciled
+enum Reconciled {
+	/// The creation was completed
+	Completed,
+	/// The creation was rolled back
+	RolledBack,
+	/// The creation could not be rolled back because the git server failed to delete the
+	/// repository
+	Orphaned(GitHostError),
+}
+
+/// Reconcile a single pending repository
+async fn reconcile_one(
+	store: &dyn Store,
+	git_host: &dyn GitHost,
+	pending: &PendingRepository,
+) -> Result<Reconciled, String> {
+	let repo_name = &pending.repo_name;
+
+	match store.get_repository(repo_name).await.map_err(|e| e.to_string())? {
+		Some(repository) => {
+			if let Some(repo_id) = repository.id {
+				store
+					.add_repository_to_user(&pending.user_id, repo_id)
+					.await
+					.map_err(|e| e.to_string())?;
+			}
+			store.delete_pending_repository(repo_name).await.map_err(|e| e.to_string())?;
+			info!("Completed interrupted creation of repository `{}`", repo_name);
+			Ok(Reconciled::Completed)
+		},
+		None => {
+			match git_host.delete_repo(repo_name).await {
+				Ok(()) | Err(GitHostError::NotFound(_)) => {},
+				Err(e) => return Ok(Reconciled::Orphaned(e)),
+			}
+			store.delete_pending_repository(repo_name).await.map_err(|e| e.to_string())?;
+			info!("Deleted orphaned git repository `{}`", repo_name);
+			Ok(Reconciled::RolledBack)
+		},
+	}
+}
+
+/// Run [`reconcile_pending_repositories`] every `interval` in the background
+pub(crate) fn spawn_reconciler(
+	store: Arc<dyn Store>,
+	git_host: Arc<dyn GitHost>,
+	interval: Duration,
+	grace_period: Duration,
+) {
+	actix_web::rt::spawn(async move {
+		let mut ticker = actix_web::rt::time::interval(interval);
+		loop {
+			ticker.tick().await;
+			match reconcile_pending_repositories(store.as_ref(), git_host.as_ref(), grace_period)
+				.await
+			{
+				Ok(report) if report == ReconcileReport::default() => {},
+				Ok(report) => info!("Repository reconciliation finished: {:?}", report),
+				Err(e) => error!("Repository reconciliation failed: {}", e),
+			}
+		}
+	});
+}
@@ -0,0 +1,314 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::{DateTime, Datelike, Utc};
+use log::{error, info, warn};
+
+use crate::{
+	errors::{DbError, NotifierError},
+	models::{ReminderRecord, Repository},
+	notifier::{Notifier, Reminder},
+	store::Store,
+	types::ExpectedPracticeFrequency,
+};
+
+/// Outcome of a single reminder pass
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct ReminderReport {
+	/// Reminders delivered
+	pub sent: usize,
+	/// Repositories that are overdue but were already reminded this period
+	pub already_sent: usize,
+	/// Reminders that could not be delivered and will be retried on the next pass
+	pub failed: usize,
+}
+
+/// How long a repository may go without activity before its owner is reminded
+fn practice_interval(frequency: &ExpectedPracticeFrequency) -> chrono::Duration {
+	match frequency {
+		ExpectedPracticeFrequency::EveryDay => chrono::Duration::days(1),
+		ExpectedPracticeFrequency::OnceAWeek => chrono::Duration::weeks(1),
+		ExpectedPracticeFrequency::OnceAMonth => chrono::Duration::days(30),
+	}
+}
+
+/// The calendar day, ISO week or month containing `now`, depending on the practice frequency. A
+/// repository is reminded at most once per period.
+fn reminder_period(frequency: &ExpectedPracticeFrequency, now: DateTime<Utc>) -> String {
+	match frequency {
+		ExpectedPracticeFrequency::EveryDay => now.format("%Y-%m-%d").to_string(),
+		ExpectedPracticeFrequency::OnceAWeek => {
+			let week = now.iso_week();
+			format!("{}-W{:02}", week.year(), week.week())
+		},
+		ExpectedPracticeFrequency::OnceAMonth => now.format("%Y-%m").to_string(),
+	}
+}
+
+/// The time of the latest submission to a repository, or its creation time if it has none
+async fn last_activity(
+	store: &dyn Store,
+	repository: &Repository,
+) -> Result<Option<DateTime<Utc>>, DbError> {
+	if let Some(at) = repository.last_submission_at.or(repository.created_at) {
+		return Ok(Some(at));
+	}
+
+	// Not yet backfilled
+	let latest = store.list_submissions(&repository.repo_name, 0, 1).await?;
+	if let Some(submission) = latest.first() {
+		return Ok(Some(submission.created_at));
+	}
+
+	Ok(repository
+		.id
+		.and_then(|id| DateTime::from_timestamp_millis(id.timestamp().timestamp_millis())))
+}
+
+/// Remind the owners of every repository whose last activity is overdue for its practice
+/// frequency. Each repository is reminded at most once per period, even across instances, since the
+/// reminder is recorded before it is delivered.
+pub(crate) async fn send_due_reminders(
+	store: &dyn Store,
+	notifier: &dyn Notifier,
+	now: DateTime<Utc>,
+) -> Result<ReminderReport, DbError> {
+	let mut report = ReminderReport::default();
+
+	for repository in store.list_reminder_enabled_repositories().await? {
+		let frequency = &repository.expected_practice_frequency;
+		let Some(last_activity_at) = last_activity(store, &repository).await? else {
+			continue;
+		};
+		if now - last_activity_at < practice_interval(frequency) {
+			continue;
+		}
+
+		let Some(owner) = repository.relationships.user.as_ref() else {
+			warn!("Repository `{}` has no owner, not sending reminder", repository.repo_name);
+			continue;
+		};
+		let Some(user) = store.get_user(&owner.id).await? else {
+			warn!("Owner `{}` of repository `{}` not found", owner.id, repository.repo_name);
+			continue;
+		};
+
+		let period = reminder_period(frequency, now);
+		let record = ReminderRecord {
+			repo_name: repository.repo_name.clone(),
+			period: period.clone(),
+			sent_at: now,
+		};
+		if !store.record_reminder(record).await? {
+			report.already_sent += 1;
+			continue;
+		}
+
+		let reminder = Reminder {
+			user_id: owner.id,
+			user_name: user.name,
+			email: user.email,
+			repo_name: repository.repo_name.clone(),
+			repo_template: repository.repo_template.clone(),
+			expected_practice_frequency: frequency.clone(),
+			last_activity_at,
+		};
+
+		match notifier.notify(&reminder).await {
+			Ok(()) => report.sent += 1,
+			// Retrying cannot help until the user adds an address, so keep the record
+			Err(e @ NotifierError::MissingRecipient(_)) => {
+				warn!("Not reminding about repository `{}`: {}", repository.repo_name, e);
+			},
+			Err(e) => {
+				warn!("Failed to send reminder for repository `{}`: {}", repository.repo_name, e);
+				store.delete_reminder(&repository.repo_name, &period).await?;
+				report.failed += 1;
+			},
+		}
+	}
+
+	Ok(report)
+}
+
+/// Run [`send_due_reminders`] every `interval` in the background
+pub(crate) fn spawn_reminder_scheduler(
+	store: Arc<dyn Store>,
+	notifier: Arc<dyn Notifier>,
+	interval: Duration,
+) {
+	actix_web::rt::spawn(async move {
+		let mut ticker = actix_web::rt::time::interval(interval);
+		loop {
+			ticker.tick().await;
+			match send_due_reminders(store.as_ref(), notifier.as_ref(), Utc::now()).await {
+				Ok(report) if report == ReminderReport::default() => {},
+				Ok(report) => info!("Practice reminder pass finished: {:?}", report),
+				Err(e) => error!("Practice reminder pass failed: {}", e),
+			}
+		}
+	});
+}
+
+#[cfg(test)]
+mod tests {
+	use std::sync::Mutex;
+
+	use async_trait::async_trait;
+	use chrono::TimeZone;
+	use mongodb::bson::oid::ObjectId;
+
+	use super::*;
+	use crate::{
+		models::{Relationship, RepositoryProgress, RepositoryRelationships, User},
+		store::InMemoryStore,
+		types::DocumentType,
+	};
+
+	/// A [`Notifier`] that records the reminders it delivers, failing while `failure` is set
+	#[derive(Default)]
+	struct RecordingNotifier {
+		sent: Mutex<Vec<String>>,
+		failure: Mutex<Option<fn() -> NotifierError>>,
+	}
+
+	impl RecordingNotifier {
+		fn sent(&self) -> Vec<String> {
+			self.sent.lock().unwrap().clone()
+		}
+
+		fn fail_with(&self, failure: Option<fn() -> NotifierError>) {
+			*self.failure.lock().unwrap() = failure;
+		}
+	}
+
+	#[async_trait]
+	impl Notifier for RecordingNotifier {
+		async fn notify(&self, reminder: &Reminder) -> Result<(), NotifierError> {
+			if let Some(failure) = *self.failure.lock().unwrap() {
+				return Err(failure());
+			}
+			self.sent.lock().unwrap().push(reminder.repo_name.clone());
+			Ok(())
+		}
+	}
+
+	fn undeliverable() -> NotifierError {
+		"not an address".parse::<lettre::Address>().unwrap_err().into()
+	}
+
+	fn missing_recipient() -> NotifierError {
+		NotifierError::MissingRecipient("learner".to_string())
+	}
+
+	fn at(year: i32, month: u32, day: u32, hour: u32) -> DateTime<Utc> {
+		Utc.with_ymd_and_hms(year, month, day, hour, 0, 0).unwrap()
+	}
+
+	/// A store holding a user and a repository of theirs with reminders enabled, last practiced at
+	/// `last_activity_at`
+	async fn store_with_repository(
+		frequency: ExpectedPracticeFrequency,
+		last_activity_at: DateTime<Utc>,
+	) -> InMemoryStore {
+		let store = InMemoryStore::new();
+		let user_id = ObjectId::new();
+		let user = User {
+			name: "learner".to_string(),
+			email: Some("learner@example.com".to_string()),
+			repositories: vec![],
+			relationships: vec![],
+		};
+		store.insert_user(user_id, user);
+
+		let repository = Repository {
+			id: None,
+			repo_name: "repo".to_string(),
+			repo_template: "rust-state-machine".to_string(),
+			tester_url: "https://tester.example.com".to_string(),
+			tester_version: None,
+			course_version: None,
+			test_ok: None,
+			relationships: RepositoryRelationships {
+				user: Some(Relationship { id: user_id, r#type: DocumentType::User }),
+				course: None,
+			},
+			expected_practice_frequency: frequency,
+			is_reminder_enabled: true,
+			progress: RepositoryProgress::default(),
+			created_at: Some(last_activity_at),
+			last_submission_at: Some(last_activity_at),
+			archived_at: None,
+		};
+		store.create_repository(repository, &user_id).await.unwrap();
+		store
+	}
+
+	#[test]
+	fn periods_are_calendar_days_iso_weeks_and_months() {
+		let now = at(2024, 12, 30, 9);
+
+		assert_eq!(reminder_period(&ExpectedPracticeFrequency::EveryDay, now), "2024-12-30");
+		assert_eq!(reminder_period(&ExpectedPracticeFrequency::OnceAWeek, now), "2025-W01");
+		assert_eq!(reminder_period(&ExpectedPracticeFrequency::OnceAMonth, now), "2024-12");
+	}
+
+	#[actix_web::test]
+	async fn only_overdue_repositories_are_reminded() {
+		let notifier = RecordingNotifier::default();
+		let now = at(2024, 3, 10, 9);
+
+		let store =
+			store_with_repository(ExpectedPracticeFrequency::EveryDay, at(2024, 3, 9, 12)).await;
+		let report = send_due_reminders(&store, &notifier, now).await.unwrap();
+		assert_eq!(report, ReminderReport::default());
+
+		let store =
+			store_with_repository(ExpectedPracticeFrequency::OnceAWeek, at(2024, 3, 5, 9)).await;
+		let report = send_due_reminders(&store, &notifier, now).await.unwrap();
+		assert_eq!(report, ReminderReport::default());
+
+		let store =
+			store_with_repository(ExpectedPracticeFrequency::EveryDay, at(2024, 3, 8, 9)).await;
+		let report = send_due_reminders(&store, &notifier, now).await.unwrap();
+		assert_eq!(report, ReminderReport { sent: 1, ..Default::default() });
+		assert_eq!(notifier.sent(), vec!["repo"]);
+	}
+
+	#[actix_web::test]
+	async fn a_repository_is_reminded_once_per_period() {
+		let notifier = RecordingNotifier::default();
+		let store =
+			store_with_repository(ExpectedPracticeFrequency::EveryDay, at(2024, 3, 1, 9)).await;
+
+		let report = send_due_reminders(&store, &notifier, at(2024, 3, 10, 9)).await.unwrap();
+		assert_eq!(report, ReminderReport { sent: 1, ..Default::default() });
+		let report = send_due_reminders(&store, &notifier, at(2024, 3, 10, 21)).await.unwrap();
+		assert_eq!(report, ReminderReport { already_sent: 1, ..Default::default() });
+		let report = send_due_reminders(&store, &notifier, at(2024, 3, 11, 9)).await.unwrap();
+		assert_eq!(report, ReminderReport { sent: 1, ..Default::default() });
+
+		assert_eq!(notifier.sent().len(), 2);
+	}
+
+	#[actix_web::test]
+	async fn a_failed_reminder_is_retried_unless_there_is_no_recipient() {
+		let notifier = RecordingNotifier::default();
+		let store =
+			store_with_repository(ExpectedPracticeFrequency::EveryDay, at(2024, 3, 1, 9)).await;
+
+		notifier.fail_with(Some(undeliverable));
+		let report = send_due_reminders(&store, &notifier, at(2024, 3, 10, 9)).await.unwrap();
+		assert_eq!(report, ReminderReport { failed: 1, ..Default::default() });
+
+		notifier.fail_with(None);
+		let report = send_due_reminders(&store, &notifier, at(2024, 3, 10, 10)).await.unwrap();
+		assert_eq!(report, ReminderReport { sent: 1, ..Default::default() });
+
+		notifier.fail_with(Some(missing_recipient));
+		let report = send_due_reminders(&store, &notifier, at(2024, 3, 11, 9)).await.unwrap();
+		assert_eq!(report, ReminderReport::default());
+		notifier.fail_with(None);
+		let report = send_due_reminders(&store, &notifier, at(2024, 3, 11, 10)).await.unwrap();
+		assert_eq!(report, ReminderReport { already_sent: 1, ..Default::default() });
+	}
+}
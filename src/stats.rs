@@ -0,0 +1,188 @@
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+
+use crate::{
+	models::Repository,
+	types::{ExpectedPracticeFrequency, RepositoryStatsResponse},
+};
+
+/// Index of the day, ISO week or month containing `date`, such that consecutive periods have
+/// consecutive indices
+fn period_index(frequency: &ExpectedPracticeFrequency, date: NaiveDate) -> i64 {
+	let days = date.num_days_from_ce() as i64;
+	match frequency {
+		ExpectedPracticeFrequency::EveryDay => days,
+		ExpectedPracticeFrequency::OnceAWeek =>
+			(days - date.weekday().num_days_from_monday() as i64) / 7,
+		ExpectedPracticeFrequency::OnceAMonth => date.year() as i64 * 12 + date.month0() as i64,
+	}
+}
+
+/// Compute practice statistics for a repository from the creation times of its submissions.
+///
+/// Streaks count consecutive days, weeks or months with at least one submission, depending on the
+/// repository's practice frequency. The current streak stays alive until a whole period passes
+/// without a submission, so practicing yesterday still counts towards today's streak.
+pub(crate) fn compute_stats(
+	repository: &Repository,
+	submission_times: &[DateTime<Utc>],
+	now: DateTime<Utc>,
+) -> RepositoryStatsResponse {
+	let frequency = &repository.expected_practice_frequency;
+
+	let mut active_days: Vec<NaiveDate> =
+		submission_times.iter().map(|time| time.date_naive()).collect();
+	active_days.sort();
+	active_days.dedup();
+
+	let mut periods: Vec<i64> =
+		active_days.iter().map(|day| period_index(frequency, *day)).collect();
+	periods.dedup();
+
+	let mut longest_streak = 0;
+	let mut streak = 0;
+	let mut previous = None;
+	for period in &periods {
+		streak = match previous {
+			Some(previous) if period - previous == 1 => streak + 1,
+			_ => 1,
+		};
+		longest_streak = longest_streak.max(streak);
+		previous = Some(*period);
+	}
+
+	let current_period = period_index(frequency, now.date_naive());
+	let current_streak = match periods.last() {
+		Some(last) if current_period - last <= 1 => streak,
+		_ => 0,
+	};
+
+	RepositoryStatsResponse {
+		repo_name: repository.repo_name.clone(),
+		expected_practice_frequency: frequency.clone(),
+		current_streak,
+		longest_streak,
+		total_active_days: active_days.len() as u64,
+		total_submissions: submission_times.len() as u64,
+		created_at: repository.created_at,
+		last_activity_at: submission_times.iter().max().copied().or(repository.created_at),
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use chrono::TimeZone;
+
+	use super::*;
+	use crate::models::{RepositoryProgress, RepositoryRelationships};
+
+	fn repository(frequency: ExpectedPracticeFrequency) -> Repository {
+		Repository {
+			id: None,
+			repo_name: "repo".to_string(),
+			repo_template: "rust-state-machine".to_string(),
+			tester_url: "https://tester.example.com".to_string(),
+			tester_version: None,
+			course_version: None,
+			test_ok: None,
+			relationships: RepositoryRelationships::default(),
+			expected_practice_frequency: frequency,
+			is_reminder_enabled: false,
+			progress: RepositoryProgress::default(),
+			created_at: Some(at(2024, 1, 1)),
+			last_submission_at: None,
+			archived_at: None,
+		}
+	}
+
+	fn at(year: i32, month: u32, day: u32) -> DateTime<Utc> {
+		Utc.with_ymd_and_hms(year, month, day, 12, 0, 0).unwrap()
+	}
+
+	fn day(year: i32, month: u32, day: u32) -> NaiveDate {
+		NaiveDate::from_ymd_opt(year, month, day).unwrap()
+	}
+
+	#[test]
+	fn weeks_start_on_monday() {
+		let week = |date| period_index(&ExpectedPracticeFrequency::OnceAWeek, date);
+
+		assert_eq!(week(day(2024, 1, 1)), week(day(2024, 1, 7)));
+		assert_eq!(week(day(2024, 1, 8)), week(day(2024, 1, 7)) + 1);
+		// The ISO week of 30 December 2024 runs into 2025
+		assert_eq!(week(day(2024, 12, 30)), week(day(2025, 1, 5)));
+		assert_eq!(week(day(2024, 12, 29)) + 1, week(day(2024, 12, 30)));
+	}
+
+	#[test]
+	fn months_are_consecutive_across_years() {
+		let month = |date| period_index(&ExpectedPracticeFrequency::OnceAMonth, date);
+
+		assert_eq!(month(day(2024, 1, 1)), month(day(2024, 1, 31)));
+		assert_eq!(month(day(2024, 2, 1)), month(day(2024, 1, 31)) + 1);
+		assert_eq!(month(day(2024, 1, 1)), month(day(2023, 12, 31)) + 1);
+	}
+
+	#[test]
+	fn daily_streaks_break_on_a_missed_day() {
+		let repository = repository(ExpectedPracticeFrequency::EveryDay);
+		let times = [
+			at(2024, 3, 1),
+			at(2024, 3, 2),
+			at(2024, 3, 2),
+			at(2024, 3, 3),
+			at(2024, 3, 5),
+			at(2024, 3, 6),
+		];
+
+		let stats = compute_stats(&repository, &times, at(2024, 3, 6));
+		assert_eq!(stats.current_streak, 2);
+		assert_eq!(stats.longest_streak, 3);
+		assert_eq!(stats.total_active_days, 5);
+		assert_eq!(stats.total_submissions, 6);
+		assert_eq!(stats.last_activity_at, Some(at(2024, 3, 6)));
+	}
+
+	#[test]
+	fn the_current_streak_survives_until_a_whole_period_is_missed() {
+		let repository = repository(ExpectedPracticeFrequency::EveryDay);
+		let times = [at(2024, 3, 5), at(2024, 3, 6)];
+
+		assert_eq!(compute_stats(&repository, &times, at(2024, 3, 7)).current_streak, 2);
+		let stats = compute_stats(&repository, &times, at(2024, 3, 8));
+		assert_eq!(stats.current_streak, 0);
+		assert_eq!(stats.longest_streak, 2);
+	}
+
+	#[test]
+	fn weekly_streaks_count_weeks_not_days() {
+		let repository = repository(ExpectedPracticeFrequency::OnceAWeek);
+		// Sunday, then the Monday after, then a week skipped
+		let times = [at(2024, 12, 29), at(2024, 12, 30), at(2025, 1, 2), at(2025, 1, 13)];
+
+		let stats = compute_stats(&repository, &times, at(2025, 1, 19));
+		assert_eq!(stats.current_streak, 1);
+		assert_eq!(stats.longest_streak, 2);
+		assert_eq!(stats.total_active_days, 4);
+	}
+
+	#[test]
+	fn monthly_streaks_run_across_years() {
+		let repository = repository(ExpectedPracticeFrequency::OnceAMonth);
+		let times = [at(2023, 11, 30), at(2023, 12, 1), at(2024, 1, 31)];
+
+		assert_eq!(compute_stats(&repository, &times, at(2024, 2, 29)).current_streak, 3);
+		let stats = compute_stats(&repository, &times, at(2024, 3, 1));
+		assert_eq!(stats.current_streak, 0);
+		assert_eq!(stats.longest_streak, 3);
+	}
+
+	#[test]
+	fn a_repository_without_submissions_has_no_streak() {
+		let repository = repository(ExpectedPracticeFrequency::EveryDay);
+
+		let stats = compute_stats(&repository, &[], at(2024, 3, 1));
+		assert_eq!((stats.current_streak, stats.longest_streak), (0, 0));
+		assert_eq!(stats.total_active_days, 0);
+		assert_eq!(stats.last_activity_at, repository.created_at);
+	}
+}
@@ -0,0 +1,112 @@
+use mongodb::bson::{doc, Document};
+use serde::Serialize;
+
+use crate::constants::{
+	COURSE_COLLECTION, COURSE_VERSION_COLLECTION, IDEMPOTENCY_COLLECTION, PENDING_REPO_COLLECTION,
+	REMINDER_COLLECTION, REPO_COLLECTION, SUBMISSION_COLLECTION, TESTER_NONCE_COLLECTION,
+};
+
+/// An index, either declared by the backend or found in the database
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub(crate) struct IndexDescription {
+	pub collection: String,
+	pub name: String,
+	pub keys: Document,
+	pub unique: bool,
+	/// Seconds after the date in the indexed field at which MongoDB deletes a document
+	pub expire_after_secs: Option<u64>,
+}
+
+impl std::fmt::Display for IndexDescription {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		write!(f, "{}.{} {}", self.collection, self.name, self.keys)?;
+		if self.unique {
+			write!(f, " unique")?;
+		}
+		if let Some(secs) = self.expire_after_secs {
+			write!(f, " expire after {}s", secs)?;
+		}
+		Ok(())
+	}
+}
+
+/// The indexes declared by the backend compared with those in the database. An index whose name
+/// matches a declared one but whose keys or options differ is reported as both missing and extra.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct IndexReport {
+	/// Declared indexes that exist as declared
+	pub present: Vec<IndexDescription>,
+	/// Declared indexes that do not exist, for example because existing duplicates prevent a
+	/// unique index from being built
+	pub missing: Vec<IndexDescription>,
+	/// Indexes in the database that the backend does not declare
+	pub extra: Vec<IndexDescription>,
+}
+
+impl IndexReport {
+	/// Whether the database has exactly the declared indexes
+	pub fn is_complete(&self) -> bool {
+		self.missing.is_empty() && self.extra.is_empty()
+	}
+}
+
+fn index(collection: &str, name: &str, keys: Document, unique: bool) -> IndexDescription {
+	IndexDescription {
+		collection: collection.to_string(),
+		name: name.to_string(),
+		keys,
+		unique,
+		expire_after_secs: None,
+	}
+}
+
+/// An index making MongoDB delete the documents of `collection` once their `expires_at` has passed
+fn expiry_index(collection: &str) -> IndexDescription {
+	IndexDescription {
+		expire_after_secs: Some(0),
+		..index(collection, "expires_at_ttl", doc! { "expires_at": 1 }, false)
+	}
+}
+
+/// Every index the backend relies on, to keep lookups fast and to make duplicates impossible
+pub(crate) fn declared_indexes() -> Vec<IndexDescription> {
+	vec![
+		index(REPO_COLLECTION, "repo_name_unique", doc! { "repo_name": 1 }, true),
+		index(REPO_COLLECTION, "owner", doc! { "relationships.user.id": 1 }, false),
+		index(COURSE_COLLECTION, "slug_unique", doc! { "slug": 1 }, true),
+		index(SUBMISSION_COLLECTION, "logstream_id_unique", doc! { "logstream_id": 1 }, true),
+		index(
+			SUBMISSION_COLLECTION,
+			"repo_name_created_at",
+			doc! { "repo_name": 1, "created_at": 1 },
+			false,
+		),
+		index(
+			COURSE_VERSION_COLLECTION,
+			"course_id_version_unique",
+			doc! { "course_id": 1, "version": 1 },
+			true,
+		),
+		index(PENDING_REPO_COLLECTION, "repo_name_unique", doc! { "repo_name": 1 }, true),
+		index(
+			TESTER_NONCE_COLLECTION,
+			"tester_id_signature_unique",
+			doc! { "tester_id": 1, "signature": 1 },
+			true,
+		),
+		expiry_index(TESTER_NONCE_COLLECTION),
+		index(
+			REMINDER_COLLECTION,
+			"repo_name_period_unique",
+			doc! { "repo_name": 1, "period": 1 },
+			true,
+		),
+		index(
+			IDEMPOTENCY_COLLECTION,
+			"user_id_endpoint_key_unique",
+			doc! { "user_id": 1, "endpoint": 1, "key": 1 },
+			true,
+		),
+		expiry_index(IDEMPOTENCY_COLLECTION),
+	]
+}
@@ -0,0 +1,778 @@
+use std::{
+	collections::HashMap,
+	path::Path,
+	sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use log::warn;
+use mongodb::bson::oid::ObjectId;
+use serde::Deserialize;
+
+use super::{IndexReport, Store};
+use crate::{
+	errors::DbError,
+	models::{
+		Course, CourseVersion, IdempotencyRecord, PendingRepository, RelatedDocument, Relationship,
+		ReminderRecord, Repository, RepositoryProgress, RepositoryRelationships, Submission,
+		TesterNonce, User,
+	},
+	types::{
+		CourseFilter, CourseStatus, DocumentType, SubmissionStatus, UpdateRepoRequest,
+		UpdateUserRequest,
+	},
+};
+
+#[derive(Default)]
+struct Collections {
+	courses: HashMap<ObjectId, Course>,
+	course_versions: Vec<CourseVersion>,
+	repositories: HashMap<ObjectId, Repository>,
+	users: HashMap<ObjectId, User>,
+	submissions: Vec<Submission>,
+	pending_repositories: HashMap<String, PendingRepository>,
+	tester_nonces: Vec<TesterNonce>,
+	idempotency_keys: Vec<IdempotencyRecord>,
+	reminders: Vec<ReminderRecord>,
+}
+
+/// A [`Store`] that keeps every collection in memory. Used for local development and tests where
+/// no MongoDB instance is available. Clones share the same underlying data.
+#[derive(Clone, Default)]
+pub(crate) struct InMemoryStore {
+	inner: Arc<RwLock<Collections>>,
+}
+
+/// Initial data for an [`InMemoryStore`], loaded from a JSON file
+#[derive(Deserialize, Default)]
+struct Seed {
+	#[serde(default)]
+	courses: Vec<Course>,
+	#[serde(default)]
+	users: Vec<SeedUser>,
+}
+
+#[derive(Deserialize)]
+struct SeedUser {
+	#[serde(rename = "_id")]
+	id: ObjectId,
+	#[serde(flatten)]
+	user: User,
+}
+
+impl InMemoryStore {
+	pub(crate) fn new() -> Self {
+		Self::default()
+	}
+
+	/// Create a store pre-populated with the courses and users from a JSON seed file
+	pub(crate) fn from_seed_file(path: impl AsRef<Path>) -> std::io::Result<Self> {
+		let contents = std::fs::read_to_string(path)?;
+		let seed: Seed = serde_json::from_str(&contents)?;
+
+		let store = Self::new();
+		for course in seed.courses {
+			store.insert_course(course);
+		}
+		for SeedUser { id, user } in seed.users {
+			store.insert_user(id, user);
+		}
+
+		Ok(store)
+	}
+
+	/// Add a course to the store, replacing any course with the same id
+	pub(crate) fn insert_course(&self, course: Course) {
+		self.write().courses.insert(course.id, course);
+	}
+
+	/// Add a user to the store, replacing any user with the same id
+	pub(crate) fn insert_user(&self, id: ObjectId, user: User) {
+		self.write().users.insert(id, user);
+	}
+
+	fn read(&self) -> RwLockReadGuard<'_, Collections> {
+		self.inner.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+	}
+
+	fn write(&self) -> RwLockWriteGuard<'_, Collections> {
+		self.inner.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+	}
+}
+
+#[async_trait]
+impl Store for InMemoryStore {
+	async fn get_course(&self, id: &ObjectId) -> Result<Option<Course>, DbError> {
+		Ok(self.read().courses.get(id).cloned())
+	}
+
+	async fn get_course_by_slug(&self, slug: &str) -> Result<Option<Course>, DbError> {
+		Ok(self.read().courses.values().find(|course| course.slug == slug).cloned())
+	}
+
+	async fn list_courses(
+		&self,
+		filter: &CourseFilter,
+		offset: u64,
+		limit: u64,
+	) -> Result<Vec<Course>, DbError> {
+		let query = filter.query.as_deref().map(str::to_lowercase);
+		let matches = |course: &&Course| {
+			let matches_query = query.as_deref().is_none_or(|query| {
+				[&course.name, &course.title, &course.author.name]
+					.iter()
+					.any(|field| field.to_lowercase().contains(query))
+			});
+			let matches_language = filter.language.as_deref().is_none_or(|language| {
+				course.language.as_deref().is_some_and(|l| l.eq_ignore_ascii_case(language))
+			});
+			let matches_difficulty =
+				filter.difficulty.is_none_or(|difficulty| course.difficulty == Some(difficulty));
+			let matches_tags = filter.tags.iter().all(|tag| course.tags.contains(tag));
+			let matches_status = filter.include_drafts || course.status == CourseStatus::Published;
+
+			matches_query &&
+				matches_language &&
+				matches_difficulty &&
+				matches_tags && matches_status
+		};
+
+		let collections = self.read();
+		let mut courses: Vec<&Course> = collections.courses.values().filter(matches).collect();
+		courses.sort_by(|a, b| a.title.cmp(&b.title).then(a.id.cmp(&b.id)));
+
+		Ok(courses
+			.into_iter()
+			.skip(offset as usize)
+			.take(limit as usize)
+			.cloned()
+			.collect())
+	}
+
+	async fn create_course(&self, course: Course) -> Result<(), DbError> {
+		let mut collections = self.write();
+		if collections.courses.values().any(|existing| existing.slug == course.slug) {
+			return Err(DbError::Conflict(format!(
+				"A course with slug `{}` already exists",
+				course.slug
+			)));
+		}
+		collections.courses.insert(course.id, course);
+		Ok(())
+	}
+
+	async fn replace_course(
+		&self,
+		course: &Course,
+		previous: CourseVersion,
+	) -> Result<bool, DbError> {
+		let mut collections = self.write();
+		match collections.courses.get_mut(&course.id) {
+			Some(stored) if stored.version == previous.version => *stored = course.clone(),
+			_ => return Ok(false),
+		}
+
+		if !collections
+			.course_versions
+			.iter()
+			.any(|seen| seen.course_id == previous.course_id && seen.version == previous.version)
+		{
+			collections.course_versions.push(previous);
+		}
+		Ok(true)
+	}
+
+	async fn set_course_status(
+		&self,
+		id: &ObjectId,
+		status: CourseStatus,
+	) -> Result<Option<Course>, DbError> {
+		let mut collections = self.write();
+		Ok(collections.courses.get_mut(id).map(|course| {
+			course.status = status;
+			course.clone()
+		}))
+	}
+
+	async fn list_course_versions(
+		&self,
+		course_id: &ObjectId,
+	) -> Result<Vec<CourseVersion>, DbError> {
+		Ok(self
+			.read()
+			.course_versions
+			.iter()
+			.filter(|version| &version.course_id == course_id)
+			.cloned()
+			.collect())
+	}
+
+	async fn get_course_version(
+		&self,
+		course_id: &ObjectId,
+		version: &str,
+	) -> Result<Option<CourseVersion>, DbError> {
+		Ok(self
+			.read()
+			.course_versions
+			.iter()
+			.find(|seen| &seen.course_id == course_id && seen.version == version)
+			.cloned())
+	}
+
+	async fn get_related_documents(
+		&self,
+		document_type: DocumentType,
+		ids: &[ObjectId],
+	) -> Result<Vec<RelatedDocument>, DbError> {
+		let collections = self.read();
+
+		Ok(ids
+			.iter()
+			.filter_map(|id| match document_type {
+				DocumentType::Repository =>
+					collections.repositories.get(id).cloned().map(RelatedDocument::Repository),
+				DocumentType::User =>
+					collections.users.get(id).cloned().map(|user| RelatedDocument::User(*id, user)),
+				DocumentType::Course =>
+					collections.courses.get(id).cloned().map(RelatedDocument::Course),
+			})
+			.collect())
+	}
+
+	async fn create_repository(
+		&self,
+		mut repository: Repository,
+		user_id: &ObjectId,
+	) -> Result<ObjectId, DbError> {
+		let mut collections = self.write();
+		if collections
+			.repositories
+			.values()
+			.any(|existing| existing.repo_name == repository.repo_name)
+		{
+			return Err(DbError::Conflict(format!(
+				"Repository `{}` already exists",
+				repository.repo_name
+			)));
+		}
+		let Some(user) = collections.users.get_mut(user_id) else {
+			return Err(DbError::NotFound(format!("User `{}` not found", user_id)));
+		};
+
+		let id = *repository.id.get_or_insert_with(ObjectId::new);
+		user.repositories.push(Relationship { id, r#type: DocumentType::Repository });
+		collections.repositories.insert(id, repository);
+		Ok(id)
+	}
+
+	async fn get_repository(&self, repo_name: &str) -> Result<Option<Repository>, DbError> {
+		Ok(self
+			.read()
+			.repositories
+			.values()
+			.find(|repo| repo.repo_name == repo_name)
+			.cloned())
+	}
+
+	async fn update_repository(
+		&self,
+		repo_name: &str,
+		update: &UpdateRepoRequest,
+	) -> Result<Option<Repository>, DbError> {
+		let mut collections = self.write();
+		let Some(repository) =
+			collections.repositories.values_mut().find(|repo| repo.repo_name == repo_name)
+		else {
+			return Ok(None);
+		};
+
+		if let Some(expected_practice_frequency) = &update.expected_practice_frequency {
+			repository.expected_practice_frequency = expected_practice_frequency.clone();
+		}
+		if let Some(is_reminder_enabled) = update.is_reminder_enabled {
+			repository.is_reminder_enabled = is_reminder_enabled;
+		}
+		if let Some(relationships) = &update.relationships {
+			repository.relationships = relationships.clone();
+		}
+
+		Ok(Some(repository.clone()))
+	}
+
+	async fn record_test_result(
+		&self,
+		repo_name: &str,
+		test_ok: bool,
+	) -> Result<Option<Repository>, DbError> {
+		let mut collections = self.write();
+		let repository =
+			collections.repositories.values_mut().find(|repo| repo.repo_name == repo_name);
+
+		Ok(repository.map(|repository| {
+			repository.test_ok = Some(test_ok);
+			repository.clone()
+		}))
+	}
+
+	async fn set_repository_course_version(
+		&self,
+		repo_name: &str,
+		course: &Course,
+	) -> Result<Option<Repository>, DbError> {
+		let mut collections = self.write();
+		let repository =
+			collections.repositories.values_mut().find(|repo| repo.repo_name == repo_name);
+
+		Ok(repository.map(|repository| {
+			repository.course_version = Some(course.version.clone());
+			repository.tester_url = course.tester_url.clone();
+			repository.tester_version = course.tester_version.clone();
+			repository.clone()
+		}))
+	}
+
+	async fn set_repository_progress(
+		&self,
+		repo_name: &str,
+		progress: &RepositoryProgress,
+	) -> Result<bool, DbError> {
+		let mut collections = self.write();
+		let repository =
+			collections.repositories.values_mut().find(|repo| repo.repo_name == repo_name);
+
+		Ok(repository.map(|repository| repository.progress = progress.clone()).is_some())
+	}
+
+	async fn set_repository_last_submission_at(
+		&self,
+		repo_name: &str,
+		at: DateTime<Utc>,
+	) -> Result<(), DbError> {
+		let mut collections = self.write();
+		if let Some(repository) =
+			collections.repositories.values_mut().find(|repo| repo.repo_name == repo_name)
+		{
+			repository.last_submission_at = Some(at);
+		}
+		Ok(())
+	}
+
+	async fn add_repository_to_user(
+		&self,
+		user_id: &ObjectId,
+		repo_id: ObjectId,
+	) -> Result<(), DbError> {
+		let mut collections = self.write();
+		match collections.users.get_mut(user_id) {
+			Some(user) => {
+				let relationship = Relationship { id: repo_id, r#type: DocumentType::Repository };
+				if !user.repositories.contains(&relationship) {
+					user.repositories.push(relationship);
+				}
+			},
+			None => warn!("User `{}` not found, repository `{}` not linked", user_id, repo_id),
+		}
+		Ok(())
+	}
+
+	async fn reset_repository(
+		&self,
+		repo_name: &str,
+		progress: &RepositoryProgress,
+	) -> Result<Option<Repository>, DbError> {
+		let mut collections = self.write();
+		let Some(repository) = collections
+			.repositories
+			.values_mut()
+			.find(|repo| repo.repo_name == repo_name && repo.archived_at.is_none())
+		else {
+			return Ok(None);
+		};
+		repository.progress = progress.clone();
+		repository.test_ok = None;
+		let repository = repository.clone();
+
+		for submission in &mut collections.submissions {
+			if submission.repo_name == repo_name {
+				submission.previous_attempt = true;
+			}
+		}
+		Ok(Some(repository))
+	}
+
+	async fn archive_repository(
+		&self,
+		repo_name: &str,
+		archived_at: DateTime<Utc>,
+	) -> Result<Option<Repository>, DbError> {
+		let mut collections = self.write();
+		let Some(repository) = collections
+			.repositories
+			.values_mut()
+			.find(|repo| repo.repo_name == repo_name && repo.archived_at.is_none())
+		else {
+			return Ok(None);
+		};
+		repository.archived_at = Some(archived_at);
+		let repository = repository.clone();
+
+		if let (Some(id), Some(owner)) = (repository.id, &repository.relationships.user) {
+			if let Some(user) = collections.users.get_mut(&owner.id) {
+				user.repositories.retain(|relationship| relationship.id != id);
+			}
+		}
+		Ok(Some(repository))
+	}
+
+	async fn restore_repository(&self, repo_name: &str) -> Result<Option<Repository>, DbError> {
+		let mut collections = self.write();
+		let Some(repository) = collections
+			.repositories
+			.values_mut()
+			.find(|repo| repo.repo_name == repo_name && repo.archived_at.is_some())
+		else {
+			return Ok(None);
+		};
+		repository.archived_at = None;
+		let repository = repository.clone();
+
+		if let (Some(id), Some(owner)) = (repository.id, &repository.relationships.user) {
+			if let Some(user) = collections.users.get_mut(&owner.id) {
+				let relationship = Relationship { id, r#type: DocumentType::Repository };
+				if !user.repositories.contains(&relationship) {
+					user.repositories.push(relationship);
+				}
+			}
+		}
+		Ok(Some(repository))
+	}
+
+	async fn list_archived_repositories(
+		&self,
+		before: DateTime<Utc>,
+	) -> Result<Vec<Repository>, DbError> {
+		Ok(self
+			.read()
+			.repositories
+			.values()
+			.filter(|repo| repo.archived_at.is_some_and(|archived_at| archived_at < before))
+			.cloned()
+			.collect())
+	}
+
+	async fn purge_repository(&self, repo_name: &str) -> Result<(), DbError> {
+		let mut collections = self.write();
+		collections.repositories.retain(|_, repo| repo.repo_name != repo_name);
+		collections.submissions.retain(|submission| submission.repo_name != repo_name);
+		collections.reminders.retain(|reminder| reminder.repo_name != repo_name);
+		Ok(())
+	}
+
+	async fn insert_submission(&self, submission: Submission) -> Result<(), DbError> {
+		self.write().submissions.push(submission);
+		Ok(())
+	}
+
+	async fn get_submission(&self, logstream_id: &str) -> Result<Option<Submission>, DbError> {
+		Ok(self
+			.read()
+			.submissions
+			.iter()
+			.find(|submission| submission.logstream_id == logstream_id)
+			.cloned())
+	}
+
+	async fn list_submissions(
+		&self,
+		repo_name: &str,
+		offset: u64,
+		limit: u64,
+	) -> Result<Vec<Submission>, DbError> {
+		// Submissions are appended as they are created, so iterating in reverse is newest first
+		Ok(self
+			.read()
+			.submissions
+			.iter()
+			.rev()
+			.filter(|submission| submission.repo_name == repo_name)
+			.skip(offset as usize)
+			.take(limit as usize)
+			.cloned()
+			.collect())
+	}
+
+	async fn list_submission_times(&self, repo_name: &str) -> Result<Vec<DateTime<Utc>>, DbError> {
+		let mut times: Vec<_> = self
+			.read()
+			.submissions
+			.iter()
+			.filter(|submission| submission.repo_name == repo_name)
+			.map(|submission| submission.created_at)
+			.collect();
+		times.sort();
+		Ok(times)
+	}
+
+	async fn update_submission_progress(
+		&self,
+		submission: &Submission,
+		expected: SubmissionStatus,
+	) -> Result<bool, DbError> {
+		let mut collections = self.write();
+		let stored = collections.submissions.iter_mut().find(|stored| {
+			stored.logstream_id == submission.logstream_id && stored.status == expected
+		});
+
+		Ok(stored
+			.map(|stored| {
+				stored.status = submission.status;
+				stored.stages = submission.stages.clone();
+				stored.started_at = submission.started_at;
+				stored.finished_at = submission.finished_at;
+			})
+			.is_some())
+	}
+
+	async fn insert_pending_repository(&self, pending: PendingRepository) -> Result<(), DbError> {
+		let mut collections = self.write();
+		if collections.pending_repositories.contains_key(&pending.repo_name) {
+			return Err(DbError::Conflict(format!(
+				"Repository `{}` is already being created",
+				pending.repo_name
+			)));
+		}
+		collections.pending_repositories.insert(pending.repo_name.clone(), pending);
+		Ok(())
+	}
+
+	async fn delete_pending_repository(&self, repo_name: &str) -> Result<(), DbError> {
+		self.write().pending_repositories.remove(repo_name);
+		Ok(())
+	}
+
+	async fn list_pending_repositories(
+		&self,
+		before: DateTime<Utc>,
+	) -> Result<Vec<PendingRepository>, DbError> {
+		Ok(self
+			.read()
+			.pending_repositories
+			.values()
+			.filter(|pending| pending.created_at < before)
+			.cloned()
+			.collect())
+	}
+
+	async fn get_user(&self, id: &ObjectId) -> Result<Option<User>, DbError> {
+		Ok(self.read().users.get(id).cloned())
+	}
+
+	async fn update_user(
+		&self,
+		id: &ObjectId,
+		update: &UpdateUserRequest,
+	) -> Result<Option<User>, DbError> {
+		let mut collections = self.write();
+		let Some(user) = collections.users.get_mut(id) else {
+			return Ok(None);
+		};
+
+		if let Some(name) = &update.name {
+			user.name = name.clone();
+		}
+		if let Some(email) = &update.email {
+			user.email = Some(email.clone());
+		}
+
+		Ok(Some(user.clone()))
+	}
+
+	async fn list_user_repositories(&self, user_id: &ObjectId) -> Result<Vec<Repository>, DbError> {
+		Ok(self
+			.read()
+			.repositories
+			.values()
+			.filter(|repo| repo.relationships.user.as_ref().is_some_and(|user| &user.id == user_id))
+			.filter(|repo| repo.archived_at.is_none())
+			.cloned()
+			.collect())
+	}
+
+	async fn list_reminder_enabled_repositories(&self) -> Result<Vec<Repository>, DbError> {
+		Ok(self
+			.read()
+			.repositories
+			.values()
+			.filter(|repo| repo.is_reminder_enabled && repo.archived_at.is_none())
+			.cloned()
+			.collect())
+	}
+
+	async fn record_reminder(&self, reminder: ReminderRecord) -> Result<bool, DbError> {
+		let mut collections = self.write();
+		if collections
+			.reminders
+			.iter()
+			.any(|sent| sent.repo_name == reminder.repo_name && sent.period == reminder.period)
+		{
+			return Ok(false);
+		}
+		collections.reminders.push(reminder);
+		Ok(true)
+	}
+
+	async fn delete_reminder(&self, repo_name: &str, period: &str) -> Result<(), DbError> {
+		self.write()
+			.reminders
+			.retain(|sent| !(sent.repo_name == repo_name && sent.period == period));
+		Ok(())
+	}
+
+	async fn record_tester_nonce(&self, nonce: TesterNonce) -> Result<bool, DbError> {
+		let now = Utc::now();
+		let mut collections = self.write();
+		collections.tester_nonces.retain(|seen| seen.expires_at > now);
+
+		if collections
+			.tester_nonces
+			.iter()
+			.any(|seen| seen.tester_id == nonce.tester_id && seen.signature == nonce.signature)
+		{
+			return Ok(false);
+		}
+		collections.tester_nonces.push(nonce);
+		Ok(true)
+	}
+
+	async fn reserve_idempotency_key(
+		&self,
+		record: IdempotencyRecord,
+	) -> Result<Option<IdempotencyRecord>, DbError> {
+		let now = Utc::now();
+		let mut collections = self.write();
+		collections.idempotency_keys.retain(|seen| seen.expires_at > now);
+
+		if let Some(existing) = collections.idempotency_keys.iter().find(|seen| {
+			seen.user_id == record.user_id &&
+				seen.endpoint == record.endpoint &&
+				seen.key == record.key
+		}) {
+			return Ok(Some(existing.clone()));
+		}
+		collections.idempotency_keys.push(record);
+		Ok(None)
+	}
+
+	async fn complete_idempotency_key(
+		&self,
+		user_id: &ObjectId,
+		endpoint: &str,
+		key: &str,
+		response: &str,
+	) -> Result<(), DbError> {
+		let mut collections = self.write();
+		if let Some(record) = collections
+			.idempotency_keys
+			.iter_mut()
+			.find(|seen| &seen.user_id == user_id && seen.endpoint == endpoint && seen.key == key)
+		{
+			record.response = Some(response.to_string());
+		}
+		Ok(())
+	}
+
+	async fn release_idempotency_key(
+		&self,
+		user_id: &ObjectId,
+		endpoint: &str,
+		key: &str,
+	) -> Result<(), DbError> {
+		self.write().idempotency_keys.retain(|seen| {
+			!(&seen.user_id == user_id && seen.endpoint == endpoint && seen.key == key)
+		});
+		Ok(())
+	}
+
+	async fn find_recent_submission(
+		&self,
+		repo_name: &str,
+		commit_sha: &str,
+		since: DateTime<Utc>,
+	) -> Result<Option<Submission>, DbError> {
+		Ok(self
+			.read()
+			.submissions
+			.iter()
+			.filter(|submission| {
+				submission.repo_name == repo_name &&
+					submission.commit_sha == commit_sha &&
+					!submission.previous_attempt &&
+					submission.created_at > since
+			})
+			.max_by_key(|submission| submission.created_at)
+			.cloned())
+	}
+
+	async fn ensure_indexes(&self) -> Result<IndexReport, DbError> {
+		self.index_report().await
+	}
+
+	async fn index_report(&self) -> Result<IndexReport, DbError> {
+		// Lookups scan the collections and uniqueness is checked on insert, so there is nothing to
+		// report
+		Ok(IndexReport::default())
+	}
+
+	async fn list_repositories(&self) -> Result<Vec<Repository>, DbError> {
+		Ok(self.read().repositories.values().cloned().collect())
+	}
+
+	async fn list_users(&self) -> Result<Vec<(ObjectId, User)>, DbError> {
+		Ok(self.read().users.iter().map(|(id, user)| (*id, user.clone())).collect())
+	}
+
+	async fn list_users_with_legacy_relationships(&self) -> Result<Vec<ObjectId>, DbError> {
+		// Seeded users are converted to the current format when they are loaded
+		Ok(vec![])
+	}
+
+	async fn set_repository_relationships(
+		&self,
+		repo_name: &str,
+		relationships: &RepositoryRelationships,
+	) -> Result<(), DbError> {
+		let mut collections = self.write();
+		if let Some(repository) =
+			collections.repositories.values_mut().find(|repo| repo.repo_name == repo_name)
+		{
+			repository.relationships = relationships.clone();
+		}
+		Ok(())
+	}
+
+	async fn set_user_relationships(
+		&self,
+		id: &ObjectId,
+		repositories: &[Relationship],
+		relationships: &[Relationship],
+	) -> Result<(), DbError> {
+		if let Some(user) = self.write().users.get_mut(id) {
+			user.repositories = repositories.to_vec();
+			user.relationships = relationships.to_vec();
+		}
+		Ok(())
+	}
+
+	async fn set_course_relationships(
+		&self,
+		id: &ObjectId,
+		relationships: &[Relationship],
+	) -> Result<(), DbError> {
+		if let Some(course) = self.write().courses.get_mut(id) {
+			course.relationships = relationships.to_vec();
+		}
+		Ok(())
+	}
+}
@@ -0,0 +1,332 @@
+mod indexes;
+mod memory;
+mod mongo;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+
+use crate::{
+	errors::DbError,
+	models::{
+		Course, CourseVersion, IdempotencyRecord, PendingRepository, RelatedDocument, Relationship,
+		ReminderRecord, Repository, RepositoryProgress, RepositoryRelationships, Submission,
+		TesterNonce, User,
+	},
+	types::{
+		CourseFilter, CourseStatus, DocumentType, SubmissionStatus, UpdateRepoRequest,
+		UpdateUserRequest,
+	},
+};
+
+pub(crate) use indexes::IndexReport;
+pub(crate) use memory::InMemoryStore;
+pub(crate) use mongo::MongoStore;
+
+/// Persistence layer for courses, repositories, users and submissions. Handlers only talk to the
+/// database through this trait so the backing storage can be swapped at startup.
+#[async_trait]
+pub(crate) trait Store: Send + Sync {
+	/// Fetch a course by its id
+	async fn get_course(&self, id: &ObjectId) -> Result<Option<Course>, DbError>;
+
+	/// Fetch a course by its slug
+	async fn get_course_by_slug(&self, slug: &str) -> Result<Option<Course>, DbError>;
+
+	/// List the courses matching `filter`, ordered by title, skipping the first `offset`
+	async fn list_courses(
+		&self,
+		filter: &CourseFilter,
+		offset: u64,
+		limit: u64,
+	) -> Result<Vec<Course>, DbError>;
+
+	/// Insert a new course. Fails with a conflict if a course with the same slug exists.
+	async fn create_course(&self, course: Course) -> Result<(), DbError>;
+
+	/// Replace a course with a new version and archive the version it replaces, `previous`,
+	/// provided the stored version is still `previous.version`. Both happen or neither. Returns
+	/// `false` if no such course exists or it has been updated in the meantime. Archiving the same
+	/// version twice keeps the first copy.
+	async fn replace_course(
+		&self,
+		course: &Course,
+		previous: CourseVersion,
+	) -> Result<bool, DbError>;
+
+	/// Set whether a course is published. Returns the updated course, or `None` if no course with
+	/// that id exists.
+	async fn set_course_status(
+		&self,
+		id: &ObjectId,
+		status: CourseStatus,
+	) -> Result<Option<Course>, DbError>;
+
+	/// List the archived versions of a course, oldest first
+	async fn list_course_versions(
+		&self,
+		course_id: &ObjectId,
+	) -> Result<Vec<CourseVersion>, DbError>;
+
+	/// Fetch an archived version of a course. Returns `None` if that version was never archived,
+	/// including when it is the current version.
+	async fn get_course_version(
+		&self,
+		course_id: &ObjectId,
+		version: &str,
+	) -> Result<Option<CourseVersion>, DbError>;
+
+	/// Fetch the documents of a single type with the given ids, from the collection of that type.
+	/// Ids that do not exist are skipped.
+	async fn get_related_documents(
+		&self,
+		document_type: DocumentType,
+		ids: &[ObjectId],
+	) -> Result<Vec<RelatedDocument>, DbError>;
+
+	/// Insert a repository and add it to its owner's repositories in a single transaction. Fails
+	/// without writing anything if the owner does not exist. Returns the id of the new document.
+	async fn create_repository(
+		&self,
+		repository: Repository,
+		user_id: &ObjectId,
+	) -> Result<ObjectId, DbError>;
+
+	/// Fetch a repository by its name
+	async fn get_repository(&self, repo_name: &str) -> Result<Option<Repository>, DbError>;
+
+	/// Apply an update to a repository. Returns the updated repository, or `None` if no repository
+	/// with that name exists.
+	async fn update_repository(
+		&self,
+		repo_name: &str,
+		update: &UpdateRepoRequest,
+	) -> Result<Option<Repository>, DbError>;
+
+	/// Record the outcome of a test run on a repository. Returns the updated repository, or `None`
+	/// if no repository with that name exists.
+	async fn record_test_result(
+		&self,
+		repo_name: &str,
+		test_ok: bool,
+	) -> Result<Option<Repository>, DbError>;
+
+	/// Move a repository onto a version of its course, pinning the tester of that version. Returns
+	/// the updated repository, or `None` if no repository with that name exists.
+	async fn set_repository_course_version(
+		&self,
+		repo_name: &str,
+		course: &Course,
+	) -> Result<Option<Repository>, DbError>;
+
+	/// Replace the stage progress of a repository. Returns `false` if no repository with that name
+	/// exists.
+	async fn set_repository_progress(
+		&self,
+		repo_name: &str,
+		progress: &RepositoryProgress,
+	) -> Result<bool, DbError>;
+
+	/// Set the time of the latest submission to a repository
+	async fn set_repository_last_submission_at(
+		&self,
+		repo_name: &str,
+		at: DateTime<Utc>,
+	) -> Result<(), DbError>;
+
+	/// Add a repository to the list of repositories owned by a user
+	async fn add_repository_to_user(
+		&self,
+		user_id: &ObjectId,
+		repo_id: ObjectId,
+	) -> Result<(), DbError>;
+
+	/// Restart the course on a repository: replace its stage progress, clear its test result and
+	/// mark its submissions as a previous attempt. Returns the updated repository, or `None` if no
+	/// unarchived repository with that name exists.
+	async fn reset_repository(
+		&self,
+		repo_name: &str,
+		progress: &RepositoryProgress,
+	) -> Result<Option<Repository>, DbError>;
+
+	/// Archive a repository and remove it from its owner's repositories. Returns the archived
+	/// repository, or `None` if no unarchived repository with that name exists.
+	async fn archive_repository(
+		&self,
+		repo_name: &str,
+		archived_at: DateTime<Utc>,
+	) -> Result<Option<Repository>, DbError>;
+
+	/// Restore an archived repository and add it back to its owner's repositories. Returns the
+	/// restored repository, or `None` if no archived repository with that name exists.
+	async fn restore_repository(&self, repo_name: &str) -> Result<Option<Repository>, DbError>;
+
+	/// List repositories archived before `before`
+	async fn list_archived_repositories(
+		&self,
+		before: DateTime<Utc>,
+	) -> Result<Vec<Repository>, DbError>;
+
+	/// Delete a repository along with its submissions and reminder records
+	async fn purge_repository(&self, repo_name: &str) -> Result<(), DbError>;
+
+	/// Insert a submission
+	async fn insert_submission(&self, submission: Submission) -> Result<(), DbError>;
+
+	/// Fetch a submission by its logstream id
+	async fn get_submission(&self, logstream_id: &str) -> Result<Option<Submission>, DbError>;
+
+	/// List the submissions of a repository, newest first, skipping the first `offset`
+	async fn list_submissions(
+		&self,
+		repo_name: &str,
+		offset: u64,
+		limit: u64,
+	) -> Result<Vec<Submission>, DbError>;
+
+	/// List the creation times of every submission to a repository, oldest first
+	async fn list_submission_times(&self, repo_name: &str) -> Result<Vec<DateTime<Utc>>, DbError>;
+
+	/// Overwrite the status, stage results and timestamps of a submission with those of
+	/// `submission`, provided its stored status is still `expected`. Returns `false` if no such
+	/// submission exists or its status has changed in the meantime.
+	async fn update_submission_progress(
+		&self,
+		submission: &Submission,
+		expected: SubmissionStatus,
+	) -> Result<bool, DbError>;
+
+	/// Record that the creation of a repository has started
+	async fn insert_pending_repository(&self, pending: PendingRepository) -> Result<(), DbError>;
+
+	/// Remove the pending marker for a repository once its creation has completed or been rolled
+	/// back
+	async fn delete_pending_repository(&self, repo_name: &str) -> Result<(), DbError>;
+
+	/// List pending repositories created before `before`
+	async fn list_pending_repositories(
+		&self,
+		before: DateTime<Utc>,
+	) -> Result<Vec<PendingRepository>, DbError>;
+
+	/// Fetch a user by id
+	async fn get_user(&self, id: &ObjectId) -> Result<Option<User>, DbError>;
+
+	/// Apply an update to a user's profile. Returns the updated user, or `None` if no user with
+	/// that id exists.
+	async fn update_user(
+		&self,
+		id: &ObjectId,
+		update: &UpdateUserRequest,
+	) -> Result<Option<User>, DbError>;
+
+	/// List the repositories owned by a user, leaving out archived ones
+	async fn list_user_repositories(&self, user_id: &ObjectId) -> Result<Vec<Repository>, DbError>;
+
+	/// List every unarchived repository that has practice reminders enabled
+	async fn list_reminder_enabled_repositories(&self) -> Result<Vec<Repository>, DbError>;
+
+	/// Record that a reminder is being sent for a repository and period. Returns `false` if one has
+	/// already been recorded for the same period.
+	async fn record_reminder(&self, reminder: ReminderRecord) -> Result<bool, DbError>;
+
+	/// Forget a recorded reminder, so that it is sent again on the next pass
+	async fn delete_reminder(&self, repo_name: &str, period: &str) -> Result<(), DbError>;
+
+	/// Remember the signature of a tester callback. Returns `false` if the same signature has
+	/// already been recorded and has not yet expired, meaning the request is a replay.
+	async fn record_tester_nonce(&self, nonce: TesterNonce) -> Result<bool, DbError>;
+
+	/// Record that a request with an idempotency key is being processed. If an unexpired record for
+	/// the same user, endpoint and key exists, it is returned and nothing is written. An expired
+	/// record is replaced, even if the database has not removed it yet.
+	async fn reserve_idempotency_key(
+		&self,
+		record: IdempotencyRecord,
+	) -> Result<Option<IdempotencyRecord>, DbError>;
+
+	/// Store the response to a request made with an idempotency key
+	async fn complete_idempotency_key(
+		&self,
+		user_id: &ObjectId,
+		endpoint: &str,
+		key: &str,
+		response: &str,
+	) -> Result<(), DbError>;
+
+	/// Forget an idempotency key whose request failed, so that it can be retried
+	async fn release_idempotency_key(
+		&self,
+		user_id: &ObjectId,
+		endpoint: &str,
+		key: &str,
+	) -> Result<(), DbError>;
+
+	/// Fetch the latest submission of `commit_sha` to a repository made after `since`, leaving out
+	/// submissions from previous attempts
+	async fn find_recent_submission(
+		&self,
+		repo_name: &str,
+		commit_sha: &str,
+		since: DateTime<Utc>,
+	) -> Result<Option<Submission>, DbError>;
+
+	/// Create the indexes the backend declares that do not exist yet. Indexes that cannot be
+	/// built, such as a unique index over existing duplicates, are logged and reported as missing.
+	async fn ensure_indexes(&self) -> Result<IndexReport, DbError>;
+
+	/// Compare the indexes the backend declares with those in the database
+	async fn index_report(&self) -> Result<IndexReport, DbError>;
+
+	/// List every repository
+	async fn list_repositories(&self) -> Result<Vec<Repository>, DbError>;
+
+	/// List every user along with their id
+	async fn list_users(&self) -> Result<Vec<(ObjectId, User)>, DbError>;
+
+	/// List the ids of users still storing their repositories under `relationships.repositories`
+	async fn list_users_with_legacy_relationships(&self) -> Result<Vec<ObjectId>, DbError>;
+
+	/// Replace the relationships of a repository
+	async fn set_repository_relationships(
+		&self,
+		repo_name: &str,
+		relationships: &RepositoryRelationships,
+	) -> Result<(), DbError>;
+
+	/// Replace the relationships of a user, rewriting them in the current format
+	async fn set_user_relationships(
+		&self,
+		id: &ObjectId,
+		repositories: &[Relationship],
+		relationships: &[Relationship],
+	) -> Result<(), DbError>;
+
+	/// Replace the relationships of a course
+	async fn set_course_relationships(
+		&self,
+		id: &ObjectId,
+		relationships: &[Relationship],
+	) -> Result<(), DbError>;
+}
+
+/// The storage backend to use, selected by `database.backend` in the config
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum StoreBackend {
+	Mongo,
+	Memory,
+}
+
+impl std::str::FromStr for StoreBackend {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"mongo" | "mongodb" => Ok(StoreBackend::Mongo),
+			"memory" => Ok(StoreBackend::Memory),
+			other => Err(format!("Unknown store backend `{}`", other)),
+		}
+	}
+}
@@ -0,0 +1,1042 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use log::{error, info, warn};
+use mongodb::{
+	bson::{self, doc, oid::ObjectId, Bson, Document},
+	error::TRANSIENT_TRANSACTION_ERROR,
+	options::{IndexOptions, ReturnDocument},
+	Client, Collection, Database, IndexModel,
+};
+
+use super::{
+	indexes::{declared_indexes, IndexDescription, IndexReport},
+	Store,
+};
+use crate::{
+	constants::{
+		COURSE_COLLECTION, COURSE_VERSION_COLLECTION, IDEMPOTENCY_COLLECTION, MIGRATION_COLLECTION,
+		PENDING_REPO_COLLECTION, REMINDER_COLLECTION, REPO_COLLECTION, SUBMISSION_COLLECTION,
+		TESTER_NONCE_COLLECTION, USER_COLLECTION,
+	},
+	errors::{is_duplicate_key_error, DbError},
+	models::{
+		Course, CourseVersion, IdempotencyRecord, PendingRepository, RelatedDocument, Relationship,
+		ReminderRecord, Repository, RepositoryProgress, RepositoryRelationships, Submission,
+		TesterNonce, User,
+	},
+	types::{
+		CourseFilter, CourseStatus, DocumentType, SubmissionStatus, UpdateRepoRequest,
+		UpdateUserRequest,
+	},
+};
+
+/// A [`Store`] backed by a MongoDB database
+#[derive(Clone)]
+pub(crate) struct MongoStore {
+	client: Client,
+	db: Database,
+}
+
+/// How many times a transaction is attempted when it fails with a transient error
+const MAX_TRANSACTION_ATTEMPTS: usize = 3;
+
+impl MongoStore {
+	pub(crate) fn new(client: &Client, db_name: &str) -> Self {
+		MongoStore { client: client.clone(), db: client.database(db_name) }
+	}
+
+	fn courses(&self) -> Collection<Document> {
+		self.db.collection(COURSE_COLLECTION)
+	}
+
+	fn repositories(&self) -> Collection<Repository> {
+		self.db.collection(REPO_COLLECTION)
+	}
+
+	fn submissions(&self) -> Collection<Submission> {
+		self.db.collection(SUBMISSION_COLLECTION)
+	}
+
+	fn pending_repositories(&self) -> Collection<PendingRepository> {
+		self.db.collection(PENDING_REPO_COLLECTION)
+	}
+
+	/// Every index in the database other than the default `_id` indexes
+	async fn existing_indexes(&self) -> Result<Vec<IndexDescription>, DbError> {
+		let mut indexes = vec![];
+
+		for collection in self.db.list_collection_names().await? {
+			if collection.starts_with("system.") || collection == MIGRATION_COLLECTION {
+				continue;
+			}
+			let models: Vec<IndexModel> = self
+				.db
+				.collection::<Document>(&collection)
+				.list_indexes()
+				.await?
+				.try_collect()
+				.await?;
+
+			for model in models {
+				let options = model.options.unwrap_or_default();
+				let Some(name) = options.name.filter(|name| name != "_id_") else {
+					continue;
+				};
+				indexes.push(IndexDescription {
+					collection: collection.clone(),
+					name,
+					keys: model.keys,
+					unique: options.unique.unwrap_or(false),
+					expire_after_secs: options
+						.expire_after
+						.map(|expire_after| expire_after.as_secs()),
+				});
+			}
+		}
+
+		Ok(indexes)
+	}
+
+	/// Find a single course and deserialize it, reporting malformed documents as internal errors
+	async fn find_course(&self, filter: Document) -> Result<Option<Course>, DbError> {
+		let course = self.courses().find_one(filter.clone()).await?;
+
+		log::debug!("{:#?}", course);
+
+		match course {
+			Some(course) => match bson::from_document::<Course>(course) {
+				Ok(course) => Ok(Some(course)),
+				Err(e) => {
+					error!("Failed to deserialize course: {}", e);
+					Err(DbError::InternalServerError(format!(
+						"Failed to deserialize course matching {}",
+						filter
+					)))
+				},
+			},
+			None => Ok(None),
+		}
+	}
+}
+
+/// A user document along with its id, which [`User`] does not carry
+#[derive(serde::Deserialize)]
+struct UserWithId {
+	#[serde(rename = "_id")]
+	id: ObjectId,
+	#[serde(flatten)]
+	user: User,
+}
+
+/// Whether two index key specifications list the same fields in the same order and directions.
+/// Directions may come back from the server as any numeric type.
+fn same_keys(a: &Document, b: &Document) -> bool {
+	let direction = |value: &Bson| match value {
+		Bson::Int32(n) => Some(*n as f64),
+		Bson::Int64(n) => Some(*n as f64),
+		Bson::Double(n) => Some(*n),
+		_ => None,
+	};
+
+	a.len() == b.len() &&
+		a.iter().zip(b.iter()).all(|((a_key, a_value), (b_key, b_value))| {
+			a_key == b_key &&
+				match (direction(a_value), direction(b_value)) {
+					(Some(a), Some(b)) => a == b,
+					_ => a_value == b_value,
+				}
+		})
+}
+
+/// Escape the characters of `text` that have a special meaning in a regular expression
+fn escape_regex(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		if "\\^$.|?*+()[]{}".contains(c) {
+			escaped.push('\\');
+		}
+		escaped.push(c);
+	}
+	escaped
+}
+
+/// Serialize a value for use in a query or update, the way the driver serializes documents so that
+/// timestamps nested in it are stored as BSON dates
+fn to_bson<T: serde::Serialize + ?Sized>(value: &T) -> Result<Bson, DbError> {
+	#[derive(serde::Serialize)]
+	struct Wrapper<'a, T: ?Sized> {
+		value: &'a T,
+	}
+
+	let raw = bson::to_raw_document_buf(&Wrapper { value })
+		.map_err(|e| DbError::DatabaseError(mongodb::error::Error::from(e)))?;
+	let mut document = raw
+		.to_document()
+		.map_err(|e| DbError::DatabaseError(mongodb::error::Error::from(e)))?;
+	Ok(document.remove("value").unwrap_or(Bson::Null))
+}
+
+/// A timestamp for use in a query or update, stored as a BSON date
+fn timestamp(at: DateTime<Utc>) -> Bson {
+	bson::DateTime::from_chrono(at).into()
+}
+
+/// An optional timestamp for use in a query or update
+fn optional_timestamp(at: Option<DateTime<Utc>>) -> Bson {
+	at.map_or(Bson::Null, timestamp)
+}
+
+#[async_trait]
+impl Store for MongoStore {
+	async fn get_course(&self, id: &ObjectId) -> Result<Option<Course>, DbError> {
+		self.find_course(doc! { "_id": id }).await
+	}
+
+	async fn get_course_by_slug(&self, slug: &str) -> Result<Option<Course>, DbError> {
+		self.find_course(doc! { "slug": slug }).await
+	}
+
+	async fn list_courses(
+		&self,
+		filter: &CourseFilter,
+		offset: u64,
+		limit: u64,
+	) -> Result<Vec<Course>, DbError> {
+		let mut query = doc! {};
+		if let Some(text) = &filter.query {
+			let pattern = doc! { "$regex": escape_regex(text), "$options": "i" };
+			query.insert(
+				"$or",
+				vec![
+					doc! { "name": pattern.clone() },
+					doc! { "title": pattern.clone() },
+					doc! { "author.name": pattern },
+				],
+			);
+		}
+		if let Some(language) = &filter.language {
+			query.insert(
+				"language",
+				doc! { "$regex": format!("^{}$", escape_regex(language)), "$options": "i" },
+			);
+		}
+		if let Some(difficulty) = &filter.difficulty {
+			query.insert("difficulty", to_bson(difficulty)?);
+		}
+		if !filter.tags.is_empty() {
+			query.insert("tags", doc! { "$all": &filter.tags });
+		}
+		if !filter.include_drafts {
+			// Courses predating the status field have none and are published
+			query.insert("status", doc! { "$ne": to_bson(&CourseStatus::Draft)? });
+		}
+
+		let courses: Vec<Document> = self
+			.courses()
+			.find(query)
+			.sort(doc! { "title": 1, "_id": 1 })
+			.skip(offset)
+			.limit(limit as i64)
+			.await?
+			.try_collect()
+			.await?;
+
+		courses
+			.into_iter()
+			.map(|course| {
+				bson::from_document::<Course>(course).map_err(|e| {
+					error!("Failed to deserialize course: {}", e);
+					DbError::InternalServerError("Failed to deserialize course".to_string())
+				})
+			})
+			.collect()
+	}
+
+	async fn create_course(&self, course: Course) -> Result<(), DbError> {
+		if self.courses().find_one(doc! { "slug": &course.slug }).await?.is_some() {
+			return Err(DbError::Conflict(format!(
+				"A course with slug `{}` already exists",
+				course.slug
+			)));
+		}
+
+		// The unique index on `slug` catches courses created concurrently
+		let collection: Collection<Course> = self.db.collection(COURSE_COLLECTION);
+		match collection.insert_one(&course).await {
+			Ok(_) => Ok(()),
+			Err(e) if is_duplicate_key_error(&e) => Err(DbError::Conflict(format!(
+				"A course with slug `{}` already exists",
+				course.slug
+			))),
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	async fn replace_course(
+		&self,
+		course: &Course,
+		previous: CourseVersion,
+	) -> Result<bool, DbError> {
+		let courses: Collection<Course> = self.db.collection(COURSE_COLLECTION);
+		let versions: Collection<CourseVersion> = self.db.collection(COURSE_VERSION_COLLECTION);
+		let filter = doc! { "_id": course.id, "version": &previous.version };
+		let version_filter = doc! { "course_id": previous.course_id, "version": &previous.version };
+		let archive = doc! { "$setOnInsert": {
+			"course": to_bson(&previous.course)?,
+			"archived_at": timestamp(previous.archived_at),
+		}};
+		let mut session = self.client.start_session().await?;
+
+		let mut attempt = 1;
+		loop {
+			session.start_transaction().await?;
+
+			// Resolves to whether the course was still at the previous version. The transaction is
+			// only committed if it was, so a concurrent writer that lost leaves no archived copy.
+			let result = async {
+				let result =
+					courses.replace_one(filter.clone(), course).session(&mut session).await?;
+				if result.matched_count == 0 {
+					return Ok(false);
+				}
+				versions
+					.update_one(version_filter.clone(), archive.clone())
+					.upsert(true)
+					.session(&mut session)
+					.await?;
+
+				session.commit_transaction().await?;
+				Ok::<_, mongodb::error::Error>(true)
+			}
+			.await;
+
+			match result {
+				Ok(true) => return Ok(true),
+				Ok(false) => {
+					session.abort_transaction().await?;
+					return Ok(false);
+				},
+				Err(e)
+					if attempt < MAX_TRANSACTION_ATTEMPTS &&
+						e.contains_label(TRANSIENT_TRANSACTION_ERROR) =>
+				{
+					warn!("Transient error replacing course `{}`, retrying: {}", course.slug, e);
+					let _ = session.abort_transaction().await;
+					attempt += 1;
+				},
+				Err(e) => {
+					let _ = session.abort_transaction().await;
+					return Err(e.into());
+				},
+			}
+		}
+	}
+
+	async fn set_course_status(
+		&self,
+		id: &ObjectId,
+		status: CourseStatus,
+	) -> Result<Option<Course>, DbError> {
+		let collection: Collection<Course> = self.db.collection(COURSE_COLLECTION);
+		let update = doc! { "$set": { "status": to_bson(&status)? } };
+
+		Ok(collection
+			.find_one_and_update(doc! { "_id": id }, update)
+			.return_document(ReturnDocument::After)
+			.await?)
+	}
+
+	async fn list_course_versions(
+		&self,
+		course_id: &ObjectId,
+	) -> Result<Vec<CourseVersion>, DbError> {
+		let collection: Collection<CourseVersion> = self.db.collection(COURSE_VERSION_COLLECTION);
+
+		let versions = collection
+			.find(doc! { "course_id": course_id })
+			.sort(doc! { "archived_at": 1, "_id": 1 })
+			.await?
+			.try_collect()
+			.await?;
+
+		Ok(versions)
+	}
+
+	async fn get_course_version(
+		&self,
+		course_id: &ObjectId,
+		version: &str,
+	) -> Result<Option<CourseVersion>, DbError> {
+		let collection: Collection<CourseVersion> = self.db.collection(COURSE_VERSION_COLLECTION);
+
+		Ok(collection.find_one(doc! { "course_id": course_id, "version": version }).await?)
+	}
+
+	async fn get_related_documents(
+		&self,
+		document_type: DocumentType,
+		ids: &[ObjectId],
+	) -> Result<Vec<RelatedDocument>, DbError> {
+		let filter = doc! { "_id": { "$in": ids } };
+
+		let documents = match document_type {
+			DocumentType::Repository =>
+				self.repositories()
+					.find(filter)
+					.await?
+					.map_ok(RelatedDocument::Repository)
+					.try_collect()
+					.await?,
+			DocumentType::User =>
+				self.db
+					.collection::<UserWithId>(USER_COLLECTION)
+					.find(filter)
+					.await?
+					.map_ok(|UserWithId { id, user }| RelatedDocument::User(id, user))
+					.try_collect()
+					.await?,
+			DocumentType::Course =>
+				self.db
+					.collection::<Course>(COURSE_COLLECTION)
+					.find(filter)
+					.await?
+					.map_ok(RelatedDocument::Course)
+					.try_collect()
+					.await?,
+		};
+
+		Ok(documents)
+	}
+
+	async fn create_repository(
+		&self,
+		mut repository: Repository,
+		user_id: &ObjectId,
+	) -> Result<ObjectId, DbError> {
+		let repo_id = *repository.id.get_or_insert_with(ObjectId::new);
+		let repository_relationship =
+			to_bson(&Relationship { id: repo_id, r#type: DocumentType::Repository })?;
+		let users: Collection<User> = self.db.collection(USER_COLLECTION);
+		let mut session = self.client.start_session().await?;
+
+		let mut attempt = 1;
+		loop {
+			session.start_transaction().await?;
+
+			// Resolves to whether the owner exists. The transaction is only committed if it does.
+			let result = async {
+				self.repositories().insert_one(&repository).session(&mut session).await?;
+
+				let filter = doc! { "_id": user_id };
+				let update = doc! { "$addToSet": { "repositories": &repository_relationship } };
+				let result = users.update_one(filter, update).session(&mut session).await?;
+				if result.matched_count == 0 {
+					return Ok(false);
+				}
+
+				session.commit_transaction().await?;
+				Ok::<_, mongodb::error::Error>(true)
+			}
+			.await;
+
+			match result {
+				Ok(true) => return Ok(repo_id),
+				Ok(false) => {
+					session.abort_transaction().await?;
+					return Err(DbError::NotFound(format!("User `{}` not found", user_id)));
+				},
+				Err(e)
+					if attempt < MAX_TRANSACTION_ATTEMPTS &&
+						e.contains_label(TRANSIENT_TRANSACTION_ERROR) =>
+				{
+					warn!(
+						"Transient error creating repository `{}`, retrying: {}",
+						repository.repo_name, e
+					);
+					let _ = session.abort_transaction().await;
+					attempt += 1;
+				},
+				Err(e) => {
+					let _ = session.abort_transaction().await;
+					if is_duplicate_key_error(&e) {
+						return Err(DbError::Conflict(format!(
+							"Repository `{}` already exists",
+							repository.repo_name
+						)));
+					}
+					return Err(e.into());
+				},
+			}
+		}
+	}
+
+	async fn get_repository(&self, repo_name: &str) -> Result<Option<Repository>, DbError> {
+		let filter = doc! { "repo_name": repo_name };
+		let repository = self.repositories().find_one(filter).await?;
+
+		info!("Repository: {:?}", repository);
+
+		Ok(repository)
+	}
+
+	async fn update_repository(
+		&self,
+		repo_name: &str,
+		update_request: &UpdateRepoRequest,
+	) -> Result<Option<Repository>, DbError> {
+		let filter = doc! { "repo_name": repo_name };
+		let mut update = doc! {};
+
+		if let Some(expected_practice_frequency) = &update_request.expected_practice_frequency {
+			update.insert(
+				"expected_practice_frequency",
+				bson::to_bson(expected_practice_frequency)
+					.map_err(|e| DbError::DatabaseError(mongodb::error::Error::from(e)))?,
+			);
+		}
+
+		if let Some(is_reminder_enabled) = update_request.is_reminder_enabled {
+			update.insert("is_reminder_enabled", is_reminder_enabled);
+		}
+
+		if let Some(relationships) = &update_request.relationships {
+			update.insert(
+				"relationships",
+				bson::to_bson(relationships)
+					.map_err(|e| DbError::DatabaseError(mongodb::error::Error::from(e)))?,
+			);
+		}
+
+		// An empty `$set` is rejected by the server, and there is nothing to change anyway
+		if update.is_empty() {
+			return Ok(self.repositories().find_one(filter).await?);
+		}
+
+		Ok(self
+			.repositories()
+			.find_one_and_update(filter, doc! { "$set": update })
+			.return_document(ReturnDocument::After)
+			.await?)
+	}
+
+	async fn record_test_result(
+		&self,
+		repo_name: &str,
+		test_ok: bool,
+	) -> Result<Option<Repository>, DbError> {
+		let filter = doc! { "repo_name": repo_name };
+		let update = doc! { "$set": { "test_ok": test_ok } };
+
+		Ok(self
+			.repositories()
+			.find_one_and_update(filter, update)
+			.return_document(ReturnDocument::After)
+			.await?)
+	}
+
+	async fn set_repository_course_version(
+		&self,
+		repo_name: &str,
+		course: &Course,
+	) -> Result<Option<Repository>, DbError> {
+		let filter = doc! { "repo_name": repo_name };
+		let update = doc! { "$set": {
+			"course_version": &course.version,
+			"tester_url": &course.tester_url,
+			"tester_version": to_bson(&course.tester_version)?,
+		}};
+
+		Ok(self
+			.repositories()
+			.find_one_and_update(filter, update)
+			.return_document(ReturnDocument::After)
+			.await?)
+	}
+
+	async fn set_repository_progress(
+		&self,
+		repo_name: &str,
+		progress: &RepositoryProgress,
+	) -> Result<bool, DbError> {
+		let filter = doc! { "repo_name": repo_name };
+		let update = doc! { "$set": { "progress": to_bson(progress)? } };
+
+		let result = self.repositories().update_one(filter, update).await?;
+		Ok(result.matched_count == 1)
+	}
+
+	async fn set_repository_last_submission_at(
+		&self,
+		repo_name: &str,
+		at: DateTime<Utc>,
+	) -> Result<(), DbError> {
+		let filter = doc! { "repo_name": repo_name };
+		let update = doc! { "$set": { "last_submission_at": timestamp(at) } };
+
+		self.repositories().update_one(filter, update).await?;
+		Ok(())
+	}
+
+	async fn add_repository_to_user(
+		&self,
+		user_id: &ObjectId,
+		repo_id: ObjectId,
+	) -> Result<(), DbError> {
+		let collection: Collection<User> = self.db.collection(USER_COLLECTION);
+
+		let filter = doc! { "_id": user_id };
+		let relationship =
+			to_bson(&Relationship { id: repo_id, r#type: DocumentType::Repository })?;
+		let update = doc! { "$addToSet": { "repositories": relationship } };
+
+		collection.update_one(filter, update).await?;
+		Ok(())
+	}
+
+	async fn reset_repository(
+		&self,
+		repo_name: &str,
+		progress: &RepositoryProgress,
+	) -> Result<Option<Repository>, DbError> {
+		let filter = doc! { "repo_name": repo_name, "archived_at": null };
+		let update = doc! { "$set": { "progress": to_bson(progress)?, "test_ok": null } };
+		let Some(repository) = self
+			.repositories()
+			.find_one_and_update(filter, update)
+			.return_document(ReturnDocument::After)
+			.await?
+		else {
+			return Ok(None);
+		};
+
+		self.submissions()
+			.update_many(
+				doc! { "repo_name": repo_name },
+				doc! { "$set": { "previous_attempt": true } },
+			)
+			.await?;
+		Ok(Some(repository))
+	}
+
+	async fn archive_repository(
+		&self,
+		repo_name: &str,
+		archived_at: DateTime<Utc>,
+	) -> Result<Option<Repository>, DbError> {
+		let filter = doc! { "repo_name": repo_name, "archived_at": null };
+		let update = doc! { "$set": { "archived_at": timestamp(archived_at) } };
+		let Some(repository) = self
+			.repositories()
+			.find_one_and_update(filter, update)
+			.return_document(ReturnDocument::After)
+			.await?
+		else {
+			return Ok(None);
+		};
+
+		// Left in place if this fails, which the integrity checker tolerates for archived
+		// repositories
+		if let (Some(id), Some(owner)) = (repository.id, &repository.relationships.user) {
+			let users: Collection<User> = self.db.collection(USER_COLLECTION);
+			let update = doc! { "$pull": { "repositories": { "id": id } } };
+			users.update_one(doc! { "_id": owner.id }, update).await?;
+		}
+		Ok(Some(repository))
+	}
+
+	async fn restore_repository(&self, repo_name: &str) -> Result<Option<Repository>, DbError> {
+		let filter = doc! { "repo_name": repo_name, "archived_at": { "$ne": null } };
+		let update = doc! { "$unset": { "archived_at": "" } };
+		let Some(repository) = self
+			.repositories()
+			.find_one_and_update(filter, update)
+			.return_document(ReturnDocument::After)
+			.await?
+		else {
+			return Ok(None);
+		};
+
+		if let (Some(id), Some(owner)) = (repository.id, &repository.relationships.user) {
+			self.add_repository_to_user(&owner.id, id).await?;
+		}
+		Ok(Some(repository))
+	}
+
+	async fn list_archived_repositories(
+		&self,
+		before: DateTime<Utc>,
+	) -> Result<Vec<Repository>, DbError> {
+		let filter = doc! { "archived_at": { "$lt": timestamp(before) } };
+		Ok(self.repositories().find(filter).await?.try_collect().await?)
+	}
+
+	async fn purge_repository(&self, repo_name: &str) -> Result<(), DbError> {
+		let filter = doc! { "repo_name": repo_name };
+		let reminders: Collection<Document> = self.db.collection(REMINDER_COLLECTION);
+
+		self.submissions().delete_many(filter.clone()).await?;
+		reminders.delete_many(filter.clone()).await?;
+		self.repositories().delete_one(filter).await?;
+		Ok(())
+	}
+
+	async fn insert_submission(&self, submission: Submission) -> Result<(), DbError> {
+		self.submissions().insert_one(submission).await?;
+		Ok(())
+	}
+
+	async fn get_submission(&self, logstream_id: &str) -> Result<Option<Submission>, DbError> {
+		Ok(self.submissions().find_one(doc! { "logstream_id": logstream_id }).await?)
+	}
+
+	async fn list_submissions(
+		&self,
+		repo_name: &str,
+		offset: u64,
+		limit: u64,
+	) -> Result<Vec<Submission>, DbError> {
+		let submissions = self
+			.submissions()
+			.find(doc! { "repo_name": repo_name })
+			.sort(doc! { "created_at": -1, "_id": -1 })
+			.skip(offset)
+			.limit(limit as i64)
+			.await?
+			.try_collect()
+			.await?;
+
+		Ok(submissions)
+	}
+
+	async fn list_submission_times(&self, repo_name: &str) -> Result<Vec<DateTime<Utc>>, DbError> {
+		let submissions: Vec<Submission> = self
+			.submissions()
+			.find(doc! { "repo_name": repo_name })
+			.sort(doc! { "created_at": 1, "_id": 1 })
+			.await?
+			.try_collect()
+			.await?;
+
+		Ok(submissions.into_iter().map(|submission| submission.created_at).collect())
+	}
+
+	async fn update_submission_progress(
+		&self,
+		submission: &Submission,
+		expected: SubmissionStatus,
+	) -> Result<bool, DbError> {
+		// Submissions created before statuses were tracked have no status field and are queued
+		let expected_status = to_bson(&expected)?;
+		let filter = if expected == SubmissionStatus::Queued {
+			doc! {
+				"logstream_id": &submission.logstream_id,
+				"$or": [{ "status": expected_status }, { "status": { "$exists": false } }],
+			}
+		} else {
+			doc! { "logstream_id": &submission.logstream_id, "status": expected_status }
+		};
+
+		let update = doc! { "$set": {
+			"status": to_bson(&submission.status)?,
+			"stages": to_bson(&submission.stages)?,
+			"started_at": optional_timestamp(submission.started_at),
+			"finished_at": optional_timestamp(submission.finished_at),
+		}};
+
+		let result = self.submissions().update_one(filter, update).await?;
+		Ok(result.matched_count == 1)
+	}
+
+	async fn insert_pending_repository(&self, pending: PendingRepository) -> Result<(), DbError> {
+		match self.pending_repositories().insert_one(&pending).await {
+			Ok(_) => Ok(()),
+			Err(e) if is_duplicate_key_error(&e) => Err(DbError::Conflict(format!(
+				"Repository `{}` is already being created",
+				pending.repo_name
+			))),
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	async fn delete_pending_repository(&self, repo_name: &str) -> Result<(), DbError> {
+		self.pending_repositories().delete_one(doc! { "repo_name": repo_name }).await?;
+		Ok(())
+	}
+
+	async fn list_pending_repositories(
+		&self,
+		before: DateTime<Utc>,
+	) -> Result<Vec<PendingRepository>, DbError> {
+		let filter = doc! { "created_at": { "$lt": timestamp(before) } };
+		Ok(self.pending_repositories().find(filter).await?.try_collect().await?)
+	}
+
+	async fn get_user(&self, id: &ObjectId) -> Result<Option<User>, DbError> {
+		let collection: Collection<User> = self.db.collection(USER_COLLECTION);
+		Ok(collection.find_one(doc! { "_id": id }).await?)
+	}
+
+	async fn update_user(
+		&self,
+		id: &ObjectId,
+		update_request: &UpdateUserRequest,
+	) -> Result<Option<User>, DbError> {
+		let collection: Collection<User> = self.db.collection(USER_COLLECTION);
+		let mut update = doc! {};
+
+		if let Some(name) = &update_request.name {
+			update.insert("name", name);
+		}
+		if let Some(email) = &update_request.email {
+			update.insert("email", email);
+		}
+
+		if update.is_empty() {
+			return Ok(collection.find_one(doc! { "_id": id }).await?);
+		}
+
+		Ok(collection
+			.find_one_and_update(doc! { "_id": id }, doc! { "$set": update })
+			.return_document(ReturnDocument::After)
+			.await?)
+	}
+
+	async fn list_user_repositories(&self, user_id: &ObjectId) -> Result<Vec<Repository>, DbError> {
+		let filter = doc! { "relationships.user.id": user_id, "archived_at": null };
+		Ok(self.repositories().find(filter).await?.try_collect().await?)
+	}
+
+	async fn list_reminder_enabled_repositories(&self) -> Result<Vec<Repository>, DbError> {
+		let repositories = self
+			.repositories()
+			.find(doc! { "is_reminder_enabled": true, "archived_at": null })
+			.await?
+			.try_collect()
+			.await?;
+
+		Ok(repositories)
+	}
+
+	async fn record_reminder(&self, reminder: ReminderRecord) -> Result<bool, DbError> {
+		let collection: Collection<ReminderRecord> = self.db.collection(REMINDER_COLLECTION);
+
+		let filter = doc! { "repo_name": &reminder.repo_name, "period": &reminder.period };
+		let update = doc! { "$setOnInsert": { "sent_at": timestamp(reminder.sent_at) } };
+		let result = collection.update_one(filter, update).upsert(true).await?;
+
+		Ok(result.upserted_id.is_some())
+	}
+
+	async fn delete_reminder(&self, repo_name: &str, period: &str) -> Result<(), DbError> {
+		let collection: Collection<ReminderRecord> = self.db.collection(REMINDER_COLLECTION);
+		collection.delete_one(doc! { "repo_name": repo_name, "period": period }).await?;
+		Ok(())
+	}
+
+	async fn record_tester_nonce(&self, nonce: TesterNonce) -> Result<bool, DbError> {
+		let collection: Collection<TesterNonce> = self.db.collection(TESTER_NONCE_COLLECTION);
+
+		// Of two concurrent upserts of the same nonce, the unique index on (tester_id, signature)
+		// makes the loser fail rather than match, so that is a replay too
+		let filter = doc! { "tester_id": &nonce.tester_id, "signature": &nonce.signature };
+		let update = doc! { "$setOnInsert": { "expires_at": timestamp(nonce.expires_at) } };
+		match collection.update_one(filter, update).upsert(true).await {
+			Ok(result) => Ok(result.upserted_id.is_some()),
+			Err(e) if is_duplicate_key_error(&e) => Ok(false),
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	async fn reserve_idempotency_key(
+		&self,
+		record: IdempotencyRecord,
+	) -> Result<Option<IdempotencyRecord>, DbError> {
+		let collection: Collection<IdempotencyRecord> = self.db.collection(IDEMPOTENCY_COLLECTION);
+
+		// The unique index makes the insert fail if a concurrent request reserved the key first
+		match collection.insert_one(&record).await {
+			Ok(_) => Ok(None),
+			Err(e) if is_duplicate_key_error(&e) => {
+				let filter = doc! {
+					"user_id": record.user_id,
+					"endpoint": &record.endpoint,
+					"key": &record.key,
+				};
+
+				// The TTL index only removes expired records about once a minute, so one may still
+				// be there. It is taken over, by at most one of concurrent requests.
+				let mut expired = filter.clone();
+				expired.insert("expires_at", doc! { "$lte": timestamp(Utc::now()) });
+				if collection.find_one_and_replace(expired, &record).await?.is_some() {
+					return Ok(None);
+				}
+
+				match collection.find_one(filter).await? {
+					Some(existing) => Ok(Some(existing)),
+					None => Err(DbError::Conflict(format!(
+						"Idempotency key `{}` was released concurrently",
+						record.key
+					))),
+				}
+			},
+			Err(e) => Err(e.into()),
+		}
+	}
+
+	async fn complete_idempotency_key(
+		&self,
+		user_id: &ObjectId,
+		endpoint: &str,
+		key: &str,
+		response: &str,
+	) -> Result<(), DbError> {
+		let collection: Collection<IdempotencyRecord> = self.db.collection(IDEMPOTENCY_COLLECTION);
+		let filter = doc! { "user_id": user_id, "endpoint": endpoint, "key": key };
+		collection.update_one(filter, doc! { "$set": { "response": response } }).await?;
+		Ok(())
+	}
+
+	async fn release_idempotency_key(
+		&self,
+		user_id: &ObjectId,
+		endpoint: &str,
+		key: &str,
+	) -> Result<(), DbError> {
+		let collection: Collection<IdempotencyRecord> = self.db.collection(IDEMPOTENCY_COLLECTION);
+		collection
+			.delete_one(doc! { "user_id": user_id, "endpoint": endpoint, "key": key })
+			.await?;
+		Ok(())
+	}
+
+	async fn find_recent_submission(
+		&self,
+		repo_name: &str,
+		commit_sha: &str,
+		since: DateTime<Utc>,
+	) -> Result<Option<Submission>, DbError> {
+		let filter = doc! {
+			"repo_name": repo_name,
+			"commit_sha": commit_sha,
+			"previous_attempt": { "$ne": true },
+			"created_at": { "$gt": timestamp(since) },
+		};
+		Ok(self.submissions().find_one(filter).sort(doc! { "created_at": -1 }).await?)
+	}
+
+	async fn ensure_indexes(&self) -> Result<IndexReport, DbError> {
+		for declared in declared_indexes() {
+			let options = IndexOptions::builder()
+				.name(declared.name.clone())
+				.unique(declared.unique)
+				.expire_after(declared.expire_after_secs.map(Duration::from_secs))
+				.build();
+			let model = IndexModel::builder().keys(declared.keys.clone()).options(options).build();
+
+			if let Err(e) =
+				self.db.collection::<Document>(&declared.collection).create_index(model).await
+			{
+				error!("Failed to create index {}: {}", declared, e);
+			}
+		}
+
+		self.index_report().await
+	}
+
+	async fn index_report(&self) -> Result<IndexReport, DbError> {
+		let existing = self.existing_indexes().await?;
+		let declared = declared_indexes();
+		let matches = |a: &IndexDescription, b: &IndexDescription| {
+			a.collection == b.collection &&
+				a.name == b.name &&
+				a.unique == b.unique &&
+				a.expire_after_secs == b.expire_after_secs &&
+				same_keys(&a.keys, &b.keys)
+		};
+
+		let mut report = IndexReport::default();
+		for index in &declared {
+			if existing.iter().any(|existing| matches(existing, index)) {
+				report.present.push(index.clone());
+			} else {
+				report.missing.push(index.clone());
+			}
+		}
+		report.extra = existing
+			.into_iter()
+			.filter(|existing| !declared.iter().any(|index| matches(existing, index)))
+			.collect();
+
+		Ok(report)
+	}
+
+	async fn list_repositories(&self) -> Result<Vec<Repository>, DbError> {
+		Ok(self.repositories().find(doc! {}).await?.try_collect().await?)
+	}
+
+	async fn list_users(&self) -> Result<Vec<(ObjectId, User)>, DbError> {
+		let collection: Collection<UserWithId> = self.db.collection(USER_COLLECTION);
+
+		Ok(collection
+			.find(doc! {})
+			.await?
+			.map_ok(|UserWithId { id, user }| (id, user))
+			.try_collect()
+			.await?)
+	}
+
+	async fn list_users_with_legacy_relationships(&self) -> Result<Vec<ObjectId>, DbError> {
+		let collection: Collection<Document> = self.db.collection(USER_COLLECTION);
+
+		let users: Vec<Document> = collection
+			.find(doc! { "relationships.repositories": { "$exists": true } })
+			.projection(doc! { "_id": 1 })
+			.await?
+			.try_collect()
+			.await?;
+
+		Ok(users.iter().filter_map(|user| user.get_object_id("_id").ok()).collect())
+	}
+
+	async fn set_repository_relationships(
+		&self,
+		repo_name: &str,
+		relationships: &RepositoryRelationships,
+	) -> Result<(), DbError> {
+		let filter = doc! { "repo_name": repo_name };
+		let update = doc! { "$set": { "relationships": to_bson(relationships)? } };
+
+		self.repositories().update_one(filter, update).await?;
+		Ok(())
+	}
+
+	async fn set_user_relationships(
+		&self,
+		id: &ObjectId,
+		repositories: &[Relationship],
+		relationships: &[Relationship],
+	) -> Result<(), DbError> {
+		let collection: Collection<User> = self.db.collection(USER_COLLECTION);
+
+		let update = doc! { "$set": {
+			"repositories": to_bson(repositories)?,
+			"relationships": to_bson(relationships)?,
+		}};
+		collection.update_one(doc! { "_id": id }, update).await?;
+		Ok(())
+	}
+
+	async fn set_course_relationships(
+		&self,
+		id: &ObjectId,
+		relationships: &[Relationship],
+	) -> Result<(), DbError> {
+		let update = doc! { "$set": { "relationships": to_bson(relationships)? } };
+
+		self.courses().update_one(doc! { "_id": id }, update).await?;
+		Ok(())
+	}
+}
@@ -0,0 +1,717 @@
+//! Handler tests running the full app against the in-memory store, the recording git host and the
+//! in-process submission queue
+
+use std::{
+	sync::{Arc, Mutex},
+	time::Duration,
+};
+
+use actix_web::{
+	body::MessageBody,
+	dev::{Service, ServiceResponse},
+	http::StatusCode,
+	test, web, App,
+};
+use hmac::{Hmac, Mac};
+use jsonwebtoken::{encode, EncodingKey, Header};
+use mongodb::bson::{self, oid::ObjectId};
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+use crate::{
+	archive::{purge_archived_repositories, PurgeReport},
+	auth::{Claims, Role, TESTER_ID_HEADER, TESTER_SIGNATURE_HEADER, TESTER_TIMESTAMP_HEADER},
+	config::Config,
+	configure_app,
+	errors::{DbError, GitHostError},
+	git_host::{GitHost, GitHostCall, LocalGitHost, RecordingGitHost},
+	integrity::{check_integrity, IntegrityProblem},
+	models::{Course, PendingRepository, Submission, User},
+	naming::{RandomHexNamer, RepoNamer},
+	queue::InMemoryQueue,
+	reconcile::{reconcile_pending_repositories, ReconcileReport},
+	store::{InMemoryStore, Store},
+	types::SubmissionStatus,
+	utils::{advance_submission, save_course_version, MAX_REPO_NAME_ATTEMPTS},
+	AppState,
+};
+
+const JWT_SECRET: &str = "test-secret";
+const TESTER_ID: &str = "rust-state-machine";
+const TESTER_SECRET: &str = "tester-secret";
+const USER_ID: &str = "66b5f3a2c1d2e3f4a5b6c7d9";
+const OTHER_USER_ID: &str = "66b5f3a2c1d2e3f4a5b6c7db";
+const COMMIT_SHA: &str = "0123abcd";
+
+struct Fixture {
+	store: Arc<InMemoryStore>,
+	git_host: RecordingGitHost,
+	state: web::Data<AppState>,
+}
+
+/// App state over an in-memory store holding a course with two stages and two users
+fn fixture() -> Fixture {
+	fixture_with_namer(Arc::new(RandomHexNamer))
+}
+
+/// [`fixture`] naming new repositories with `repo_namer`
+fn fixture_with_namer(repo_namer: Arc<dyn RepoNamer>) -> Fixture {
+	let mut config: Config = toml::from_str("").expect("defaults are valid");
+	config.auth.jwt_secret = Some(JWT_SECRET.to_string());
+	config.tester.secrets.insert(TESTER_ID.to_string(), TESTER_SECRET.to_string());
+	config.redis.uri = Some("redis://test".to_string());
+	config.logstream.ws_url = Some("ws://test".to_string());
+
+	let store = Arc::new(InMemoryStore::new());
+	let course: Course = serde_json::from_value(json!({
+		"version": "1",
+		"_id": { "$oid": "66b5f3a2c1d2e3f4a5b6c7d8" },
+		"slug": "rust-state-machine",
+		"name": "rsm",
+		"title": "Rust State Machine",
+		"author": { "name": "author", "url": "https://example.com" },
+		"testerUrl": "https://tester.example.com",
+		"stages": [
+			{ "slug": "setup", "title": "Setup", "description": "", "testerStageId": "t1" },
+			{ "slug": "balances", "title": "Balances", "description": "", "testerStageId": "t2" },
+		],
+	}))
+	.expect("valid course");
+	store.insert_course(course);
+	for (id, name) in [(USER_ID, "learner"), (OTHER_USER_ID, "other")] {
+		let user = User {
+			name: name.to_string(),
+			email: None,
+			repositories: vec![],
+			relationships: vec![],
+		};
+		store.insert_user(ObjectId::parse_str(id).expect("valid id"), user);
+	}
+
+	let git_host = RecordingGitHost::new();
+	let state = web::Data::new(AppState {
+		config: Arc::new(config),
+		store: store.clone(),
+		git_host: Arc::new(git_host.clone()),
+		repo_namer,
+		queue: Arc::new(InMemoryQueue::new(Duration::from_secs(60), 3)),
+	});
+
+	Fixture { store, git_host, state }
+}
+
+async fn init_app(
+	fixture: &Fixture,
+) -> impl Service<
+	actix_http::Request,
+	Response = ServiceResponse<impl MessageBody>,
+	Error = actix_web::Error,
+> {
+	test::init_service(App::new().app_data(fixture.state.clone()).configure(configure_app)).await
+}
+
+/// A bearer token for `user_id`
+fn token(user_id: &str, role: Role) -> String {
+	let claims = Claims {
+		sub: user_id.to_string(),
+		role,
+		exp: (chrono::Utc::now().timestamp() + 600) as u64,
+	};
+	let token =
+		encode(&Header::default(), &claims, &EncodingKey::from_secret(JWT_SECRET.as_bytes()))
+			.expect("token encodes");
+	format!("Bearer {}", token)
+}
+
+/// A POST request with a JSON body signed by the test tester
+fn signed(uri: &str, body: &Value) -> test::TestRequest {
+	signed_at(uri, body, chrono::Utc::now().timestamp())
+}
+
+/// A POST request with a JSON body signed by the test tester with the unix time `timestamp`
+fn signed_at(uri: &str, body: &Value, timestamp: i64) -> test::TestRequest {
+	let body = body.to_string();
+	let timestamp = timestamp.to_string();
+	let mut mac = Hmac::<Sha256>::new_from_slice(TESTER_SECRET.as_bytes()).expect("any key");
+	mac.update(format!("{}.{}", timestamp, body).as_bytes());
+	let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+
+	test::TestRequest::post()
+		.uri(uri)
+		.insert_header((TESTER_ID_HEADER, TESTER_ID))
+		.insert_header((TESTER_TIMESTAMP_HEADER, timestamp))
+		.insert_header((TESTER_SIGNATURE_HEADER, signature))
+		.insert_header(("content-type", "application/json"))
+		.set_payload(body)
+}
+
+/// A request creating a repository for `user_id`, without credentials
+fn create_repo_request(user_id: &str) -> test::TestRequest {
+	test::TestRequest::post().uri("/api/v0/repository").set_json(json!({
+		"repo_template": "rust-state-machine",
+		"user_id": user_id,
+		"expected_practice_frequency": "every_day",
+		"is_reminder_enabled": false,
+	}))
+}
+
+/// Create a repository for the test user and return its name
+async fn create_repo<S, B>(app: &S) -> String
+where
+	S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+	B: MessageBody,
+{
+	let request =
+		create_repo_request(USER_ID).insert_header(("authorization", token(USER_ID, Role::User)));
+	let response: Value = test::call_and_read_body_json(app, request.to_request()).await;
+	response["repo_name"].as_str().expect("repo_name").to_string()
+}
+
+/// A request submitting `commit_sha` to a repository of the test user
+fn create_submission_request(repo_name: &str, commit_sha: &str) -> test::TestRequest {
+	test::TestRequest::post()
+		.uri("/api/v0/submission")
+		.insert_header(("authorization", token(USER_ID, Role::User)))
+		.set_json(json!({ "repo_name": repo_name, "commit_sha": commit_sha }))
+}
+
+/// Push a commit to a repository of the test user, submit it and return the logstream id
+async fn create_submission<S, B>(fixture: &Fixture, app: &S, repo_name: &str) -> String
+where
+	S: Service<actix_http::Request, Response = ServiceResponse<B>, Error = actix_web::Error>,
+	B: MessageBody,
+{
+	fixture.git_host.push_commit(repo_name, COMMIT_SHA);
+	let request = create_submission_request(repo_name, COMMIT_SHA).to_request();
+	let response: Value = test::call_and_read_body_json(app, request).await;
+	response["logstream_id"].as_str().expect("logstream_id").to_string()
+}
+
+#[actix_web::test]
+async fn create_repository_creates_it_on_the_git_host_and_in_the_store() {
+	let fixture = fixture();
+	let app = init_app(&fixture).await;
+
+	let repo_name = create_repo(&app).await;
+
+	assert_eq!(
+		fixture.git_host.calls(),
+		vec![GitHostCall::Create {
+			repo_name: repo_name.clone(),
+			template: "rust-state-machine".to_string(),
+		}]
+	);
+	let repository = fixture.store.get_repository(&repo_name).await.unwrap().expect("stored");
+	assert_eq!(repository.tester_url, "https://tester.example.com");
+	assert_eq!(repository.progress.current_stage.as_deref(), Some("setup"));
+}
+
+/// Names repositories from a fixed list, repeating the last name once it runs out
+struct ListNamer(Mutex<Vec<&'static str>>);
+
+impl RepoNamer for ListNamer {
+	fn name(&self, _course: &Course) -> String {
+		let mut names = self.0.lock().unwrap();
+		match names.len() {
+			1 => names[0].to_string(),
+			_ => names.remove(0).to_string(),
+		}
+	}
+}
+
+#[actix_web::test]
+async fn a_taken_repository_name_is_retried_with_a_new_one() {
+	let fixture = fixture_with_namer(Arc::new(ListNamer(Mutex::new(vec!["taken", "free"]))));
+	let app = init_app(&fixture).await;
+	fixture.git_host.create_repo("taken", "rust-state-machine").await.unwrap();
+
+	assert_eq!(create_repo(&app).await, "free");
+}
+
+#[actix_web::test]
+async fn repository_creation_gives_up_after_too_many_taken_names() {
+	let fixture = fixture_with_namer(Arc::new(ListNamer(Mutex::new(vec!["taken"]))));
+	let app = init_app(&fixture).await;
+	fixture.git_host.create_repo("taken", "rust-state-machine").await.unwrap();
+
+	let request =
+		create_repo_request(USER_ID).insert_header(("authorization", token(USER_ID, Role::User)));
+	let response = test::call_service(&app, request.to_request()).await;
+	assert_eq!(response.status(), StatusCode::CONFLICT);
+
+	let creates = fixture
+		.git_host
+		.calls()
+		.iter()
+		.filter(|call| matches!(call, GitHostCall::Create { .. }))
+		.count();
+	assert_eq!(creates, 1 + MAX_REPO_NAME_ATTEMPTS as usize);
+}
+
+#[actix_web::test]
+async fn create_repository_requires_a_valid_token() {
+	let fixture = fixture();
+	let app = init_app(&fixture).await;
+
+	// The authentication middleware rejects an invalid token before the request reaches a handler
+	let request = create_repo_request(USER_ID).insert_header(("authorization", "Bearer x"));
+	let error = test::try_call_service(&app, request.to_request())
+		.await
+		.err()
+		.expect("rejected");
+	let response = error.error_response();
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+	let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+	let body: Value = serde_json::from_slice(&body).unwrap();
+	assert_eq!(body["code"], "invalid_token");
+
+	let request = create_repo_request(USER_ID);
+	let response = test::call_service(&app, request.to_request()).await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+	let body: Value = test::read_body_json(response).await;
+	assert_eq!(body["code"], "missing_token");
+
+	assert!(fixture.git_host.calls().is_empty());
+}
+
+#[actix_web::test]
+async fn create_repository_for_another_user_is_forbidden() {
+	let fixture = fixture();
+	let app = init_app(&fixture).await;
+
+	let request = create_repo_request(OTHER_USER_ID)
+		.insert_header(("authorization", token(USER_ID, Role::User)))
+		.to_request();
+	let response = test::call_service(&app, request).await;
+
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+	assert!(fixture.git_host.calls().is_empty());
+}
+
+#[actix_web::test]
+async fn submissions_are_only_visible_to_the_owner() {
+	let fixture = fixture();
+	let app = init_app(&fixture).await;
+	let repo_name = create_repo(&app).await;
+	let logstream_id = create_submission(&fixture, &app, &repo_name).await;
+
+	let submission = fixture.store.get_submission(&logstream_id).await.unwrap().expect("stored");
+	assert_eq!(submission.repo_name, repo_name);
+	assert_eq!(submission.commit_sha, COMMIT_SHA);
+
+	let request = test::TestRequest::get()
+		.uri(&format!("/api/v0/submission/{}", logstream_id))
+		.insert_header(("authorization", token(OTHER_USER_ID, Role::User)))
+		.to_request();
+	let response = test::call_service(&app, request).await;
+	assert_eq!(response.status(), StatusCode::FORBIDDEN);
+}
+
+#[actix_web::test]
+async fn only_commits_on_the_git_host_can_be_submitted() {
+	let fixture = fixture();
+	let app = init_app(&fixture).await;
+	let repo_name = create_repo(&app).await;
+
+	let request = create_submission_request(&repo_name, "89abcdef").to_request();
+	let response = test::call_service(&app, request).await;
+	assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+	assert_eq!(
+		fixture.git_host.calls().last(),
+		Some(&GitHostCall::CommitExists {
+			repo_name: repo_name.clone(),
+			commit_sha: "89abcdef".to_string(),
+		})
+	);
+
+	let calls = fixture.git_host.calls().len();
+	let request = create_submission_request(&repo_name, "--output=/tmp/x").to_request();
+	let response = test::call_service(&app, request).await;
+	assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+	assert_eq!(fixture.git_host.calls().len(), calls);
+
+	let submissions = fixture.store.list_submissions(&repo_name, 0, 10).await.unwrap();
+	assert!(submissions.is_empty());
+}
+
+#[actix_web::test]
+async fn a_claimed_and_passed_submission_records_the_result() {
+	let fixture = fixture();
+	let app = init_app(&fixture).await;
+	let repo_name = create_repo(&app).await;
+	let logstream_id = create_submission(&fixture, &app, &repo_name).await;
+
+	let request = signed("/api/v0/tester/jobs/claim", &json!({ "worker_id": "worker-1" }));
+	let job: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+	assert_eq!(job["logstream_id"], logstream_id.as_str());
+	assert_eq!(job["delivery"], 1);
+
+	let request = signed("/api/v0/tester/jobs/claim", &json!({ "worker_id": "worker-2" }));
+	let response = test::call_service(&app, request.to_request()).await;
+	assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+	let report = json!({
+		"status": "passed",
+		"stages": [{ "stage": "t1", "passed": true }],
+	});
+	let request = signed(&format!("/api/v0/tester/submissions/{}", logstream_id), &report);
+	let submission: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+	assert_eq!(submission["status"], "passed");
+
+	let repository = fixture.store.get_repository(&repo_name).await.unwrap().expect("stored");
+	assert_eq!(repository.test_ok, Some(true));
+	assert_eq!(repository.progress.current_stage.as_deref(), Some("balances"));
+
+	// The finished job left the queue, so its worker can no longer heartbeat it
+	assert!(!fixture.state.queue.heartbeat("worker-1", &logstream_id).await.unwrap());
+}
+
+#[actix_web::test]
+async fn a_submission_that_never_ran_has_no_start_time() {
+	let fixture = fixture();
+	let app = init_app(&fixture).await;
+	let repo_name = create_repo(&app).await;
+	let logstream_id = create_submission(&fixture, &app, &repo_name).await;
+
+	let submission =
+		advance_submission(fixture.store.as_ref(), &logstream_id, SubmissionStatus::Errored, &[])
+			.await
+			.unwrap();
+	assert_eq!(submission.started_at, None);
+	assert!(submission.finished_at.is_some());
+}
+
+#[actix_web::test]
+async fn tester_callbacks_must_be_signed_and_not_replayed() {
+	let fixture = fixture();
+	let app = init_app(&fixture).await;
+	let repo_name = create_repo(&app).await;
+
+	let result = json!({ "repo_name": repo_name, "test_ok": true });
+	let request = test::TestRequest::post()
+		.uri("/api/v0/tester/results")
+		.set_json(&result)
+		.to_request();
+	let response = test::call_service(&app, request).await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let tampered = signed("/api/v0/tester/results", &result)
+		.set_payload(json!({ "repo_name": repo_name, "test_ok": false }).to_string());
+	let response = test::call_service(&app, tampered.to_request()).await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+	let request = signed("/api/v0/tester/results", &result).to_request();
+	let response = test::call_service(&app, request).await;
+	assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+	let replay = signed("/api/v0/tester/results", &result).to_request();
+	let response = test::call_service(&app, replay).await;
+	assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+	let body: Value = test::read_body_json(response).await;
+	assert_eq!(body["code"], "replayed_request");
+}
+
+#[actix_web::test]
+async fn tester_callbacks_signed_at_extreme_times_are_rejected() {
+	let fixture = fixture();
+	let app = init_app(&fixture).await;
+	let repo_name = create_repo(&app).await;
+	let result = json!({ "repo_name": repo_name, "test_ok": true });
+
+	for timestamp in [i64::MIN, i64::MIN + 1, -1, i64::MAX] {
+		let request = signed_at("/api/v0/tester/results", &result, timestamp).to_request();
+		let response = test::call_service(&app, request).await;
+		assert_eq!(response.status(), StatusCode::UNAUTHORIZED, "signed at {}", timestamp);
+	}
+}
+
+#[actix_web::test]
+async fn test_results_must_name_a_submission_of_the_repository() {
+	let fixture = fixture();
+	let app = init_app(&fixture).await;
+	let repo_name = create_repo(&app).await;
+	let other_repo_name = create_repo(&app).await;
+	let logstream_id = create_submission(&fixture, &app, &repo_name).await;
+
+	let result = json!({
+		"repo_name": other_repo_name,
+		"logstream_id": logstream_id,
+		"test_ok": true,
+	});
+	let response =
+		test::call_service(&app, signed("/api/v0/tester/results", &result).to_request()).await;
+	assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+	let other = fixture.store.get_repository(&other_repo_name).await.unwrap().expect("stored");
+	assert_eq!(other.test_ok, None);
+}
+
+#[actix_web::test]
+async fn a_git_server_failure_leaves_no_repository_behind() {
+	let fixture = fixture();
+	let app = init_app(&fixture).await;
+	fixture.git_host.fail_creates(true);
+
+	let request =
+		create_repo_request(USER_ID).insert_header(("authorization", token(USER_ID, Role::User)));
+	let response = test::call_service(&app, request.to_request()).await;
+	assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+	let [GitHostCall::Create { repo_name, .. }] = &fixture.git_host.calls()[..] else {
+		panic!("expected a single create call");
+	};
+	assert_eq!(fixture.store.get_repository(repo_name).await.unwrap(), None);
+	let pending = fixture.store.list_pending_repositories(chrono::Utc::now()).await.unwrap();
+	assert!(pending.is_empty());
+}
+
+#[actix_web::test]
+async fn an_orphaned_repository_is_kept_pending_until_the_git_server_deletes_it() {
+	let fixture = fixture();
+	let repo_name = "orphaned";
+	fixture.git_host.create_repo(repo_name, "rust-state-machine").await.unwrap();
+	let pending = PendingRepository {
+		repo_name: repo_name.to_string(),
+		repo_template: "rust-state-machine".to_string(),
+		user_id: ObjectId::parse_str(USER_ID).unwrap(),
+		created_at: chrono::Utc::now() - chrono::Duration::minutes(10),
+	};
+	fixture.store.insert_pending_repository(pending).await.unwrap();
+
+	fixture.git_host.fail_deletes(true);
+	let report = reconcile_pending_repositories(
+		fixture.store.as_ref(),
+		&fixture.git_host,
+		Duration::from_secs(60),
+	)
+	.await
+	.unwrap();
+	assert_eq!(report, ReconcileReport { orphaned: 1, ..Default::default() });
+	let stale = fixture.store.list_pending_repositories(chrono::Utc::now()).await.unwrap();
+	assert_eq!(stale.len(), 1);
+
+	fixture.git_host.fail_deletes(false);
+	let report = reconcile_pending_repositories(
+		fixture.store.as_ref(),
+		&fixture.git_host,
+		Duration::from_secs(60),
+	)
+	.await
+	.unwrap();
+	assert_eq!(report, ReconcileReport { rolled_back: 1, ..Default::default() });
+	let stale = fixture.store.list_pending_repositories(chrono::Utc::now()).await.unwrap();
+	assert!(stale.is_empty());
+}
+
+#[actix_web::test]
+async fn repositories_on_the_git_host_without_a_document_are_reported() {
+	let fixture = fixture();
+	let app = init_app(&fixture).await;
+	let repo_name = create_repo(&app).await;
+	fixture.git_host.create_repo("stray", "rust-state-machine").await.unwrap();
+
+	let report = check_integrity(fixture.store.as_ref(), &fixture.git_host, true).await.unwrap();
+	let untracked: Vec<_> = report
+		.issues
+		.iter()
+		.filter(|issue| issue.problem == IntegrityProblem::Untracked)
+		.map(|issue| (issue.document.as_str(), issue.repaired))
+		.collect();
+	assert_eq!(untracked, vec![("stray", false)]);
+	assert!(fixture.git_host.calls().contains(&GitHostCall::ListRepos));
+	assert!(!report.issues.iter().any(|issue| issue.document == repo_name));
+}
+
+#[actix_web::test]
+async fn an_archived_repository_is_only_purged_once_the_git_server_deletes_it() {
+	let fixture = fixture();
+	let app = init_app(&fixture).await;
+	let repo_name = create_repo(&app).await;
+
+	let request = test::TestRequest::delete()
+		.uri(&format!("/api/v0/repository/{}", repo_name))
+		.insert_header(("authorization", token(USER_ID, Role::User)));
+	let response = test::call_service(&app, request.to_request()).await;
+	assert!(response.status().is_success());
+	assert!(fixture
+		.git_host
+		.calls()
+		.contains(&GitHostCall::Archive { repo_name: repo_name.clone() }));
+
+	fixture.git_host.fail_deletes(true);
+	let report =
+		purge_archived_repositories(fixture.store.as_ref(), &fixture.git_host, Duration::ZERO)
+			.await
+			.unwrap();
+	assert_eq!(report, PurgeReport { purged: 0, failed: 1 });
+	assert!(fixture.store.get_repository(&repo_name).await.unwrap().is_some());
+
+	fixture.git_host.fail_deletes(false);
+	let report =
+		purge_archived_repositories(fixture.store.as_ref(), &fixture.git_host, Duration::ZERO)
+			.await
+			.unwrap();
+	assert_eq!(report, PurgeReport { purged: 1, failed: 0 });
+	assert_eq!(fixture.store.get_repository(&repo_name).await.unwrap(), None);
+}
+
+#[actix_web::test]
+async fn a_failed_reseed_is_a_bad_gateway() {
+	let fixture = fixture();
+	let app = init_app(&fixture).await;
+	let repo_name = create_repo(&app).await;
+	fixture.git_host.delete_repo(&repo_name).await.unwrap();
+
+	let request = test::TestRequest::post()
+		.uri(&format!("/api/v0/repository/{}/reset", repo_name))
+		.insert_header(("authorization", token(USER_ID, Role::User)))
+		.set_json(json!({ "reseed": true }));
+	let response = test::call_service(&app, request.to_request()).await;
+	assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+}
+
+#[actix_web::test]
+async fn a_local_repository_is_created_once_and_reseeded_in_place() {
+	let root = std::env::temp_dir().join(format!("dcs-local-{}", uuid::Uuid::new_v4()));
+	let (repos_dir, templates_dir) = (root.join("repos"), root.join("templates"));
+	std::fs::create_dir_all(templates_dir.join("course")).unwrap();
+	let readme = templates_dir.join("course").join("README.md");
+	let git_host = LocalGitHost::new(&repos_dir, &templates_dir);
+
+	std::fs::write(&readme, "first").unwrap();
+	git_host.create_repo("learner", "course").await.unwrap();
+	let result = git_host.create_repo("learner", "course").await;
+	assert!(matches!(result, Err(GitHostError::Conflict(_))));
+	std::fs::write(&readme, "second").unwrap();
+	git_host.reseed_repo("learner", "course").await.unwrap();
+
+	let output = std::process::Command::new("git")
+		.arg("-C")
+		.arg(repos_dir.join("learner.git"))
+		.args(["show", "HEAD:README.md"])
+		.output()
+		.unwrap();
+	assert_eq!(output.stdout, b"second");
+	// Nothing is left over from the swap
+	let entries: Vec<_> = std::fs::read_dir(&repos_dir)
+		.unwrap()
+		.map(|entry| entry.unwrap().file_name().into_string().unwrap())
+		.collect();
+	assert_eq!(entries, ["learner.git"]);
+
+	// A missing template leaves the repository in place
+	let result = git_host.reseed_repo("learner", "missing").await;
+	assert!(matches!(result, Err(GitHostError::NotFound(_))));
+	assert!(repos_dir.join("learner.git").join("HEAD").is_file());
+
+	std::fs::remove_dir_all(&root).unwrap();
+}
+
+#[actix_web::test]
+async fn timestamps_are_bson_dates_in_documents_and_strings_in_json() {
+	let at = chrono::DateTime::from_timestamp_millis(1_700_000_000_123).unwrap();
+	let submission = Submission {
+		repo_name: "repo".to_string(),
+		commit_sha: "0123abcd".to_string(),
+		logstream_id: "logstream".to_string(),
+		logstream_url: "https://logs".to_string(),
+		relationships: vec![],
+		created_at: at,
+		status: SubmissionStatus::Running,
+		stages: vec![],
+		started_at: Some(at),
+		finished_at: None,
+		previous_attempt: false,
+	};
+
+	let document = bson::to_raw_document_buf(&submission).unwrap();
+	let date = bson::DateTime::from_chrono(at);
+	assert_eq!(document.get_datetime("created_at").unwrap(), date);
+	assert_eq!(document.get_datetime("started_at").unwrap(), date);
+	assert_eq!(bson::from_slice::<Submission>(document.as_bytes()).unwrap(), submission);
+
+	let json = serde_json::to_value(&submission).unwrap();
+	assert_eq!(json["created_at"], "2023-11-14T22:13:20.123Z");
+	assert_eq!(json["started_at"], "2023-11-14T22:13:20.123Z");
+	assert_eq!(serde_json::from_value::<Submission>(json).unwrap(), submission);
+
+	// Written before timestamps were stored as dates
+	let legacy = bson::doc! {
+		"repo_name": "repo",
+		"commit_sha": "0123abcd",
+		"logstream_id": "logstream",
+		"logstream_url": "https://logs",
+		"relationships": [],
+		"created_at": at.to_rfc3339(),
+		"status": "running",
+		"started_at": at.to_rfc3339(),
+		"finished_at": null,
+	};
+	let legacy = bson::to_vec(&legacy).unwrap();
+	assert_eq!(bson::from_slice::<Submission>(&legacy).unwrap(), submission);
+}
+
+#[actix_web::test]
+async fn repositories_keep_the_stages_of_the_course_version_they_are_on() {
+	let fixture = fixture();
+	let app = init_app(&fixture).await;
+	let repo_name = create_repo(&app).await;
+
+	// A new first stage in the next version of the course
+	let admin_id = ObjectId::new().to_hex();
+	let request = test::TestRequest::put()
+		.uri("/api/v0/course/66b5f3a2c1d2e3f4a5b6c7d8")
+		.insert_header(("authorization", token(&admin_id, Role::Admin)))
+		.set_json(json!({ "stages": [
+			{ "slug": "intro", "title": "Intro", "description": "", "testerStageId": "t0" },
+			{ "slug": "setup", "title": "Setup", "description": "", "testerStageId": "t1" },
+			{ "slug": "balances", "title": "Balances", "description": "", "testerStageId": "t2" },
+		]}));
+	let response = test::call_service(&app, request.to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+
+	let result = json!({ "status": "passed", "stages": [{ "stage": "t1", "passed": true }] });
+	let logstream_id = create_submission(&fixture, &app, &repo_name).await;
+	let uri = format!("/api/v0/tester/submissions/{}", logstream_id);
+	let response = test::call_service(&app, signed(&uri, &result).to_request()).await;
+	assert_eq!(response.status(), StatusCode::OK);
+	let repository = fixture.store.get_repository(&repo_name).await.unwrap().expect("stored");
+	assert_eq!(repository.progress.current_stage.as_deref(), Some("balances"));
+
+	let request = test::TestRequest::get()
+		.uri(&format!("/api/v0/user/{}/repositories", USER_ID))
+		.insert_header(("authorization", token(USER_ID, Role::User)));
+	let repositories: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+	assert_eq!(repositories[0]["course"]["stage_count"], 2);
+
+	let request = test::TestRequest::post()
+		.uri(&format!("/api/v0/repository/{}/reset", repo_name))
+		.insert_header(("authorization", token(USER_ID, Role::User)))
+		.set_json(json!({}));
+	let repository: Value = test::call_and_read_body_json(&app, request.to_request()).await;
+	assert_eq!(repository["progress"]["current_stage"], "setup");
+}
+
+#[actix_web::test]
+async fn a_course_version_saved_concurrently_is_archived_once() {
+	let fixture = fixture();
+	let id = ObjectId::parse_str("66b5f3a2c1d2e3f4a5b6c7d8").unwrap();
+	let current = fixture.store.get_course(&id).await.unwrap().expect("stored");
+
+	let mut renamed = current.clone();
+	renamed.title = "Renamed".to_string();
+	let saved = save_course_version(fixture.store.as_ref(), &current, renamed).await.unwrap();
+	assert_eq!(saved.version, "2");
+
+	// A writer that read the course before the first save lost the race
+	let mut stale = current.clone();
+	stale.title = "Stale".to_string();
+	let error = save_course_version(fixture.store.as_ref(), &current, stale).await.unwrap_err();
+	assert!(matches!(error, DbError::Conflict(_)), "{}", error);
+
+	let versions = fixture.store.list_course_versions(&id).await.unwrap();
+	assert_eq!(versions.len(), 1);
+	assert_eq!(versions[0].version, "1");
+	let course = fixture.store.get_course(&id).await.unwrap().expect("stored");
+	assert_eq!(course.title, "Renamed");
+}
@@ -1,18 +1,40 @@
-use crate::models::Relationship;
+use crate::models::{
+	Author, Course, Repository, RepositoryProgress, RepositoryRelationships, Stage, StageResult,
+	Submission,
+};
+use mongodb::bson::oid::ObjectId;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
-use strum_macros::Display;
+use strum_macros::{Display, VariantNames};
 
 /// The type of document. This is used to identify the type of document in the relationships between
 /// documents.
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum DocumentType {
+	/// Written as `repositories` by older versions
+	#[serde(alias = "repositories")]
 	Repository,
 	User,
 	Course,
 }
 
+impl std::str::FromStr for DocumentType {
+	type Err = String;
+
+	/// Parse a document type by its singular or plural name, as used in `?include=`
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"repository" | "repositories" => Ok(DocumentType::Repository),
+			"user" | "users" => Ok(DocumentType::User),
+			"course" | "courses" => Ok(DocumentType::Course),
+			other => Err(format!(
+				"Unknown relationship `{}` in include, expected repository, user or course",
+				other
+			)),
+		}
+	}
+}
+
 /// Expected activity frequency for a repository. This is used to determine how often the user wants
 /// to practice.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Display)]
@@ -23,7 +45,68 @@ pub enum ExpectedPracticeFrequency {
 	OnceAMonth,
 }
 
-#[derive(serde::Deserialize)]
+/// How challenging a course is
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum Difficulty {
+	Beginner,
+	Intermediate,
+	Advanced,
+}
+
+/// Whether a course is listed in the catalog. Courses created through the admin API start out as
+/// drafts; courses predating it are published.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum CourseStatus {
+	Draft,
+	#[default]
+	Published,
+}
+
+/// Where a submission is in its lifecycle. A submission starts out queued, runs once a tester
+/// worker claims it, and ends in one of the finished states. A running submission whose worker
+/// stops heartbeating is queued again, or times out once it has been delivered too often.
+#[derive(
+	Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, Display, VariantNames,
+)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum SubmissionStatus {
+	#[default]
+	Queued,
+	Running,
+	Passed,
+	Failed,
+	Errored,
+	TimedOut,
+}
+
+impl SubmissionStatus {
+	/// Whether the submission has finished running, successfully or not
+	pub fn is_finished(&self) -> bool {
+		matches!(
+			self,
+			SubmissionStatus::Passed |
+				SubmissionStatus::Failed |
+				SubmissionStatus::Errored |
+				SubmissionStatus::TimedOut
+		)
+	}
+
+	/// Whether a submission in this state may move to `next`. Finished submissions never change.
+	pub fn can_transition_to(&self, next: SubmissionStatus) -> bool {
+		match self {
+			SubmissionStatus::Queued => next != SubmissionStatus::Queued,
+			SubmissionStatus::Running => next == SubmissionStatus::Queued || next.is_finished(),
+			_ => false,
+		}
+	}
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct CreateRepoRequest {
 	pub repo_template: String,
 	pub(super) user_id: String,
@@ -37,26 +120,310 @@ pub struct CreateRepoResponse {
 	pub repo_template: String,
 }
 
-#[derive(serde::Deserialize)]
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct CreateSubmissionRequest {
 	pub repo_name: String,
 	pub commit_sha: String,
 }
 
-#[derive(serde::Serialize)]
+#[derive(serde::Serialize, serde::Deserialize)]
 pub struct CreateSubmissionResponse {
 	pub logstream_url: String,
 	pub logstream_id: String,
 	pub ws_url: String,
 	pub tester_url: String,
+	pub tester_version: Option<String>,
 }
 
+/// Fields of a repository a client may update. Test results can only be recorded by the tester,
+/// see [`TesterResultRequest`].
 #[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct UpdateRepoRequest {
 	pub expected_practice_frequency: Option<ExpectedPracticeFrequency>,
 	pub is_reminder_enabled: Option<bool>,
+	pub relationships: Option<RepositoryRelationships>,
+}
+
+impl UpdateRepoRequest {
+	/// Whether the update leaves every field as it is
+	pub fn is_empty(&self) -> bool {
+		self.expected_practice_frequency.is_none() &&
+			self.is_reminder_enabled.is_none() &&
+			self.relationships.is_none()
+	}
+}
+
+/// Options for restarting the course on an existing repository
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ResetRepoRequest {
+	/// Also replace the contents of the git repository with the course template
+	#[serde(default)]
+	pub reseed: bool,
+}
+
+/// The outcome of a test run, reported by the tester through the signed callback endpoint
+#[derive(serde::Deserialize)]
+pub struct TesterResultRequest {
+	pub repo_name: String,
+	pub logstream_id: Option<String>,
+	pub test_ok: bool,
+}
+
+/// A progress report for a single submission, sent by the tester through the signed callback
+/// endpoint. Stage results are merged into those already recorded, by stage.
+#[derive(serde::Deserialize)]
+pub struct SubmissionReportRequest {
+	pub status: SubmissionStatus,
+	#[serde(default)]
+	pub stages: Vec<StageResult>,
+}
+
+/// Identifies the tester worker claiming a submission job or heartbeating one it holds
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkerRequest {
+	pub worker_id: String,
+}
+
+/// A submission job handed to a tester worker. The worker must heartbeat within
+/// `visibility_timeout_secs` of the claim and of each heartbeat, or the job is redelivered.
+#[derive(serde::Serialize)]
+pub struct ClaimedJobResponse {
+	pub logstream_id: String,
+	pub repo_name: String,
+	pub commit_sha: String,
+	pub logstream_url: String,
+	pub tester_url: String,
+	pub tester_version: Option<String>,
+	/// How many times the job has been handed out, including this time
+	pub delivery: u32,
+	pub visibility_timeout_secs: u64,
+}
+
+/// Query parameters for paginated listings. Pages are numbered from 1.
+#[derive(serde::Deserialize)]
+pub struct PaginationQuery {
+	pub page: Option<u64>,
+	pub per_page: Option<u64>,
+}
+
+impl PaginationQuery {
+	const DEFAULT_PER_PAGE: u64 = 20;
+	const MAX_PER_PAGE: u64 = 100;
+
+	pub fn page(&self) -> u64 {
+		self.page.unwrap_or(1).max(1)
+	}
+
+	pub fn per_page(&self) -> u64 {
+		self.per_page.unwrap_or(Self::DEFAULT_PER_PAGE).clamp(1, Self::MAX_PER_PAGE)
+	}
+
+	/// The number of items preceding the requested page
+	pub fn offset(&self) -> u64 {
+		(self.page() - 1) * self.per_page()
+	}
+}
+
+/// Query parameters for the course catalog. `q` matches the course name, title or author name;
+/// `tags` is a comma separated list of tags a course must all have.
+#[derive(serde::Deserialize)]
+pub struct CourseQuery {
+	pub page: Option<u64>,
+	pub per_page: Option<u64>,
+	pub q: Option<String>,
+	pub language: Option<String>,
+	pub difficulty: Option<Difficulty>,
+	pub tags: Option<String>,
+}
+
+impl CourseQuery {
+	pub fn pagination(&self) -> PaginationQuery {
+		PaginationQuery { page: self.page, per_page: self.per_page }
+	}
+
+	pub fn filter(&self) -> CourseFilter {
+		let non_empty = |value: &Option<String>| {
+			value
+				.as_deref()
+				.map(str::trim)
+				.filter(|value| !value.is_empty())
+				.map(String::from)
+		};
+
+		CourseFilter {
+			include_drafts: false,
+			query: non_empty(&self.q),
+			language: non_empty(&self.language),
+			difficulty: self.difficulty,
+			tags: self
+				.tags
+				.as_deref()
+				.unwrap_or_default()
+				.split(',')
+				.map(str::trim)
+				.filter(|tag| !tag.is_empty())
+				.map(String::from)
+				.collect(),
+		}
+	}
+}
+
+/// Related documents to embed in a response, JSON:API style. `include` is a comma separated list of
+/// document types, such as `user,course`.
+#[derive(serde::Deserialize)]
+pub struct IncludeQuery {
+	pub include: Option<String>,
+}
+
+impl IncludeQuery {
+	/// The requested document types, without duplicates
+	pub fn document_types(&self) -> Result<Vec<DocumentType>, String> {
+		let mut types = vec![];
+		for name in self.include.as_deref().unwrap_or_default().split(',').map(str::trim) {
+			if name.is_empty() {
+				continue;
+			}
+			let document_type = name.parse()?;
+			if !types.contains(&document_type) {
+				types.push(document_type);
+			}
+		}
+		Ok(types)
+	}
+}
+
+/// Criteria a course must match to be listed in the catalog. Empty criteria match every published
+/// course.
+#[derive(Clone, Debug, Default)]
+pub struct CourseFilter {
+	pub include_drafts: bool,
+	pub query: Option<String>,
+	pub language: Option<String>,
+	pub difficulty: Option<Difficulty>,
+	pub tags: Vec<String>,
+}
+
+/// A new course, created as a draft at version 1
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct CreateCourseRequest {
+	pub slug: String,
+	pub name: String,
+	pub title: String,
+	pub author: Author,
+	pub tester_url: String,
+	pub tester_version: Option<String>,
+	pub language: Option<String>,
+	pub difficulty: Option<Difficulty>,
+	#[serde(default)]
+	pub tags: Vec<String>,
+	#[serde(default)]
+	pub stages: Vec<Stage>,
+}
+
+/// Changes to a course. Every update archives the current version and bumps the version number.
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct UpdateCourseRequest {
+	pub name: Option<String>,
+	pub title: Option<String>,
+	pub author: Option<Author>,
+	pub tester_url: Option<String>,
+	pub tester_version: Option<String>,
+	pub language: Option<String>,
+	pub difficulty: Option<Difficulty>,
+	pub tags: Option<Vec<String>>,
+	pub stages: Option<Vec<Stage>>,
+}
+
+/// A document along with the related documents requested through `?include=`. `included` is left
+/// out when nothing was requested.
+#[derive(serde::Serialize)]
+pub struct IncludedResponse<T> {
+	#[serde(flatten)]
+	pub data: T,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub included: Vec<IncludedDocument>,
+}
+
+/// A related document embedded in a response, tagged with its type
+#[derive(serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IncludedDocument {
+	Repository(Repository),
+	User(UserResponse),
+	Course(Course),
+}
+
+#[derive(serde::Serialize)]
+pub struct CoursePageResponse {
+	pub courses: Vec<Course>,
+	pub page: u64,
+	pub per_page: u64,
+	pub has_more: bool,
+}
+
+#[derive(serde::Serialize)]
+pub struct SubmissionPageResponse {
+	pub submissions: Vec<Submission>,
+	pub page: u64,
+	pub per_page: u64,
+	pub has_more: bool,
+}
+
+/// Profile fields of a user a client may update
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UpdateUserRequest {
+	pub name: Option<String>,
+	pub email: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+pub struct UserResponse {
+	pub id: ObjectId,
+	pub name: String,
+	pub email: Option<String>,
+}
+
+/// The course a repository was created from, as listed alongside the repository
+#[derive(serde::Serialize)]
+pub struct CourseSummary {
+	pub id: ObjectId,
+	pub slug: String,
+	pub title: String,
+	pub stage_count: usize,
+}
+
+/// A repository as listed on its owner's dashboard
+#[derive(serde::Serialize)]
+pub struct UserRepositoryResponse {
+	pub repo_name: String,
+	pub repo_template: String,
+	pub course: Option<CourseSummary>,
+	pub progress: RepositoryProgress,
 	pub test_ok: Option<bool>,
-	pub relationships: Option<HashMap<String, Relationship>>,
+	pub expected_practice_frequency: ExpectedPracticeFrequency,
+	pub is_reminder_enabled: bool,
+	pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+	pub last_submission_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+/// Practice statistics for a repository. Streaks are counted in days, weeks or months depending on
+/// `expected_practice_frequency`.
+#[derive(serde::Serialize)]
+pub struct RepositoryStatsResponse {
+	pub repo_name: String,
+	pub expected_practice_frequency: ExpectedPracticeFrequency,
+	pub current_streak: u64,
+	pub longest_streak: u64,
+	pub total_active_days: u64,
+	pub total_submissions: u64,
+	pub created_at: Option<chrono::DateTime<chrono::Utc>>,
+	pub last_activity_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 #[derive(serde::Serialize)]
@@ -64,8 +431,9 @@ pub struct UpdateRepoResponse {
 	pub repo_name: String,
 	pub repo_template: String,
 	pub tester_url: String,
+	pub tester_version: Option<String>,
 	pub test_ok: Option<bool>,
-	pub relationships: HashMap<String, Relationship>,
+	pub relationships: RepositoryRelationships,
 	pub expected_practice_frequency: ExpectedPracticeFrequency,
 	pub is_reminder_enabled: bool,
 }
@@ -1,246 +1,399 @@
 use std::collections::HashMap;
 
 use log::{error, info, warn};
-use mongodb::{
-	bson::{self, doc, oid::ObjectId},
-	Client,
-};
-use rand::prelude::*;
+use mongodb::bson::oid::ObjectId;
 
 use crate::{
-	constants::{DB_NAME, GIT_SERVER_URL, REPO_COLLECTION, SUBMISSION_COLLECTION, USER_COLLECTION},
-	errors::{DbError, RepoCreationError},
-	models::{self, Course, Repository},
+	auth::Identity,
+	config::Config,
+	errors::{AuthError, DbError, GitHostError, RepoCreationError},
+	git_host::GitHost,
+	integrity::{self, IntegrityReport},
+	models::{
+		self, Course, CourseVersion, PendingRepository, Repository, RepositoryProgress,
+		RepositoryRelationships, StageProgress, StageResult, Submission, TesterNonce,
+	},
+	naming::RepoNamer,
+	queue::{SubmissionJob, SubmissionQueue},
+	stats,
+	store::{IndexReport, Store},
 	types::{
-		CreateRepoRequest, CreateSubmissionRequest, CreateSubmissionResponse, DocumentType,
-		UpdateRepoRequest,
+		ClaimedJobResponse, CourseFilter, CoursePageResponse, CourseQuery, CourseStatus,
+		CourseSummary, CreateCourseRequest, CreateRepoRequest, CreateSubmissionRequest,
+		CreateSubmissionResponse, DocumentType, IncludeQuery, IncludedDocument, IncludedResponse,
+		PaginationQuery, RepositoryStatsResponse, ResetRepoRequest, SubmissionPageResponse,
+		SubmissionReportRequest, SubmissionStatus, TesterResultRequest, UpdateCourseRequest,
+		UpdateRepoRequest, UpdateUserRequest, UserRepositoryResponse, UserResponse, WorkerRequest,
 	},
 	ExpectedPracticeFrequency,
 };
 
-/// Generate a repository ID
-/// This is used to identify the repository on the git server
-pub(super) fn generate_repo_id() -> String {
-	// Generate a random 16 character string
-	let mut repo_id = hex::encode(rand::thread_rng().gen::<[u8; 16]>()).to_string();
-	repo_id.truncate(16);
-	repo_id
-}
+/// How many names to try for a new repository before giving up on collisions
+pub(super) const MAX_REPO_NAME_ATTEMPTS: u32 = 5;
+
+/// Longest worker id accepted from a tester worker
+const MAX_WORKER_ID_LEN: usize = 255;
+
+/// Length of the longest commit hash, as used by SHA-256 repositories. Abbreviated hashes down to
+/// git's minimum of 4 characters are accepted too.
+const MAX_COMMIT_SHA_LEN: usize = 64;
 
 /// Generate a unique submission ID
 pub(super) fn generate_submission_id() -> String {
 	uuid::Uuid::new_v4().to_string()
 }
 
-/// Fetch course data from database
-pub(super) async fn fetch_course(client: &Client, id: &str) -> Result<Course, DbError> {
-	let collection = client.database(DB_NAME).collection("courses");
+/// Whether the caller may see a course. Drafts are only visible to admins.
+fn is_course_visible(course: &Course, identity: Option<&Identity>) -> bool {
+	course.status == CourseStatus::Published || identity.is_some_and(Identity::is_admin)
+}
+
+/// Fetch the documents related through `relationships` whose type was requested with `?include=`,
+/// with one query per document type. Documents the caller may not see are left out, and users are
+/// included without their email unless the caller may see it.
+async fn include_related<'a>(
+	store: &dyn Store,
+	identity: Option<&Identity>,
+	relationships: impl IntoIterator<Item = &'a models::Relationship>,
+	include: &IncludeQuery,
+) -> Result<Vec<IncludedDocument>, DbError> {
+	let document_types = include.document_types().map_err(DbError::Validation)?;
+	if document_types.is_empty() {
+		return Ok(vec![]);
+	}
+	let relationships: Vec<_> = relationships.into_iter().collect();
+
+	let mut included = vec![];
+	for document_type in document_types {
+		let mut ids: Vec<ObjectId> = vec![];
+		for relationship in &relationships {
+			if relationship.r#type == document_type && !ids.contains(&relationship.id) {
+				ids.push(relationship.id);
+			}
+		}
+		if ids.is_empty() {
+			continue;
+		}
+
+		for document in store.get_related_documents(document_type, &ids).await? {
+			match document {
+				models::RelatedDocument::Repository(repository)
+					if identity.is_some_and(|identity| {
+						identity.authorize_repository(&repository).is_ok()
+					}) =>
+					included.push(IncludedDocument::Repository(repository)),
+				models::RelatedDocument::Repository(_) => {},
+				models::RelatedDocument::User(id, user) => {
+					let can_see_email =
+						identity.is_some_and(|identity| identity.authorize_user(&id).is_ok());
+					included.push(IncludedDocument::User(UserResponse {
+						id,
+						name: user.name,
+						email: user.email.filter(|_| can_see_email),
+					}));
+				},
+				models::RelatedDocument::Course(course) if is_course_visible(&course, identity) =>
+					included.push(IncludedDocument::Course(course)),
+				models::RelatedDocument::Course(_) => {},
+			}
+		}
+	}
+
+	Ok(included)
+}
+
+/// Fetch course data from database, along with the related documents requested with `?include=`
+pub(super) async fn fetch_course(
+	store: &dyn Store,
+	identity: Option<&Identity>,
+	id: &str,
+	include: &IncludeQuery,
+) -> Result<IncludedResponse<Course>, DbError> {
 	let id = ObjectId::parse_str(id).map_err(|e| {
 		error!("Invalid ObjectId: {}", id);
-		DbError::InternalServerError(e.to_string())
+		DbError::InvalidObjectId(e)
 	})?;
 
-	let filter = doc! { "_id": id };
-	let course = collection.find_one(filter).await?;
-
-	log::debug!("{:#?}", course);
-
-	let result = match course {
-		Some(course) => match bson::from_document::<Course>(course) {
-			Ok(course) => {
-				info!("Fetched course: {:?}", course);
-				Ok(course)
-			},
-			Err(e) => {
-				error!("Failed to deserialize course: {}", e);
-				Err(DbError::InternalServerError(format!(
-					"Failed to deserialize course with id {}",
-					id
-				)))
-			},
+	let result = match store.get_course(&id).await? {
+		Some(course) if is_course_visible(&course, identity) => {
+			info!("Fetched course: {:?}", course);
+			Ok(course)
 		},
-		None => Err(DbError::InternalServerError(format!("Course with id {} not found", id))),
+		_ => Err(DbError::NotFound(format!("Course with id {} not found", id))),
 	};
 
 	log::debug!("{:#?}", result);
-	result
+	let course = result?;
+
+	let included = include_related(store, identity, &course.relationships, include).await?;
+	Ok(IncludedResponse { data: course, included })
 }
 
-/// Create a repository on the git server and insert it into the database
+/// Fetch a course by its slug
+pub(super) async fn fetch_course_by_slug(
+	store: &dyn Store,
+	identity: Option<&Identity>,
+	slug: &str,
+	include: &IncludeQuery,
+) -> Result<IncludedResponse<Course>, DbError> {
+	let course = match store.get_course_by_slug(slug).await? {
+		Some(course) if is_course_visible(&course, identity) => course,
+		_ => return Err(DbError::NotFound(format!("Course with slug `{}` not found", slug))),
+	};
+
+	let included = include_related(store, identity, &course.relationships, include).await?;
+	Ok(IncludedResponse { data: course, included })
+}
+
+/// List a page of the course catalog, ordered by title. Admins also see drafts.
+pub(super) async fn list_courses(
+	store: &dyn Store,
+	identity: Option<&Identity>,
+	query: &CourseQuery,
+) -> Result<CoursePageResponse, DbError> {
+	let pagination = query.pagination();
+	let per_page = pagination.per_page();
+	let filter =
+		CourseFilter { include_drafts: identity.is_some_and(Identity::is_admin), ..query.filter() };
+
+	// Fetch one extra course to find out whether there is another page
+	let mut courses = store.list_courses(&filter, pagination.offset(), per_page + 1).await?;
+	let has_more = courses.len() as u64 > per_page;
+	courses.truncate(per_page as usize);
+
+	Ok(CoursePageResponse { courses, page: pagination.page(), per_page, has_more })
+}
+
+/// Create a repository on the git server and insert it into the database.
+///
+/// Creation is all-or-nothing: the repository document and the owner's repository list are written
+/// in one transaction, and the git repository is deleted again if that transaction fails. A pending
+/// marker is kept for the duration so that [`crate::reconcile`] can repair a creation interrupted
+/// by a crash.
 pub(super) async fn do_create_repo(
-	client: &Client,
+	store: &dyn Store,
+	git_host: &dyn GitHost,
+	namer: &dyn RepoNamer,
+	identity: &Identity,
 	json: &CreateRepoRequest,
 ) -> Result<String, RepoCreationError> {
-	let repo_name = generate_repo_id();
 	let repo_template = json.repo_template.clone();
 	let user_id = json.user_id.clone();
 	let user_id = ObjectId::parse_str(&user_id).map_err(|e| {
 		error!("Invalid ObjectId: {}", user_id);
 		RepoCreationError::InvalidObjectId(e)
 	})?;
+	identity.authorize_user(&user_id)?;
 	let expected_practice_frequency = json.expected_practice_frequency.clone();
 	let is_reminder_enabled = json.is_reminder_enabled;
 
-	info!("Creating repository `{}` using template `{}` with expected practice frequency `{}` and reminders `{}`", repo_name, &repo_template, &expected_practice_frequency, &is_reminder_enabled);
-
-	create_git_repo(&repo_name, &repo_template).await?;
-	let repo_id = insert_repo_into_db(
-		client,
-		&repo_name,
-		&repo_template,
-		&user_id,
-		expected_practice_frequency,
-		is_reminder_enabled,
-	)
-	.await?;
-	update_user_repo_list(client, &user_id, repo_id).await?;
+	let course = get_course_by_slug(store, &repo_template).await?;
 
-	info!("Successfully created repository `{}` on git server", repo_name);
+	let mut attempt = 1;
+	loop {
+		let repo_name = namer.name(&course);
+		info!("Creating repository `{}` using template `{}` with expected practice frequency `{}` and reminders `{}`", repo_name, &repo_template, &expected_practice_frequency, &is_reminder_enabled);
 
-	Ok(repo_name)
+		let repository = build_repository(
+			&repo_name,
+			&repo_template,
+			&user_id,
+			&course,
+			expected_practice_frequency.clone(),
+			is_reminder_enabled,
+		);
+		match create_named_repo(store, git_host, repository, &user_id).await {
+			Ok(()) => {
+				info!("Successfully created repository `{}` on git server", repo_name);
+				return Ok(repo_name);
+			},
+			Err(
+				RepoCreationError::Conflict(_) |
+				RepoCreationError::GitServerError(GitHostError::Conflict(_)),
+			) if attempt < MAX_REPO_NAME_ATTEMPTS => {
+				warn!("Repository name `{}` is taken, retrying with a new name", repo_name);
+				attempt += 1;
+			},
+			Err(e) => return Err(e),
+		}
+	}
 }
 
-/// Send a post request to the git server to create a repository
-async fn create_git_repo(repo_name: &str, template: &str) -> Result<(), RepoCreationError> {
-	let client = reqwest::Client::new();
-	let url = format!("{}/api/v0/create_repository", GIT_SERVER_URL);
+/// Create a repository on the git server and save it, rolling back the git server on failure. A
+/// name already taken in the database or on the git server fails with a conflict, leaving the
+/// existing repository untouched.
+async fn create_named_repo(
+	store: &dyn Store,
+	git_host: &dyn GitHost,
+	repository: Repository,
+	user_id: &ObjectId,
+) -> Result<(), RepoCreationError> {
+	let repo_name = repository.repo_name.clone();
+	let repo_template = repository.repo_template.clone();
 
-	let json = HashMap::from([("repo_name", repo_name), ("template_repo", template)]);
+	store
+		.insert_pending_repository(PendingRepository {
+			repo_name: repo_name.clone(),
+			repo_template: repo_template.clone(),
+			user_id: *user_id,
+			created_at: chrono::Utc::now(),
+		})
+		.await?;
 
-	let request = client.post(&url);
-	let request = add_bearer_token_if_available(request);
+	if let Err(e) = git_host.create_repo(&repo_name, &repo_template).await {
+		error!("Failed to create repository `{}` on git server: {}", repo_name, e);
+		clear_pending_repository(store, &repo_name).await;
+		return Err(e.into());
+	}
 
-	let response = request.json(&json).send().await?;
-	response.error_for_status()?;
+	if let Err(e) = insert_repo_into_db(store, repository, user_id).await {
+		error!("Failed to save repository `{}`, rolling back: {}", repo_name, e);
+		match git_host.delete_repo(&repo_name).await {
+			Ok(()) => clear_pending_repository(store, &repo_name).await,
+			// Leave the pending marker so the reconciler retries the deletion
+			Err(delete_error) => error!(
+				"Failed to delete repository `{}` from git server during rollback: {}",
+				repo_name, delete_error
+			),
+		}
+		return Err(e);
+	}
 
+	clear_pending_repository(store, &repo_name).await;
 	Ok(())
 }
 
-/// Insert a repository into the database
-pub(super) async fn insert_repo_into_db(
-	client: &Client,
+/// Build the repository document for a new repository
+fn build_repository(
 	repo_name: &str,
 	template: &str,
 	user_id: &ObjectId,
+	course: &Course,
 	expected_practice_frequency: ExpectedPracticeFrequency,
 	is_reminder_enabled: bool,
-) -> Result<mongodb::bson::oid::ObjectId, RepoCreationError> {
-	let collection = client.database(DB_NAME).collection(REPO_COLLECTION);
-	let course_id = get_course_id_by_slug(client, template).await?;
-
-	let mut relationships = HashMap::new();
-	relationships.insert(
-		"user".to_string(),
-		models::Relationship { id: *user_id, r#type: DocumentType::User },
-	);
-	relationships.insert(
-		"course".to_string(),
-		models::Relationship { id: course_id, r#type: DocumentType::Course },
-	);
+) -> Repository {
+	let relationships = RepositoryRelationships {
+		user: Some(models::Relationship { id: *user_id, r#type: DocumentType::User }),
+		course: Some(models::Relationship { id: course.id, r#type: DocumentType::Course }),
+	};
 
-	let repository = Repository {
+	Repository {
+		id: None,
 		repo_name: repo_name.to_string(),
 		repo_template: template.to_string(),
-		// TODO: Use the correct URL based on the template
-		tester_url: format!("https://github.com/dotcodeschool/{}-tester", template),
+		tester_url: course.tester_url.clone(),
+		tester_version: course.tester_version.clone(),
+		course_version: Some(course.version.clone()),
 		test_ok: None,
 		relationships,
 		expected_practice_frequency,
 		is_reminder_enabled,
-	};
-
-	let result = collection.insert_one(repository).await?;
-
-	result.inserted_id.as_object_id().ok_or_else(|| {
-		error!("Failed to get ObjectId after inserting repository `{}` into database", repo_name);
-		RepoCreationError::InsertionError("Failed to get ObjectId after insertion".into())
-	})
-}
-
-/// Get the course ID using the course slug
-pub(super) async fn get_course_id_by_slug(
-	client: &Client,
-	slug: &str,
-) -> Result<ObjectId, RepoCreationError> {
-	let collection = client.database(DB_NAME).collection("courses");
-
-	let filter = doc! { "slug": slug };
-	let course = collection.find_one(filter).await?;
-
-	match course {
-		Some(course) => {
-			let course: models::Course = match mongodb::bson::de::from_document(course) {
-				Ok(course) => course,
-				Err(e) => {
-					error!("Failed to deserialize course: {}", e);
-					return Err(RepoCreationError::InternalServerError(e.to_string()));
-				},
-			};
-			Ok(course.id)
-		},
-		None => {
-			warn!("Course with slug `{}` not found", slug);
-			Err(RepoCreationError::NotFound(slug.to_string()))
+		progress: RepositoryProgress {
+			current_stage: course.stages.first().map(|stage| stage.slug.clone()),
+			stages: vec![],
 		},
+		created_at: Some(chrono::Utc::now()),
+		last_submission_at: None,
+		archived_at: None,
 	}
 }
 
-/// Update the user's repository list in the database
-pub(super) async fn update_user_repo_list(
-	client: &Client,
+/// Insert a repository into the database and add it to the user's repository list
+pub(super) async fn insert_repo_into_db(
+	store: &dyn Store,
+	repository: Repository,
 	user_id: &ObjectId,
-	repo_id: ObjectId,
-) -> Result<(), RepoCreationError> {
-	let collection: mongodb::Collection<models::User> =
-		client.database(DB_NAME).collection(USER_COLLECTION);
-
-	let filter = doc! { "_id": user_id };
-	let update = doc! { "$addToSet": { "relationships.repositories.data": { "id": repo_id, "type": "repositories" }} };
+) -> Result<ObjectId, RepoCreationError> {
+	let repo_name = repository.repo_name.clone();
 
-	collection
-		.update_one(filter, update)
+	store
+		.create_repository(repository, user_id)
 		.await
-		.map(|_| info!("Successfully updated user `{}` with repository `{}`", user_id, repo_id))
-		.map_err(RepoCreationError::from)
+		.inspect(|_| {
+			info!("Successfully updated user `{}` with repository `{}`", user_id, repo_name)
+		})
+		.map_err(|e| match e {
+			DbError::InternalServerError(e) => RepoCreationError::InsertionError(e),
+			e => RepoCreationError::from(e),
+		})
 }
 
-/// Add a bearer token to the request if available
-fn add_bearer_token_if_available(request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
-	match std::env::var("BEARER_TOKEN_SECRET") {
-		Ok(token) => {
-			info!("Using bearer token to authenticate with git server");
-			request.bearer_auth(token)
-		},
-		Err(_) => {
-			warn!("No bearer token found, proceeding without authentication");
-			request
+/// Remove the pending marker for a repository. A failure is only logged, since the reconciler
+/// removes stale markers for repositories that were created successfully.
+async fn clear_pending_repository(store: &dyn Store, repo_name: &str) {
+	if let Err(e) = store.delete_pending_repository(repo_name).await {
+		warn!("Failed to clear pending marker for repository `{}`: {}", repo_name, e);
+	}
+}
+
+/// Get the course using the course slug
+pub(super) async fn get_course_by_slug(
+	store: &dyn Store,
+	slug: &str,
+) -> Result<Course, RepoCreationError> {
+	match store.get_course_by_slug(slug).await? {
+		Some(course) if course.status == CourseStatus::Published => Ok(course),
+		_ => {
+			warn!("Published course with slug `{}` not found", slug);
+			Err(RepoCreationError::UnknownTemplate(slug.to_string()))
 		},
 	}
 }
 
 /// Create a submission for a repository.
 /// This will generate a unique submission ID and return the logstream and tester URL.
-/// The submission will be inserted into the database.
+/// The commit must exist in the repository on the git host. The submission will be inserted into
+/// the database and queued for a tester worker.
 pub(super) async fn do_create_submission(
-	client: &Client,
-	redis_uri: &str,
-	ws_url: &str,
+	store: &dyn Store,
+	git_host: &dyn GitHost,
+	queue: &dyn SubmissionQueue,
+	identity: &Identity,
+	config: &Config,
 	json: &CreateSubmissionRequest,
 ) -> Result<CreateSubmissionResponse, DbError> {
 	let repo_name = &json.repo_name;
 	let commit_sha = &json.commit_sha;
-	let ws_url = ws_url.to_string();
+	let ws_url = config.ws_url().to_string();
+	let dedup_window =
+		std::time::Duration::from_secs(config.idempotency.submission_dedup_window_secs);
 
 	info!("Creating submission for repository `{}` with commit `{}`", repo_name, commit_sha);
 
-	let repository = get_repo_from_db(client, repo_name).await?;
-	let tester_url = repository.tester_url.clone();
+	validate_commit_sha(commit_sha)?;
+	let repository = get_authorized_repo(store, identity, repo_name).await?;
+	if !git_host.commit_exists(repo_name, commit_sha).await? {
+		return Err(DbError::Validation(format!(
+			"Commit `{}` does not exist in repository `{}`",
+			commit_sha, repo_name
+		)));
+	}
+	let (tester_url, tester_version) = resolve_tester(store, &repository).await?;
+
+	if !dedup_window.is_zero() {
+		let window = chrono::Duration::from_std(dedup_window)
+			.map_err(|e| DbError::InternalServerError(e.to_string()))?;
+		let since = chrono::Utc::now() - window;
+		if let Some(existing) = store.find_recent_submission(repo_name, commit_sha, since).await? {
+			info!(
+				"Commit `{}` was already submitted to repository `{}` at {}, returning that submission",
+				commit_sha, repo_name, existing.created_at
+			);
+			return Ok(CreateSubmissionResponse {
+				logstream_id: existing.logstream_id,
+				logstream_url: existing.logstream_url,
+				ws_url,
+				tester_url,
+				tester_version,
+			});
+		}
+	}
 
 	let logstream_id = generate_submission_id();
-	let logstream_url = format!("{}/{}", redis_uri, logstream_id);
+	let logstream_url = format!("{}/{}", config.redis_uri(), logstream_id);
 
 	insert_submission_into_db(
-		client,
+		store,
 		repo_name.to_string(),
 		commit_sha.to_string(),
 		logstream_id.to_string(),
@@ -248,116 +401,680 @@ pub(super) async fn do_create_submission(
 	)
 	.await?;
 
+	let job = SubmissionJob {
+		logstream_id: logstream_id.clone(),
+		repo_name: repo_name.clone(),
+		commit_sha: commit_sha.clone(),
+		logstream_url: logstream_url.clone(),
+		tester_url: tester_url.clone(),
+		tester_version: tester_version.clone(),
+	};
+	if let Err(e) = queue.enqueue(job).await {
+		// Nothing would ever run the submission, so it must not be left queued
+		if let Err(e) =
+			advance_submission(store, &logstream_id, SubmissionStatus::Errored, &[]).await
+		{
+			error!("Failed to mark unqueued submission `{}` as errored: {}", logstream_id, e);
+		}
+		return Err(e.into());
+	}
+
 	info!(
 		"Successfully created submission for repository `{}` with logstream url `{}`",
 		repo_name, logstream_url
 	);
 
-	Ok(CreateSubmissionResponse { logstream_id, logstream_url, ws_url, tester_url })
+	Ok(CreateSubmissionResponse { logstream_id, logstream_url, ws_url, tester_url, tester_version })
+}
+
+/// The course a repository works through, at the version the repository is on. Repositories
+/// created before courses were versioned follow the current version. Returns `None` if the
+/// repository has no course, or its course or course version no longer exists.
+async fn repository_course(
+	store: &dyn Store,
+	repository: &Repository,
+) -> Result<Option<Course>, DbError> {
+	let Some(course_id) = repository.relationships.course.as_ref().map(|course| course.id) else {
+		warn!("Repository `{}` has no course", repository.repo_name);
+		return Ok(None);
+	};
+	let Some(course) = store.get_course(&course_id).await? else {
+		warn!("Course `{}` of repository `{}` not found", course_id, repository.repo_name);
+		return Ok(None);
+	};
+
+	match &repository.course_version {
+		Some(version) if *version != course.version => {
+			let archived = store.get_course_version(&course_id, version).await?;
+			if archived.is_none() {
+				warn!(
+					"Version {} of course `{}` of repository `{}` not found",
+					version, course.slug, repository.repo_name
+				);
+			}
+			Ok(archived.map(|archived| archived.course))
+		},
+		_ => Ok(Some(course)),
+	}
+}
+
+/// The tester URL and pinned release to run a repository's submissions with, taken from the
+/// version of its course the repository is on. Falls back to the URL stored on the repository if
+/// its course or course version no longer exists.
+async fn resolve_tester(
+	store: &dyn Store,
+	repository: &Repository,
+) -> Result<(String, Option<String>), DbError> {
+	match repository_course(store, repository).await? {
+		Some(course) => Ok((course.tester_url, course.tester_version)),
+		None => {
+			warn!("Using the stored tester of repository `{}`", repository.repo_name);
+			Ok((repository.tester_url.clone(), repository.tester_version.clone()))
+		},
+	}
 }
 
 /// Fetch a repository from the database. Fail if the repository does not exist.
 pub(super) async fn get_repo_from_db(
-	client: &Client,
+	store: &dyn Store,
 	repo_name: &str,
 ) -> Result<Repository, DbError> {
-	let collection = client.database(DB_NAME).collection(REPO_COLLECTION);
-
-	let filter = doc! { "repo_name": repo_name };
 	info!("Fetching repository `{}` from database", repo_name);
-	let repository = collection.find_one(filter).await;
-
-	info!("Repository: {:?}", repository);
+	let repository = store.get_repository(repo_name).await;
 
 	match repository {
+		Ok(Some(repo)) if repo.archived_at.is_some() => {
+			info!("Repository `{}` is archived", repo_name);
+			Err(DbError::NotFound(format!("Repository `{}` not found", repo_name)))
+		},
 		Ok(Some(repo)) => {
 			info!("Successfully fetched repository `{}` from database", repo_name);
 			Ok(repo)
 		},
 		Ok(None) => {
 			error!("Repository `{}` not found in database", repo_name);
-			Err(DbError::NotFound(actix_web::error::ErrorNotFound(format!(
-				"Repository `{}` not found",
-				repo_name
-			))))
+			Err(DbError::NotFound(format!("Repository `{}` not found", repo_name)))
 		},
 		Err(e) => {
 			error!("Error fetching repository `{}` from database: {:?}", repo_name, e);
-			Err(DbError::DatabaseError(e))
+			Err(e)
 		},
 	}
 }
 
+/// Fetch a repository from the database, failing unless the caller owns it or is an admin
+pub(super) async fn get_authorized_repo(
+	store: &dyn Store,
+	identity: &Identity,
+	repo_name: &str,
+) -> Result<Repository, DbError> {
+	let repository = get_repo_from_db(store, repo_name).await?;
+	identity.authorize_repository(&repository)?;
+	Ok(repository)
+}
+
+/// Fetch a repository the caller owns, along with the related documents requested with `?include=`
+pub(super) async fn get_repository(
+	store: &dyn Store,
+	identity: &Identity,
+	repo_name: &str,
+	include: &IncludeQuery,
+) -> Result<IncludedResponse<Repository>, DbError> {
+	let repository = get_authorized_repo(store, identity, repo_name).await?;
+
+	let included =
+		include_related(store, Some(identity), repository.relationships.iter(), include).await?;
+	Ok(IncludedResponse { data: repository, included })
+}
+
+/// Restart the course on a repository the caller owns. Stage progress and the test result are
+/// cleared, and existing submissions are kept as a previous attempt. With `reseed`, the git
+/// repository is first replaced with the course template.
+pub(super) async fn reset_repository(
+	store: &dyn Store,
+	git_host: &dyn GitHost,
+	identity: &Identity,
+	repo_name: &str,
+	request: &ResetRepoRequest,
+) -> Result<Repository, DbError> {
+	let repository = get_authorized_repo(store, identity, repo_name).await?;
+	let course = repository_course(store, &repository).await?;
+
+	if request.reseed {
+		if let Err(e) = git_host.reseed_repo(repo_name, &repository.repo_template).await {
+			error!("Failed to reseed repository `{}` on git server: {}", repo_name, e);
+			return Err(e.into());
+		}
+	}
+
+	let progress = RepositoryProgress {
+		current_stage: course.and_then(|course| course.stages.first().map(|s| s.slug.clone())),
+		stages: vec![],
+	};
+	let repository = store
+		.reset_repository(repo_name, &progress)
+		.await?
+		.ok_or_else(|| DbError::NotFound(format!("Repository `{}` not found", repo_name)))?;
+
+	info!(
+		"User `{}` reset repository `{}`{}",
+		identity.user_id,
+		repo_name,
+		if request.reseed { " and reseeded it from its template" } else { "" }
+	);
+	Ok(repository)
+}
+
+/// Archive a repository the caller owns. It is hidden from its owner and archived on the git
+/// server until an admin restores it, or it is purged once the grace period has passed.
+pub(super) async fn archive_repository(
+	store: &dyn Store,
+	git_host: &dyn GitHost,
+	identity: &Identity,
+	repo_name: &str,
+) -> Result<Repository, DbError> {
+	get_authorized_repo(store, identity, repo_name).await?;
+
+	let Some(repository) = store.archive_repository(repo_name, chrono::Utc::now()).await? else {
+		return Err(DbError::NotFound(format!("Repository `{}` not found", repo_name)));
+	};
+
+	// The purge deletes the git repository whether or not it was archived, so a failure here only
+	// leaves it reachable until then
+	if let Err(e) = git_host.archive_repo(repo_name).await {
+		error!("Failed to archive repository `{}` on git server: {}", repo_name, e);
+	}
+
+	info!("User `{}` archived repository `{}`", identity.user_id, repo_name);
+	Ok(repository)
+}
+
+/// Restore an archived repository that has not been purged yet. Admin only.
+pub(super) async fn restore_repository(
+	store: &dyn Store,
+	git_host: &dyn GitHost,
+	identity: &Identity,
+	repo_name: &str,
+) -> Result<Repository, DbError> {
+	identity.require_admin()?;
+
+	match store.get_repository(repo_name).await? {
+		Some(repository) if repository.archived_at.is_some() => {},
+		Some(_) =>
+			return Err(DbError::Conflict(format!("Repository `{}` is not archived", repo_name))),
+		None => return Err(DbError::NotFound(format!("Repository `{}` not found", repo_name))),
+	}
+
+	match git_host.restore_repo(repo_name).await {
+		// Archiving may have failed on the git server, leaving the repository where it was
+		Ok(()) | Err(GitHostError::NotFound(_)) => {},
+		Err(e) => {
+			error!("Failed to restore repository `{}` on git server: {}", repo_name, e);
+			return Err(e.into());
+		},
+	}
+
+	let repository = store
+		.restore_repository(repo_name)
+		.await?
+		.ok_or_else(|| DbError::NotFound(format!("Repository `{}` not found", repo_name)))?;
+
+	info!("User `{}` restored repository `{}`", identity.user_id, repo_name);
+	Ok(repository)
+}
+
 /// Insert a submission into the database
 async fn insert_submission_into_db(
-	client: &Client,
+	store: &dyn Store,
 	repo_name: String,
 	commit_sha: String,
 	logstream_id: String,
 	logstream_url: String,
 ) -> Result<(), DbError> {
-	let collection = client.database(DB_NAME).collection(SUBMISSION_COLLECTION);
-
+	let created_at = chrono::Utc::now();
 	let submission = models::Submission {
 		repo_name: repo_name.clone(),
 		commit_sha,
 		logstream_id,
 		logstream_url,
 		relationships: vec![],
-		created_at: chrono::Utc::now(),
+		created_at,
+		status: SubmissionStatus::Queued,
+		stages: vec![],
+		started_at: None,
+		finished_at: None,
+		previous_attempt: false,
 	};
 
 	info!("Inserting submission for repository `{}` into database", repo_name);
 
-	collection
-		.insert_one(submission)
-		.await
-		.map(|_| {
-			info!("Successfully inserted submission for repository `{}` into database", repo_name)
-		})
-		.map_err(DbError::from)
+	store.insert_submission(submission).await?;
+	info!("Successfully inserted submission for repository `{}` into database", repo_name);
+
+	store.set_repository_last_submission_at(&repo_name, created_at).await
 }
 
-/// Update a repository in the database
-pub(super) async fn update_repository(
-	client: &Client,
-	repo_name: &str,
-	update_request: &UpdateRepoRequest,
+/// Record the outcome of a test run reported by a tester. The caller must already have verified the
+/// signature the nonce was taken from; a nonce that has been seen before is rejected as a replay.
+/// If the report names a submission, that submission must belong to the repository and is marked
+/// as finished too. Archived repositories do not accept results.
+pub(super) async fn record_test_result(
+	store: &dyn Store,
+	queue: &dyn SubmissionQueue,
+	nonce: TesterNonce,
+	result: &TesterResultRequest,
 ) -> Result<Repository, DbError> {
-	let collection = client.database(DB_NAME).collection::<Repository>(REPO_COLLECTION);
+	let repo_name = &result.repo_name;
+	let tester_id = nonce.tester_id.clone();
+	check_tester_nonce(store, nonce).await?;
+
+	info!(
+		"Tester `{}` reported test_ok={} for repository `{}` (logstream {:?})",
+		tester_id, result.test_ok, repo_name, result.logstream_id
+	);
+
+	// Fails for archived repositories as well as missing ones
+	get_repo_from_db(store, repo_name).await?;
 
-	let filter = doc! { "repo_name": repo_name };
-	let mut update = doc! {};
+	if let Some(logstream_id) = &result.logstream_id {
+		let submission = store
+			.get_submission(logstream_id)
+			.await?
+			.ok_or_else(|| DbError::NotFound(format!("Submission `{}` not found", logstream_id)))?;
+		if submission.repo_name != *repo_name {
+			warn!(
+				"Tester `{}` reported submission `{}` of repository `{}` for repository `{}`",
+				tester_id, logstream_id, submission.repo_name, repo_name
+			);
+			return Err(DbError::Validation(format!(
+				"Submission `{}` does not belong to repository `{}`",
+				logstream_id, repo_name
+			)));
+		}
 
-	if let Some(expected_practice_frequency) = &update_request.expected_practice_frequency {
-		update.insert(
-			"expected_practice_frequency",
-			bson::to_bson(expected_practice_frequency)
-				.map_err(|e| DbError::DatabaseError(mongodb::error::Error::from(e)))?,
+		let status =
+			if result.test_ok { SubmissionStatus::Passed } else { SubmissionStatus::Failed };
+		let submission = advance_submission(store, logstream_id, status, &[]).await?;
+		complete_job(queue, logstream_id).await;
+		if submission.previous_attempt {
+			info!("Submission `{}` predates a reset, not recording its result", logstream_id);
+			return get_repo_from_db(store, repo_name).await;
+		}
+	}
+
+	set_repository_test_result(store, repo_name, result.test_ok).await
+}
+
+/// Record a progress report for a submission sent by a tester. As with [`record_test_result`], the
+/// caller must already have verified the signature the nonce was taken from. A finished submission
+/// is removed from the queue, and a passed or failed one also records its outcome on the
+/// repository.
+pub(super) async fn record_submission_report(
+	store: &dyn Store,
+	queue: &dyn SubmissionQueue,
+	nonce: TesterNonce,
+	logstream_id: &str,
+	report: &SubmissionReportRequest,
+) -> Result<Submission, DbError> {
+	let tester_id = nonce.tester_id.clone();
+	check_tester_nonce(store, nonce).await?;
+
+	if report.status == SubmissionStatus::Queued {
+		return Err(DbError::Validation(
+			"A submission can only be queued again by the submission queue".to_string(),
+		));
+	}
+
+	info!(
+		"Tester `{}` reported submission `{}` as {} with {} stage result(s)",
+		tester_id,
+		logstream_id,
+		report.status,
+		report.stages.len()
+	);
+
+	let submission = advance_submission(store, logstream_id, report.status, &report.stages).await?;
+	if submission.status.is_finished() {
+		complete_job(queue, logstream_id).await;
+	}
+	if submission.previous_attempt {
+		info!("Submission `{}` predates a reset, not updating repository progress", logstream_id);
+		return Ok(submission);
+	}
+
+	// The submission is already recorded, so a failure here must not make the tester retry it
+	if !report.stages.is_empty() {
+		if let Err(e) = update_stage_progress(store, &submission.repo_name, &report.stages).await {
+			error!(
+				"Failed to update stage progress of repository `{}`: {}",
+				submission.repo_name, e
+			);
+		}
+	}
+
+	match submission.status {
+		SubmissionStatus::Passed | SubmissionStatus::Failed => {
+			let test_ok = submission.status == SubmissionStatus::Passed;
+			set_repository_test_result(store, &submission.repo_name, test_ok).await?;
+		},
+		_ => {},
+	}
+
+	Ok(submission)
+}
+
+/// Remove the job of a finished submission from the queue. The submission is already recorded as
+/// finished, so a failure is only logged; a job left behind is dropped when it is next claimed.
+async fn complete_job(queue: &dyn SubmissionQueue, logstream_id: &str) {
+	if let Err(e) = queue.complete(logstream_id).await {
+		error!("Failed to remove the job of submission `{}` from the queue: {}", logstream_id, e);
+	}
+}
+
+/// Check the worker id a tester worker identifies itself with
+fn validate_worker_id(request: &WorkerRequest) -> Result<&str, DbError> {
+	let worker_id = request.worker_id.as_str();
+	if worker_id.is_empty() || worker_id.len() > MAX_WORKER_ID_LEN {
+		return Err(DbError::Validation(format!(
+			"worker_id must be 1 to {} characters",
+			MAX_WORKER_ID_LEN
+		)));
+	}
+	Ok(worker_id)
+}
+
+/// Check that a commit hash is hexadecimal before it is passed to the git host
+fn validate_commit_sha(commit_sha: &str) -> Result<(), DbError> {
+	if commit_sha.len() < 4 ||
+		commit_sha.len() > MAX_COMMIT_SHA_LEN ||
+		!commit_sha.bytes().all(|byte| byte.is_ascii_hexdigit())
+	{
+		return Err(DbError::Validation(format!(
+			"commit_sha must be 4 to {} hexadecimal characters",
+			MAX_COMMIT_SHA_LEN
+		)));
+	}
+	Ok(())
+}
+
+/// Hand the next queued submission to a tester worker and mark it as running. As with
+/// [`record_test_result`], the caller must already have verified the signature the nonce was taken
+/// from. Jobs whose submission has since finished or been purged are dropped along the way.
+pub(super) async fn claim_submission_job(
+	store: &dyn Store,
+	queue: &dyn SubmissionQueue,
+	nonce: TesterNonce,
+	request: &WorkerRequest,
+	visibility_timeout: std::time::Duration,
+) -> Result<Option<ClaimedJobResponse>, DbError> {
+	let worker_id = validate_worker_id(request)?;
+	check_tester_nonce(store, nonce).await?;
+
+	while let Some(claimed) = queue.claim(worker_id).await? {
+		let job = claimed.job;
+		let logstream_id = &job.logstream_id;
+		match advance_submission(store, logstream_id, SubmissionStatus::Running, &[]).await {
+			Ok(_) => {},
+			// The tester may already have reported the submission, so look at where it is now
+			Err(DbError::Conflict(_) | DbError::NotFound(_)) =>
+				match store.get_submission(logstream_id).await? {
+					Some(submission) if !submission.status.is_finished() => {},
+					_ => {
+						info!("Dropping job of finished or deleted submission `{}`", logstream_id);
+						queue.complete(logstream_id).await?;
+						continue;
+					},
+				},
+			Err(e) => return Err(e),
+		}
+
+		info!(
+			"Worker `{}` claimed submission `{}` (delivery {})",
+			worker_id, logstream_id, claimed.delivery
 		);
+		return Ok(Some(ClaimedJobResponse {
+			logstream_id: job.logstream_id,
+			repo_name: job.repo_name,
+			commit_sha: job.commit_sha,
+			logstream_url: job.logstream_url,
+			tester_url: job.tester_url,
+			tester_version: job.tester_version,
+			delivery: claimed.delivery,
+			visibility_timeout_secs: visibility_timeout.as_secs(),
+		}));
 	}
 
-	if let Some(is_reminder_enabled) = update_request.is_reminder_enabled {
-		update.insert("is_reminder_enabled", is_reminder_enabled);
+	Ok(None)
+}
+
+/// Extend a tester worker's claim on the job of a submission. Fails with a conflict if the worker
+/// no longer holds the job, in which case it should stop running the submission.
+pub(super) async fn heartbeat_submission_job(
+	store: &dyn Store,
+	queue: &dyn SubmissionQueue,
+	nonce: TesterNonce,
+	logstream_id: &str,
+	request: &WorkerRequest,
+) -> Result<(), DbError> {
+	let worker_id = validate_worker_id(request)?;
+	check_tester_nonce(store, nonce).await?;
+
+	if !queue.heartbeat(worker_id, logstream_id).await? {
+		return Err(DbError::Conflict(format!(
+			"Worker `{}` does not hold the job of submission `{}`",
+			worker_id, logstream_id
+		)));
 	}
+	Ok(())
+}
 
-	if let Some(test_ok) = update_request.test_ok {
-		update.insert("test_ok", test_ok);
+/// Fold stage results reported for a submission into the progress of its repository. Results are
+/// matched to the stages of the course version the repository is on by tester stage id or slug;
+/// results for unknown stages are ignored.
+async fn update_stage_progress(
+	store: &dyn Store,
+	repo_name: &str,
+	results: &[StageResult],
+) -> Result<(), DbError> {
+	let repository = get_repo_from_db(store, repo_name).await?;
+	let Some(course) = repository_course(store, &repository).await? else {
+		warn!("Not tracking stage progress of repository `{}`", repo_name);
+		return Ok(());
+	};
+
+	let now = chrono::Utc::now();
+	let mut progress = repository.progress;
+	for result in results {
+		let Some(stage) = course
+			.stages
+			.iter()
+			.find(|stage| stage.tester_stage_id == result.stage || stage.slug == result.stage)
+		else {
+			warn!(
+				"Ignoring result for unknown stage `{}` of course `{}`",
+				result.stage, course.slug
+			);
+			continue;
+		};
+
+		let index = match progress.stages.iter().position(|seen| seen.stage == stage.slug) {
+			Some(index) => index,
+			None => {
+				progress.stages.push(StageProgress {
+					stage: stage.slug.clone(),
+					first_attempted_at: now,
+					completed_at: None,
+				});
+				progress.stages.len() - 1
+			},
+		};
+		if result.passed {
+			progress.stages[index].completed_at.get_or_insert(now);
+		}
+	}
+
+	let position = |slug: &str| course.stages.iter().position(|stage| stage.slug == slug);
+	progress.stages.sort_by_key(|seen| position(&seen.stage));
+	progress.current_stage = course
+		.stages
+		.iter()
+		.find(|stage| {
+			!progress
+				.stages
+				.iter()
+				.any(|seen| seen.stage == stage.slug && seen.completed_at.is_some())
+		})
+		.map(|stage| stage.slug.clone());
+
+	if !store.set_repository_progress(repo_name, &progress).await? {
+		return Err(DbError::NotFound(format!("Repository `{}` not found", repo_name)));
+	}
+
+	info!("Repository `{}` is now at stage {:?}", repo_name, progress.current_stage);
+	Ok(())
+}
+
+/// Reject a tester nonce that has been seen before
+async fn check_tester_nonce(store: &dyn Store, nonce: TesterNonce) -> Result<(), DbError> {
+	let tester_id = nonce.tester_id.clone();
+	if !store.record_tester_nonce(nonce).await? {
+		warn!("Rejected replayed callback from tester `{}`", tester_id);
+		return Err(AuthError::Replayed.into());
 	}
+	Ok(())
+}
+
+/// Move a submission to `status`, merging in `stages` by stage. The start time is stamped when it
+/// first runs, and the finish time when it finishes. Fails with a conflict if the submission has
+/// already finished or is changed concurrently.
+pub(super) async fn advance_submission(
+	store: &dyn Store,
+	logstream_id: &str,
+	status: SubmissionStatus,
+	stages: &[StageResult],
+) -> Result<Submission, DbError> {
+	let mut submission = store
+		.get_submission(logstream_id)
+		.await?
+		.ok_or_else(|| DbError::NotFound(format!("Submission `{}` not found", logstream_id)))?;
 
-	if let Some(relationships) = &update_request.relationships {
-		update.insert(
-			"relationships",
-			bson::to_bson(relationships)
-				.map_err(|e| DbError::DatabaseError(mongodb::error::Error::from(e)))?,
+	let previous = submission.status;
+	if !previous.can_transition_to(status) {
+		warn!(
+			"Rejected transition of submission `{}` from {} to {}",
+			logstream_id, previous, status
 		);
+		return Err(DbError::Conflict(format!(
+			"Submission `{}` cannot move from {} to {}",
+			logstream_id, previous, status
+		)));
 	}
 
-	let update_doc = doc! { "$set": update };
+	let now = chrono::Utc::now();
+	submission.status = status;
+	if status == SubmissionStatus::Running {
+		submission.started_at.get_or_insert(now);
+	}
+	if status.is_finished() {
+		submission.finished_at = Some(now);
+	}
+	for stage in stages {
+		match submission.stages.iter_mut().find(|existing| existing.stage == stage.stage) {
+			Some(existing) => *existing = stage.clone(),
+			None => submission.stages.push(stage.clone()),
+		}
+	}
+
+	if !store.update_submission_progress(&submission, previous).await? {
+		return Err(DbError::Conflict(format!(
+			"Submission `{}` was updated concurrently",
+			logstream_id
+		)));
+	}
+
+	info!("Submission `{}` moved from {} to {}", logstream_id, previous, status);
+	Ok(submission)
+}
+
+/// Record whether the latest test run of a repository passed
+async fn set_repository_test_result(
+	store: &dyn Store,
+	repo_name: &str,
+	test_ok: bool,
+) -> Result<Repository, DbError> {
+	match store.record_test_result(repo_name, test_ok).await? {
+		Some(repository) => Ok(repository),
+		None => {
+			error!("Repository `{}` not found in database", repo_name);
+			Err(DbError::NotFound(format!("Repository `{}` not found", repo_name)))
+		},
+	}
+}
+
+/// Fetch a submission, failing unless the caller owns its repository or is an admin
+pub(super) async fn get_authorized_submission(
+	store: &dyn Store,
+	identity: &Identity,
+	logstream_id: &str,
+) -> Result<Submission, DbError> {
+	let submission = store
+		.get_submission(logstream_id)
+		.await?
+		.ok_or_else(|| DbError::NotFound(format!("Submission `{}` not found", logstream_id)))?;
+
+	get_authorized_repo(store, identity, &submission.repo_name).await?;
+	Ok(submission)
+}
+
+/// List a page of the submissions of a repository, newest first
+pub(super) async fn list_repository_submissions(
+	store: &dyn Store,
+	identity: &Identity,
+	repo_name: &str,
+	pagination: &PaginationQuery,
+) -> Result<SubmissionPageResponse, DbError> {
+	get_authorized_repo(store, identity, repo_name).await?;
+
+	let per_page = pagination.per_page();
+	// Fetch one extra submission to find out whether there is another page
+	let mut submissions =
+		store.list_submissions(repo_name, pagination.offset(), per_page + 1).await?;
+	let has_more = submissions.len() as u64 > per_page;
+	submissions.truncate(per_page as usize);
+
+	Ok(SubmissionPageResponse { submissions, page: pagination.page(), per_page, has_more })
+}
+
+/// Compute practice statistics for a repository from its submissions
+pub(super) async fn get_repository_stats(
+	store: &dyn Store,
+	identity: &Identity,
+	repo_name: &str,
+) -> Result<RepositoryStatsResponse, DbError> {
+	let repository = get_authorized_repo(store, identity, repo_name).await?;
+	let submission_times = store.list_submission_times(repo_name).await?;
+
+	Ok(stats::compute_stats(&repository, &submission_times, chrono::Utc::now()))
+}
+
+/// Update a repository in the database. Only admins may change the repository's relationships.
+pub(super) async fn update_repository(
+	store: &dyn Store,
+	identity: &Identity,
+	repo_name: &str,
+	update_request: &UpdateRepoRequest,
+) -> Result<Repository, DbError> {
+	get_authorized_repo(store, identity, repo_name).await?;
+	if update_request.is_empty() {
+		return Err(DbError::Validation("The update does not change any field".to_string()));
+	}
+	if update_request.relationships.is_some() {
+		identity.require_admin()?;
+	}
 
 	info!("Updating repository `{}` in database", repo_name);
 
-	let result = collection.find_one_and_update(filter, update_doc).await?;
+	let result = store.update_repository(repo_name, update_request).await?;
 
 	match result {
 		Some(updated_repo) => {
@@ -366,10 +1083,370 @@ pub(super) async fn update_repository(
 		},
 		None => {
 			error!("Repository `{}` not found in database", repo_name);
-			Err(DbError::NotFound(actix_web::error::ErrorNotFound(format!(
-				"Repository `{}` not found",
-				repo_name
-			))))
+			Err(DbError::NotFound(format!("Repository `{}` not found", repo_name)))
 		},
 	}
 }
+
+/// Parse a user id and check that the caller is that user or an admin
+fn authorize_user_id(identity: &Identity, id: &str) -> Result<ObjectId, DbError> {
+	let user_id = ObjectId::parse_str(id)?;
+	identity.authorize_user(&user_id)?;
+	Ok(user_id)
+}
+
+/// Fetch a user's profile
+pub(super) async fn get_user(
+	store: &dyn Store,
+	identity: &Identity,
+	id: &str,
+	include: &IncludeQuery,
+) -> Result<IncludedResponse<UserResponse>, DbError> {
+	let user_id = authorize_user_id(identity, id)?;
+
+	let Some(user) = store.get_user(&user_id).await? else {
+		return Err(DbError::NotFound(format!("User `{}` not found", user_id)));
+	};
+
+	let relationships = user.repositories.iter().chain(&user.relationships);
+	let included = include_related(store, Some(identity), relationships, include).await?;
+	Ok(IncludedResponse {
+		data: UserResponse { id: user_id, name: user.name, email: user.email },
+		included,
+	})
+}
+
+/// Update a user's profile fields
+pub(super) async fn update_user(
+	store: &dyn Store,
+	identity: &Identity,
+	id: &str,
+	update_request: &UpdateUserRequest,
+) -> Result<UserResponse, DbError> {
+	let user_id = authorize_user_id(identity, id)?;
+
+	if update_request.name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+		return Err(DbError::Validation("Name must not be empty".to_string()));
+	}
+	if let Some(email) = &update_request.email {
+		email
+			.parse::<lettre::Address>()
+			.map_err(|e| DbError::Validation(format!("Invalid email address: {}", e)))?;
+	}
+
+	info!("Updating user `{}` in database", user_id);
+
+	match store.update_user(&user_id, update_request).await? {
+		Some(user) => Ok(UserResponse { id: user_id, name: user.name, email: user.email }),
+		None => Err(DbError::NotFound(format!("User `{}` not found", user_id))),
+	}
+}
+
+/// List a user's repositories along with the version of its course each is on
+pub(super) async fn list_user_repositories(
+	store: &dyn Store,
+	identity: &Identity,
+	id: &str,
+) -> Result<Vec<UserRepositoryResponse>, DbError> {
+	let user_id = authorize_user_id(identity, id)?;
+	if store.get_user(&user_id).await?.is_none() {
+		return Err(DbError::NotFound(format!("User `{}` not found", user_id)));
+	}
+
+	let repositories = store.list_user_repositories(&user_id).await?;
+
+	// Repositories of the same course are usually on the same version of it
+	let mut courses: HashMap<(Option<ObjectId>, Option<String>), Option<Course>> = HashMap::new();
+	let mut response = Vec::with_capacity(repositories.len());
+	for repository in repositories {
+		let key = (
+			repository.relationships.course.as_ref().map(|course| course.id),
+			repository.course_version.clone(),
+		);
+		let course = match courses.get(&key) {
+			Some(course) => course.clone(),
+			None => {
+				let course = repository_course(store, &repository).await?;
+				courses.insert(key, course.clone());
+				course
+			},
+		};
+
+		response.push(UserRepositoryResponse {
+			repo_name: repository.repo_name,
+			repo_template: repository.repo_template,
+			course: course.map(|course| CourseSummary {
+				id: course.id,
+				slug: course.slug,
+				title: course.title,
+				stage_count: course.stages.len(),
+			}),
+			progress: repository.progress,
+			test_ok: repository.test_ok,
+			expected_practice_frequency: repository.expected_practice_frequency,
+			is_reminder_enabled: repository.is_reminder_enabled,
+			created_at: repository.created_at,
+			last_submission_at: repository.last_submission_at,
+		});
+	}
+
+	Ok(response)
+}
+
+/// Check the fields of a course an admin is creating or updating
+pub(super) fn validate_course(course: &Course) -> Result<(), DbError> {
+	let is_slug = |slug: &str| {
+		!slug.is_empty() &&
+			slug.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+	};
+
+	if !is_slug(&course.slug) {
+		return Err(DbError::Validation(format!(
+			"Invalid course slug `{}`: use lowercase letters, digits and dashes",
+			course.slug
+		)));
+	}
+	if course.name.trim().is_empty() || course.title.trim().is_empty() {
+		return Err(DbError::Validation("Course name and title must not be empty".to_string()));
+	}
+
+	let mut stage_slugs = std::collections::HashSet::new();
+	for stage in &course.stages {
+		if !is_slug(&stage.slug) {
+			return Err(DbError::Validation(format!("Invalid stage slug `{}`", stage.slug)));
+		}
+		if !stage_slugs.insert(&stage.slug) {
+			return Err(DbError::Validation(format!("Duplicate stage slug `{}`", stage.slug)));
+		}
+	}
+
+	Ok(())
+}
+
+/// Fetch a course for an admin to act on, including drafts
+async fn get_course_for_admin(
+	store: &dyn Store,
+	identity: &Identity,
+	id: &str,
+) -> Result<Course, DbError> {
+	identity.require_admin()?;
+	let id = ObjectId::parse_str(id)?;
+
+	store
+		.get_course(&id)
+		.await?
+		.ok_or_else(|| DbError::NotFound(format!("Course with id {} not found", id)))
+}
+
+/// Create a course as a draft at version 1. Admin only.
+pub(super) async fn create_course(
+	store: &dyn Store,
+	identity: &Identity,
+	request: &CreateCourseRequest,
+) -> Result<Course, DbError> {
+	identity.require_admin()?;
+
+	let course = Course {
+		version: "1".to_string(),
+		id: ObjectId::new(),
+		slug: request.slug.clone(),
+		name: request.name.clone(),
+		title: request.title.clone(),
+		author: request.author.clone(),
+		tester_url: request.tester_url.clone(),
+		tester_version: request.tester_version.clone(),
+		language: request.language.clone(),
+		difficulty: request.difficulty,
+		tags: request.tags.clone(),
+		stages: request.stages.clone(),
+		status: CourseStatus::Draft,
+		relationships: vec![],
+	};
+	validate_course(&course)?;
+
+	store.create_course(course.clone()).await?;
+	info!("User `{}` created course `{}`", identity.user_id, course.slug);
+
+	Ok(course)
+}
+
+/// Update a course, archiving the current version first. Admin only. Repositories keep the version
+/// they were created against.
+pub(super) async fn update_course(
+	store: &dyn Store,
+	identity: &Identity,
+	id: &str,
+	request: &UpdateCourseRequest,
+) -> Result<Course, DbError> {
+	let current = get_course_for_admin(store, identity, id).await?;
+
+	let mut course = current.clone();
+	if let Some(name) = &request.name {
+		course.name = name.clone();
+	}
+	if let Some(title) = &request.title {
+		course.title = title.clone();
+	}
+	if let Some(author) = &request.author {
+		course.author = author.clone();
+	}
+	if let Some(tester_url) = &request.tester_url {
+		course.tester_url = tester_url.clone();
+	}
+	if let Some(tester_version) = &request.tester_version {
+		course.tester_version = Some(tester_version.clone());
+	}
+	if let Some(language) = &request.language {
+		course.language = Some(language.clone());
+	}
+	if let Some(difficulty) = request.difficulty {
+		course.difficulty = Some(difficulty);
+	}
+	if let Some(tags) = &request.tags {
+		course.tags = tags.clone();
+	}
+	if let Some(stages) = &request.stages {
+		course.stages = stages.clone();
+	}
+	validate_course(&course)?;
+
+	let course = save_course_version(store, &current, course).await?;
+	info!(
+		"User `{}` updated course `{}` from version {} to {}",
+		identity.user_id, course.slug, current.version, course.version
+	);
+	Ok(course)
+}
+
+/// Archive `current` and replace it with `course` under the next version number.
+///
+/// Versions are numbered from 1; a course whose version is not a number, such as one created by
+/// hand, continues from 1. Fails with a conflict if the course changed since `current` was read.
+pub(super) async fn save_course_version(
+	store: &dyn Store,
+	current: &Course,
+	mut course: Course,
+) -> Result<Course, DbError> {
+	course.id = current.id;
+	course.version = (current.version.parse::<u64>().unwrap_or(0) + 1).to_string();
+
+	let previous = CourseVersion {
+		course_id: current.id,
+		version: current.version.clone(),
+		course: current.clone(),
+		archived_at: chrono::Utc::now(),
+	};
+	if !store.replace_course(&course, previous).await? {
+		return Err(DbError::Conflict(format!(
+			"Course `{}` was updated concurrently, retry against the latest version",
+			current.slug
+		)));
+	}
+
+	Ok(course)
+}
+
+/// Publish or unpublish a course. Admin only.
+pub(super) async fn set_course_status(
+	store: &dyn Store,
+	identity: &Identity,
+	id: &str,
+	status: CourseStatus,
+) -> Result<Course, DbError> {
+	let course = get_course_for_admin(store, identity, id).await?;
+
+	let course = store
+		.set_course_status(&course.id, status)
+		.await?
+		.ok_or_else(|| DbError::NotFound(format!("Course with id {} not found", course.id)))?;
+
+	info!("User `{}` set course `{}` to {}", identity.user_id, course.slug, status);
+	Ok(course)
+}
+
+/// Move a repository onto the current version of its course, so its submissions run the tester
+/// pinned by that version. Admin only.
+pub(super) async fn migrate_repository_tester(
+	store: &dyn Store,
+	identity: &Identity,
+	repo_name: &str,
+) -> Result<Repository, DbError> {
+	identity.require_admin()?;
+
+	let repository = get_repo_from_db(store, repo_name).await?;
+	let course_id =
+		repository
+			.relationships
+			.course
+			.as_ref()
+			.map(|course| course.id)
+			.ok_or_else(|| {
+				DbError::Validation(format!(
+					"Repository `{}` has no course to migrate to",
+					repo_name
+				))
+			})?;
+	let course = store
+		.get_course(&course_id)
+		.await?
+		.ok_or_else(|| DbError::NotFound(format!("Course with id {} not found", course_id)))?;
+
+	let repository = store
+		.set_repository_course_version(repo_name, &course)
+		.await?
+		.ok_or_else(|| DbError::NotFound(format!("Repository `{}` not found", repo_name)))?;
+
+	info!(
+		"User `{}` migrated repository `{}` to version {} of course `{}` with tester {}{}",
+		identity.user_id,
+		repo_name,
+		course.version,
+		course.slug,
+		course.tester_url,
+		course
+			.tester_version
+			.as_deref()
+			.map(|version| format!("@{}", version))
+			.unwrap_or_default()
+	);
+	Ok(repository)
+}
+
+/// Check the references between repositories, users and courses, and the repositories on the git
+/// host, repairing references if `repair` is set. Admin only.
+pub(super) async fn check_relationship_integrity(
+	store: &dyn Store,
+	git_host: &dyn GitHost,
+	identity: &Identity,
+	repair: bool,
+) -> Result<IntegrityReport, DbError> {
+	identity.require_admin()?;
+
+	let report = integrity::check_integrity(store, git_host, repair).await?;
+	info!(
+		"User `{}` ran an integrity check{}: {} issues",
+		identity.user_id,
+		if repair { " with repairs" } else { "" },
+		report.issues.len()
+	);
+	Ok(report)
+}
+
+/// Compare the indexes in the database with those the backend declares. Admin only.
+pub(super) async fn get_index_report(
+	store: &dyn Store,
+	identity: &Identity,
+) -> Result<IndexReport, DbError> {
+	identity.require_admin()?;
+	store.index_report().await
+}
+
+/// List the archived versions of a course, oldest first. Admin only.
+pub(super) async fn list_course_versions(
+	store: &dyn Store,
+	identity: &Identity,
+	id: &str,
+) -> Result<Vec<CourseVersion>, DbError> {
+	let course = get_course_for_admin(store, identity, id).await?;
+	store.list_course_versions(&course.id).await
+}